@@ -0,0 +1,11 @@
+-- Track when tasks are completed and archived, plus the per-user auto-archive setting
+ALTER TABLE tasks
+    ADD COLUMN completed_at TIMESTAMPTZ,
+    ADD COLUMN archived_at TIMESTAMPTZ;
+
+UPDATE tasks SET completed_at = created_at WHERE done;
+
+ALTER TABLE users
+    ADD COLUMN auto_archive_after_days INTEGER CHECK (auto_archive_after_days > 0);
+
+CREATE INDEX idx_tasks_user_archived ON tasks (user_id, archived_at);
@@ -0,0 +1,37 @@
+-- Workspaces own tasks; users gain access to tasks through workspace membership
+CREATE TABLE workspaces (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    name TEXT NOT NULL,
+    created_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    is_personal BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE UNIQUE INDEX idx_workspaces_personal ON workspaces (created_by) WHERE is_personal;
+
+CREATE TABLE workspace_members (
+    workspace_id UUID NOT NULL REFERENCES workspaces(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    joined_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (workspace_id, user_id)
+);
+
+CREATE INDEX idx_workspace_members_user ON workspace_members (user_id);
+
+-- Every existing user gets a personal workspace holding their tasks
+INSERT INTO workspaces (name, created_by, is_personal)
+SELECT 'Personal', id, TRUE FROM users;
+
+INSERT INTO workspace_members (workspace_id, user_id)
+SELECT id, created_by FROM workspaces WHERE is_personal;
+
+-- tasks.user_id now records the task's creator
+ALTER TABLE tasks ADD COLUMN workspace_id UUID REFERENCES workspaces(id) ON DELETE CASCADE;
+
+UPDATE tasks t SET workspace_id = w.id
+FROM workspaces w
+WHERE w.is_personal AND w.created_by = t.user_id;
+
+ALTER TABLE tasks ALTER COLUMN workspace_id SET NOT NULL;
+
+CREATE INDEX idx_tasks_workspace ON tasks (workspace_id);
@@ -0,0 +1,9 @@
+-- Members get a role that determines what they may do inside a workspace
+CREATE TYPE workspace_role AS ENUM ('owner', 'admin', 'editor', 'commenter', 'viewer');
+
+ALTER TABLE workspace_members
+    ADD COLUMN role workspace_role NOT NULL DEFAULT 'editor';
+
+UPDATE workspace_members m SET role = 'owner'
+FROM workspaces w
+WHERE w.id = m.workspace_id AND w.created_by = m.user_id;
@@ -0,0 +1,13 @@
+-- Individually revoked access tokens (by jti), kept until the token would have expired
+CREATE TABLE revoked_tokens (
+    jti UUID PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    expires_at TIMESTAMPTZ NOT NULL,
+    revoked_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_revoked_tokens_expires ON revoked_tokens (expires_at);
+
+-- Access tokens carry the version current when they were issued; bumping it
+-- revokes every outstanding token for the user at once
+ALTER TABLE users ADD COLUMN token_version INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,13 @@
+ALTER TABLE users ADD COLUMN email_verified_at TIMESTAMPTZ;
+-- When the last verification email went out, to throttle resends
+ALTER TABLE users ADD COLUMN verification_sent_at TIMESTAMPTZ;
+
+-- Emails are now stored with a lowercase domain. Addresses that would collide
+-- with an existing account are left as they are.
+UPDATE users u
+SET email = split_part(u.email, '@', 1) || '@' || lower(split_part(u.email, '@', 2))
+WHERE u.email <> split_part(u.email, '@', 1) || '@' || lower(split_part(u.email, '@', 2))
+  AND NOT EXISTS (
+    SELECT 1 FROM users o
+    WHERE o.email = split_part(u.email, '@', 1) || '@' || lower(split_part(u.email, '@', 2))
+  );
@@ -0,0 +1,13 @@
+-- Security-relevant changes to accounts (password and email changes, ...)
+CREATE TABLE audit_events (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    action TEXT NOT NULL,
+    details JSONB NOT NULL DEFAULT '{}',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_audit_events_user ON audit_events (user_id, created_at);
+
+-- New address waiting for confirmation; only the latest request can be confirmed
+ALTER TABLE users ADD COLUMN pending_email TEXT;
@@ -0,0 +1,5 @@
+-- Accounts scheduled for deletion are purged once this time has passed
+ALTER TABLE users ADD COLUMN deletion_scheduled_for TIMESTAMPTZ;
+
+CREATE INDEX idx_users_deletion_scheduled_for ON users (deletion_scheduled_for)
+    WHERE deletion_scheduled_for IS NOT NULL;
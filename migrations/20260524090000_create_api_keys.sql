@@ -0,0 +1,17 @@
+CREATE TYPE api_scope AS ENUM ('tasks:read', 'tasks:write', 'workspaces:read', 'workspaces:write');
+
+-- Personal access tokens for scripts. Only a hash of the token is stored; the
+-- prefix identifies the key in listings.
+CREATE TABLE api_keys (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    prefix TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    scopes api_scope[] NOT NULL,
+    expires_at TIMESTAMPTZ,
+    last_used_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_api_keys_user ON api_keys (user_id);
@@ -0,0 +1,7 @@
+-- Failed login attempts, counted per account ("account:<email>") and per
+-- client IP ("ip:<address>") for brute-force lockout.
+CREATE TABLE login_attempts (
+    key TEXT PRIMARY KEY,
+    failures INTEGER NOT NULL,
+    last_failure_at TIMESTAMPTZ NOT NULL
+);
@@ -0,0 +1,2 @@
+-- Accounts disabled by an operator cannot sign in or use their tokens and API keys
+ALTER TABLE users ADD COLUMN disabled_at TIMESTAMPTZ;
@@ -0,0 +1,15 @@
+-- Tasks without a creator are credited to an owner of their workspace
+UPDATE tasks t SET user_id = (
+    SELECT m.user_id FROM workspace_members m
+    WHERE m.workspace_id = t.workspace_id
+    ORDER BY m.role, m.joined_at
+    LIMIT 1
+)
+WHERE t.user_id IS NULL;
+
+ALTER TABLE tasks
+    DROP CONSTRAINT tasks_user_id_fkey,
+    ADD CONSTRAINT tasks_user_id_fkey
+        FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE;
+
+ALTER TABLE tasks ALTER COLUMN user_id SET NOT NULL;
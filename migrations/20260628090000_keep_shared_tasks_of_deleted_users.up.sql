@@ -0,0 +1,8 @@
+-- Deleting a user keeps the tasks they created; only the creator is cleared.
+-- Their personal workspace, and its tasks, are still deleted with the account.
+ALTER TABLE tasks ALTER COLUMN user_id DROP NOT NULL;
+
+ALTER TABLE tasks
+    DROP CONSTRAINT tasks_user_id_fkey,
+    ADD CONSTRAINT tasks_user_id_fkey
+        FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE SET NULL;
@@ -0,0 +1,10 @@
+-- Challenges of two-factor logins in progress. A challenge is deleted when it
+-- is used, and refused after a few attempts, so codes cannot be guessed.
+CREATE TABLE two_factor_challenges (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX idx_two_factor_challenges_user ON two_factor_challenges (user_id);
@@ -0,0 +1,146 @@
+//! Operations behind the binary's admin commands, see [`crate::cli`]. They
+//! work on the same [`AppState`] as the server, so they follow its
+//! configuration, e.g. the password policy.
+
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+use crate::{
+    audit, errors::AppError, handlers::auth, models::user::User, revocation, state::AppState,
+};
+
+async fn find_user(conn: &mut PgConnection, email: &str) -> Result<Uuid, AppError> {
+    auth::find_user_by_email(conn, email)
+        .await?
+        .map(|user| user.id)
+        .ok_or_else(|| AppError::NotFound(format!("No account for {}", email)))
+}
+
+/// Creates an account with its personal workspace, like registering but
+/// without sending a verification email. `verified` marks the address as
+/// verified up front.
+pub async fn create_user(
+    state: &AppState,
+    email: &str,
+    password: &str,
+    verified: bool,
+) -> Result<User, AppError> {
+    state.password_policy.check("password", password)?;
+
+    let mut tx = state.pool.begin().await?;
+    let mut user = auth::create_user(&mut tx, &state.password_hasher, email, password).await?;
+    if verified {
+        user = sqlx::query_as::<_, User>(
+            "UPDATE users SET email_verified_at = NOW() WHERE id = $1 RETURNING *",
+        )
+        .bind(user.id)
+        .fetch_one(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+
+    Ok(user)
+}
+
+/// Sets a new password, signs out every session and lifts a login lockout,
+/// like a password reset by email.
+pub async fn reset_password(state: &AppState, email: &str, password: &str) -> Result<(), AppError> {
+    state.password_policy.check("password", password)?;
+
+    let mut tx = state.pool.begin().await?;
+    let user_id = find_user(&mut tx, email).await?;
+    let email = sqlx::query_scalar::<_, String>(
+        "UPDATE users SET password_hash = $1 WHERE id = $2 RETURNING email",
+    )
+    .bind(state.password_hasher.hash(password).await?)
+    .bind(user_id)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    revocation::revoke_all_tokens(&mut tx, user_id).await?;
+    audit::record(
+        &mut tx,
+        user_id,
+        "password_reset_by_admin",
+        serde_json::json!({}),
+    )
+    .await?;
+    tx.commit().await?;
+    state.revocations.invalidate(user_id);
+    state.login_throttle.unlock(&email).await?;
+
+    Ok(())
+}
+
+/// Disables an account or enables it again. Disabling signs out every
+/// session; until the account is enabled, it cannot sign in and its API keys
+/// are rejected.
+pub async fn set_disabled(state: &AppState, email: &str, disabled: bool) -> Result<(), AppError> {
+    let mut tx = state.pool.begin().await?;
+    let user_id = find_user(&mut tx, email).await?;
+
+    sqlx::query(
+        "UPDATE users
+         SET disabled_at = CASE WHEN $2 THEN COALESCE(disabled_at, NOW()) END
+         WHERE id = $1",
+    )
+    .bind(user_id)
+    .bind(disabled)
+    .execute(&mut *tx)
+    .await?;
+
+    if disabled {
+        revocation::revoke_all_tokens(&mut tx, user_id).await?;
+    }
+    let action = if disabled {
+        "account_disabled"
+    } else {
+        "account_enabled"
+    };
+    audit::record(&mut tx, user_id, action, serde_json::json!({})).await?;
+    tx.commit().await?;
+    state.revocations.invalidate(user_id);
+
+    Ok(())
+}
+
+/// Counts the tasks [`purge_archived_tasks`] would delete.
+pub async fn count_archived_tasks(pool: &PgPool, older_than_days: i32) -> Result<u64, AppError> {
+    check_age(older_than_days)?;
+
+    let count: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM tasks
+         WHERE archived_at IS NOT NULL
+           AND archived_at <= NOW() - make_interval(days => $1)",
+    )
+    .bind(older_than_days)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(count as u64)
+}
+
+/// Permanently deletes tasks that have been archived for at least
+/// `older_than_days`. Archived tasks can still be restored by their owners,
+/// so this cannot be undone. Returns the number of tasks deleted.
+pub async fn purge_archived_tasks(pool: &PgPool, older_than_days: i32) -> Result<u64, AppError> {
+    check_age(older_than_days)?;
+
+    let result = sqlx::query(
+        "DELETE FROM tasks
+         WHERE archived_at IS NOT NULL
+           AND archived_at <= NOW() - make_interval(days => $1)",
+    )
+    .bind(older_than_days)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+fn check_age(older_than_days: i32) -> Result<(), AppError> {
+    if older_than_days < 0 {
+        return Err(AppError::Validation("The age must not be negative".into()));
+    }
+    Ok(())
+}
@@ -0,0 +1,22 @@
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::errors::AppError;
+
+/// Records a security-relevant event on a user's account, e.g.
+/// `password_changed`. `details` holds action-specific data.
+pub async fn record(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    action: &str,
+    details: serde_json::Value,
+) -> Result<(), AppError> {
+    sqlx::query("INSERT INTO audit_events (user_id, action, details) VALUES ($1, $2, $3)")
+        .bind(user_id)
+        .bind(action)
+        .bind(details)
+        .execute(conn)
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,159 @@
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{config::Config, errors::AppError, models::workspace::Role};
+
+/// Actions that can be performed inside a workspace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    /// List the workspace's tasks and members.
+    View,
+    /// Create, update, archive and delete tasks.
+    EditTasks,
+    /// Add and remove members and change their roles.
+    ManageMembers,
+    /// Rename the workspace.
+    ManageWorkspace,
+    DeleteWorkspace,
+}
+
+impl Permission {
+    /// The least privileged role that is granted this permission.
+    pub fn min_role(self) -> Role {
+        match self {
+            Permission::View => Role::Viewer,
+            Permission::EditTasks => Role::Editor,
+            Permission::ManageMembers | Permission::ManageWorkspace => Role::Admin,
+            Permission::DeleteWorkspace => Role::Owner,
+        }
+    }
+}
+
+impl Role {
+    pub fn can(self, permission: Permission) -> bool {
+        self >= permission.min_role()
+    }
+}
+
+/// Fails with `Forbidden` unless `role` grants `permission`.
+pub fn check(role: Role, permission: Permission) -> Result<Role, AppError> {
+    if !role.can(permission) {
+        return Err(AppError::Forbidden(
+            "Insufficient permissions in this workspace".into(),
+        ));
+    }
+
+    Ok(role)
+}
+
+pub async fn member_role(
+    pool: &PgPool,
+    workspace_id: Uuid,
+    user_id: Uuid,
+) -> Result<Option<Role>, AppError> {
+    let role = sqlx::query_scalar::<_, Role>(
+        "SELECT role FROM workspace_members WHERE workspace_id = $1 AND user_id = $2",
+    )
+    .bind(workspace_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(role)
+}
+
+/// Checks that `user_id` holds `permission` in the workspace and returns their role.
+///
+/// Non-members get `NotFound` so they cannot probe which workspaces exist;
+/// members lacking the permission get `Forbidden`.
+pub async fn authorize(
+    pool: &PgPool,
+    user_id: Uuid,
+    workspace_id: Uuid,
+    permission: Permission,
+) -> Result<Role, AppError> {
+    let role = member_role(pool, workspace_id, user_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Workspace not found".into()))?;
+
+    check(role, permission)
+}
+
+/// Like [`authorize`], but for the workspace that owns `task_id`. Returns the
+/// task's workspace id.
+pub async fn authorize_task(
+    pool: &PgPool,
+    user_id: Uuid,
+    task_id: Uuid,
+    permission: Permission,
+) -> Result<Uuid, AppError> {
+    let (workspace_id, role) = sqlx::query_as::<_, (Uuid, Role)>(
+        "SELECT t.workspace_id, m.role FROM tasks t
+         JOIN workspace_members m ON m.workspace_id = t.workspace_id AND m.user_id = $2
+         WHERE t.id = $1",
+    )
+    .bind(task_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound("Task not found".into()))?;
+
+    check(role, permission)?;
+    Ok(workspace_id)
+}
+
+/// Account-level actions that can be restricted to users who have verified
+/// their email address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifiedAction {
+    /// Create shared workspaces.
+    CreateWorkspace,
+    /// Invite or add people to a workspace.
+    InviteMembers,
+}
+
+impl VerifiedAction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            VerifiedAction::CreateWorkspace => "create_workspace",
+            VerifiedAction::InviteMembers => "invite_members",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [
+            VerifiedAction::CreateWorkspace,
+            VerifiedAction::InviteMembers,
+        ]
+        .into_iter()
+        .find(|action| action.as_str() == name)
+    }
+}
+
+/// Fails with `Forbidden` if `action` is restricted to verified accounts and
+/// the user has not verified their email address yet.
+pub async fn require_verified_email(
+    pool: &PgPool,
+    config: &Config,
+    user_id: Uuid,
+    action: VerifiedAction,
+) -> Result<(), AppError> {
+    if !config.requires_verified_email(action) {
+        return Ok(());
+    }
+
+    let verified = sqlx::query_scalar::<_, bool>(
+        "SELECT email_verified_at IS NOT NULL FROM users WHERE id = $1",
+    )
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+
+    if !verified {
+        return Err(AppError::Forbidden(
+            "Verify your email address to perform this action".into(),
+        ));
+    }
+
+    Ok(())
+}
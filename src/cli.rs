@@ -0,0 +1,316 @@
+//! The command line of the binary: the server and the admin commands. Every
+//! command loads the same [`Config`] and connects the same way as the server.
+
+use std::{io::BufRead, net::SocketAddr};
+
+use clap::{Parser, Subcommand};
+use sqlx::migrate::MigrateError;
+use thiserror::Error;
+
+use crate::{
+    admin,
+    config::{Config, ConfigError},
+    db::{self, Revert},
+    errors::AppError,
+    mailer, scheduler,
+    state::AppState,
+    tokens,
+};
+
+#[derive(Debug, Parser)]
+#[command(name = "task-manager", version, about = "Task manager API server")]
+pub struct Cli {
+    /// Defaults to `serve`.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the API server
+    Serve {
+        /// Don't apply pending migrations first (see also MIGRATE_ON_STARTUP)
+        #[arg(long)]
+        no_migrate: bool,
+    },
+    /// Apply, revert or list database migrations
+    #[command(subcommand)]
+    Migrate(MigrateCommand),
+    /// Manage accounts
+    #[command(subcommand)]
+    User(UserCommand),
+    /// Maintain tasks
+    #[command(subcommand)]
+    Tasks(TasksCommand),
+    /// Inspect the configuration
+    #[command(subcommand)]
+    Config(ConfigCommand),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum MigrateCommand {
+    /// Apply pending migrations
+    Up,
+    /// Revert the latest migration, if it has a down script
+    Down,
+    /// List migrations and whether they are applied
+    Status,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum UserCommand {
+    /// Create an account
+    Create {
+        email: String,
+        /// Read the password from the first line of stdin instead of
+        /// generating one
+        #[arg(long)]
+        password_stdin: bool,
+        /// Mark the email address as verified
+        #[arg(long)]
+        verified: bool,
+    },
+    /// Set a new password and sign out every session
+    ResetPassword {
+        email: String,
+        /// Read the password from the first line of stdin instead of
+        /// generating one
+        #[arg(long)]
+        password_stdin: bool,
+    },
+    /// Block an account from signing in and sign out every session
+    Disable { email: String },
+    /// Allow a disabled account to sign in again
+    Enable { email: String },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum TasksCommand {
+    /// Permanently delete archived tasks. Their owners can no longer
+    /// restore them; this cannot be undone
+    #[command(alias = "purge-trash")]
+    PurgeArchived {
+        /// Only delete tasks archived at least this many days ago
+        #[arg(long)]
+        older_than_days: i32,
+        /// Delete them; without this, only report how many would be deleted
+        #[arg(long)]
+        yes: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Load and validate the configuration, and check the database connection
+    Check,
+}
+
+#[derive(Debug, Error)]
+pub enum CliError {
+    #[error("Configuration error: {0}")]
+    Config(#[from] ConfigError),
+
+    #[error("Failed to connect to database: {0}")]
+    Database(#[from] sqlx::Error),
+
+    #[error("Migration failed: {0}")]
+    Migrate(#[from] MigrateError),
+
+    #[error("{}", describe(.0))]
+    App(#[from] AppError),
+
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+
+    /// The command cannot do what was asked, e.g. revert an irreversible
+    /// migration.
+    #[error("{0}")]
+    Refused(String),
+}
+
+/// Spells out invalid fields, which `AppError` only debug-prints.
+fn describe(error: &AppError) -> String {
+    match error {
+        AppError::InvalidFields(errors) => errors
+            .iter()
+            .map(|e| format!("{}: {}", e.field, e.message))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        other => other.to_string(),
+    }
+}
+
+/// Runs a parsed command line.
+pub async fn run(cli: Cli) -> Result<(), CliError> {
+    let config = Config::load()?;
+
+    match cli.command.unwrap_or(Command::Serve { no_migrate: false }) {
+        Command::Serve { no_migrate } => serve(config, !no_migrate).await,
+        Command::Migrate(command) => migrate(&config, command).await,
+        Command::User(command) => user(config, command).await,
+        Command::Tasks(TasksCommand::PurgeArchived {
+            older_than_days,
+            yes,
+        }) => {
+            let pool = db::create_pool(&config).await?;
+            if !yes {
+                let count = admin::count_archived_tasks(&pool, older_than_days).await?;
+                return Err(CliError::Refused(format!(
+                    "This would permanently delete {} archived tasks; pass --yes to delete them",
+                    count
+                )));
+            }
+            let count = admin::purge_archived_tasks(&pool, older_than_days).await?;
+            println!("Deleted {} archived tasks", count);
+            Ok(())
+        }
+        Command::Config(ConfigCommand::Check) => check_config(config).await,
+    }
+}
+
+fn state(config: Config, pool: sqlx::PgPool) -> Result<AppState, CliError> {
+    let mailer = mailer::from_config(&config).map_err(|e| ConfigError::invalid("SMTP_HOST", e))?;
+    Ok(AppState::new(config, pool, mailer)?)
+}
+
+async fn serve(config: Config, migrate: bool) -> Result<(), CliError> {
+    let bind_address = config.bind_address;
+    let interval = config.background_job_interval();
+
+    let pool = db::create_pool(&config).await?;
+    if migrate && config.migrate_on_startup {
+        db::MIGRATOR.run(&pool).await?;
+    }
+
+    scheduler::spawn_auto_archive(pool.clone(), interval);
+    scheduler::spawn_account_purge(pool.clone(), interval);
+
+    let state = state(config, pool)?;
+    scheduler::spawn_revocation_cleanup(state.revocations.clone(), interval);
+    scheduler::spawn_login_attempt_cleanup(state.login_throttle.clone(), interval);
+
+    let listener = tokio::net::TcpListener::bind(bind_address)
+        .await
+        .map_err(|e| {
+            std::io::Error::new(e.kind(), format!("Failed to bind {}: {}", bind_address, e))
+        })?;
+    println!("Server running on http://{}", bind_address);
+    axum::serve(
+        listener,
+        crate::app(state).into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await?;
+
+    Ok(())
+}
+
+async fn migrate(config: &Config, command: MigrateCommand) -> Result<(), CliError> {
+    let pool = db::create_pool(config).await?;
+
+    match command {
+        MigrateCommand::Up => {
+            db::MIGRATOR.run(&pool).await?;
+            println!("Database is up to date");
+        }
+        MigrateCommand::Down => match db::revert_latest_migration(&pool).await? {
+            Revert::Reverted(version, description) => {
+                println!("Reverted {} {}", version, description)
+            }
+            Revert::Irreversible(version, description) => {
+                return Err(CliError::Refused(format!(
+                    "Migration {} {} has no down script and cannot be reverted",
+                    version, description
+                )))
+            }
+            Revert::NothingApplied => println!("No migrations have been applied"),
+        },
+        MigrateCommand::Status => {
+            for migration in db::migration_status(&pool).await? {
+                let status = match (migration.applied, migration.modified) {
+                    (true, false) => "applied",
+                    (true, true) => "applied, modified since",
+                    (false, _) => "pending",
+                };
+                println!(
+                    "{} {} ({})",
+                    migration.version, migration.description, status
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The password from stdin, or a generated one that is printed.
+fn password(from_stdin: bool) -> Result<String, CliError> {
+    if from_stdin {
+        let mut line = String::new();
+        std::io::stdin().lock().read_line(&mut line)?;
+        return Ok(line.trim_end_matches(['\r', '\n']).to_string());
+    }
+
+    let password = tokens::generate_opaque();
+    println!("Generated password: {}", password);
+    Ok(password)
+}
+
+async fn user(config: Config, command: UserCommand) -> Result<(), CliError> {
+    let pool = db::create_pool(&config).await?;
+    let state = state(config, pool)?;
+
+    match command {
+        UserCommand::Create {
+            email,
+            password_stdin,
+            verified,
+        } => {
+            let password = password(password_stdin)?;
+            let user = admin::create_user(&state, &email, &password, verified).await?;
+            println!("Created {} ({})", user.email, user.id);
+        }
+        UserCommand::ResetPassword {
+            email,
+            password_stdin,
+        } => {
+            let password = password(password_stdin)?;
+            admin::reset_password(&state, &email, &password).await?;
+            println!(
+                "Reset the password of {} and signed out its sessions",
+                email
+            );
+        }
+        UserCommand::Disable { email } => {
+            admin::set_disabled(&state, &email, true).await?;
+            println!("Disabled {}", email);
+        }
+        UserCommand::Enable { email } => {
+            admin::set_disabled(&state, &email, false).await?;
+            println!("Enabled {}", email);
+        }
+    }
+
+    Ok(())
+}
+
+async fn check_config(config: Config) -> Result<(), CliError> {
+    println!("Configuration is valid");
+
+    // Connecting lazily reports key and password file problems even when the
+    // database is down
+    let pool = sqlx::postgres::PgPoolOptions::new()
+        .max_connections(1)
+        .connect_lazy(&config.database_url)?;
+    state(config, pool.clone())?;
+    println!("Signing keys and password files load");
+
+    pool.acquire().await?;
+    let pending = db::migration_status(&pool)
+        .await?
+        .iter()
+        .filter(|m| !m.applied)
+        .count();
+    println!("Database is reachable, {} pending migrations", pending);
+
+    Ok(())
+}
@@ -0,0 +1,420 @@
+//! Application configuration, read once at startup.
+//!
+//! Settings come from environment variables and, when `CONFIG_FILE` names
+//! one, a TOML file whose keys are the variable names in lowercase, e.g.
+//! `access_token_ttl_minutes = 15`. Environment variables take precedence.
+
+use std::{collections::HashMap, fmt::Display, net::SocketAddr, path::PathBuf, str::FromStr};
+
+use thiserror::Error;
+
+use crate::{
+    authz::VerifiedAction,
+    lockout::{AttemptStoreKind, LockoutPolicy},
+    mailer::{MailerKind, SmtpSettings, SmtpTls},
+    middleware::client::TrustedProxies,
+    oidc::{ProviderConfig, DEFAULT_SCOPES},
+};
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("{0} must be set")]
+    Missing(String),
+
+    #[error("Invalid {key}: {message}")]
+    Invalid { key: String, message: String },
+
+    #[error("Failed to read config file {path}: {message}")]
+    File { path: String, message: String },
+}
+
+impl ConfigError {
+    pub fn invalid(key: &str, message: impl Display) -> Self {
+        ConfigError::Invalid {
+            key: key.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+/// Every setting the application reads. Build it with [`Config::load`], or
+/// with [`Config::from_sources`] to set values without touching the process
+/// environment.
+#[derive(Clone)]
+pub struct Config {
+    /// `BIND_ADDRESS`, default `0.0.0.0:3000`.
+    pub bind_address: SocketAddr,
+    /// `DATABASE_URL`, required.
+    pub database_url: String,
+    /// `DATABASE_MAX_CONNECTIONS`, default 10.
+    pub database_max_connections: u32,
+    /// `MIGRATE_ON_STARTUP`, whether `serve` applies pending migrations
+    /// before listening. Default true.
+    pub migrate_on_startup: bool,
+    /// `APP_BASE_URL`, the base of links sent by email, without a trailing
+    /// slash. Default `http://localhost:3000`.
+    pub app_base_url: String,
+    /// `JWT_SECRET`, required. Signs single-purpose tokens, and access tokens
+    /// when there are no `jwt_keys`.
+    pub jwt_secret: String,
+    /// `TOTP_ENCRYPTION_KEY`, 32 bytes in base64 that encrypt two-factor
+    /// secrets at rest. Derived from `jwt_secret` when unset.
+    pub totp_encryption_key: Option<[u8; 32]>,
+    /// `JWT_KEYS`, PEM files that sign access tokens, signing key first.
+    pub jwt_keys: Vec<PathBuf>,
+    /// `ACCESS_TOKEN_TTL_MINUTES`, default 15.
+    pub access_token_ttl_minutes: i64,
+    /// `REFRESH_TOKEN_TTL_DAYS`, default 30.
+    pub refresh_token_ttl_days: i32,
+    /// `ACCOUNT_DELETION_GRACE_DAYS`, default 14.
+    pub account_deletion_grace_days: i32,
+    /// `REQUIRE_VERIFIED_EMAIL`, actions restricted to verified accounts.
+    pub require_verified_email: Vec<VerifiedAction>,
+    /// `BACKGROUND_JOB_INTERVAL_SECS`, how often background jobs run. Falls
+    /// back to the older `AUTO_ARCHIVE_INTERVAL_SECS`. Default 3600.
+    pub background_job_interval_secs: u64,
+    /// `MAILER` (`log`, `file` or `smtp`), with `MAIL_DIR` (default
+    /// `./mail`) for `file` and the `SMTP_*` settings and `MAIL_FROM` for
+    /// `smtp`.
+    pub mailer: MailerKind,
+    /// `LOGIN_ATTEMPT_STORE`, `postgres` (default) or `memory`.
+    pub login_attempt_store: AttemptStoreKind,
+    /// `LOGIN_LOCKOUT_*`.
+    pub lockout: LockoutPolicy,
+    /// `TRUSTED_PROXIES`, reverse proxies whose `X-Forwarded-For` gives the
+    /// client's address. Default none.
+    pub trusted_proxies: TrustedProxies,
+    /// `RATE_LIMIT_AUTH_PER_MINUTE`, default 20; 0 turns the limit off.
+    pub rate_limit_auth_per_minute: u32,
+    /// `RATE_LIMIT_API_PER_MINUTE`, default 600; 0 turns the limit off.
+    pub rate_limit_api_per_minute: u32,
+    /// `PASSWORD_HASH_MEMORY_KIB`, default 19456.
+    pub password_hash_memory_kib: u32,
+    /// `PASSWORD_HASH_ITERATIONS`, default 2.
+    pub password_hash_iterations: u32,
+    /// `PASSWORD_HASH_PARALLELISM`, default 1.
+    pub password_hash_parallelism: u32,
+    /// `PASSWORD_MIN_LENGTH`, default 8.
+    pub password_min_length: usize,
+    /// `BREACHED_PASSWORDS_FILE`, replacing the built-in list.
+    pub breached_passwords_file: Option<PathBuf>,
+    /// `OIDC_PROVIDERS`, each configured by its `OIDC_<NAME>_*` settings.
+    pub oidc_providers: Vec<ProviderConfig>,
+}
+
+impl Config {
+    /// Reads the process environment, and the TOML file named by
+    /// `CONFIG_FILE` if it is set.
+    pub fn load() -> Result<Self, ConfigError> {
+        let file = match std::env::var("CONFIG_FILE") {
+            Ok(path) => std::fs::read_to_string(&path).map_err(|e| ConfigError::File {
+                path,
+                message: e.to_string(),
+            })?,
+            Err(_) => String::new(),
+        };
+
+        Self::from_sources(&file, std::env::vars())
+    }
+
+    /// Builds the configuration from TOML text and variables, which take
+    /// precedence over it.
+    pub fn from_sources<I, K, V>(file: &str, vars: I) -> Result<Self, ConfigError>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        let file = file.parse::<toml::Table>().map_err(|e| ConfigError::File {
+            path: "CONFIG_FILE".to_string(),
+            message: e.to_string(),
+        })?;
+        let sources = Sources {
+            file,
+            vars: vars
+                .into_iter()
+                .map(|(key, value)| (key.into(), value.into()))
+                .collect(),
+        };
+
+        let default_lockout = LockoutPolicy::default();
+        let secs = |key: &str, default: std::time::Duration| {
+            sources
+                .get(key, default.as_secs())
+                .map(std::time::Duration::from_secs)
+        };
+
+        let config = Self {
+            bind_address: sources.get("BIND_ADDRESS", ([0, 0, 0, 0], 3000).into())?,
+            database_url: sources.required("DATABASE_URL")?,
+            database_max_connections: sources.get("DATABASE_MAX_CONNECTIONS", 10)?,
+            migrate_on_startup: sources.get("MIGRATE_ON_STARTUP", true)?,
+            app_base_url: sources
+                .get("APP_BASE_URL", "http://localhost:3000".to_string())?
+                .trim_end_matches('/')
+                .to_string(),
+            jwt_secret: sources.required("JWT_SECRET")?,
+            totp_encryption_key: sources
+                .raw("TOTP_ENCRYPTION_KEY")
+                .map(|key| {
+                    use base64::{engine::general_purpose::STANDARD, Engine};
+
+                    STANDARD
+                        .decode(&key)
+                        .ok()
+                        .and_then(|key| key.try_into().ok())
+                        .ok_or_else(|| {
+                            ConfigError::invalid(
+                                "TOTP_ENCRYPTION_KEY",
+                                "expected 32 bytes in base64",
+                            )
+                        })
+                })
+                .transpose()?,
+            jwt_keys: sources.list("JWT_KEYS").map(PathBuf::from).collect(),
+            access_token_ttl_minutes: sources.get("ACCESS_TOKEN_TTL_MINUTES", 15)?,
+            refresh_token_ttl_days: sources.get("REFRESH_TOKEN_TTL_DAYS", 30)?,
+            account_deletion_grace_days: sources.get("ACCOUNT_DELETION_GRACE_DAYS", 14)?,
+            require_verified_email: sources
+                .list("REQUIRE_VERIFIED_EMAIL")
+                .map(|name| {
+                    VerifiedAction::from_name(&name).ok_or_else(|| {
+                        ConfigError::invalid(
+                            "REQUIRE_VERIFIED_EMAIL",
+                            format!("unknown action {}", name),
+                        )
+                    })
+                })
+                .collect::<Result<_, _>>()?,
+            background_job_interval_secs: match sources.optional("BACKGROUND_JOB_INTERVAL_SECS")? {
+                Some(secs) => secs,
+                // Its name from when auto-archiving was the only job
+                None => sources.get("AUTO_ARCHIVE_INTERVAL_SECS", 3600)?,
+            },
+            mailer: match sources.raw("MAILER").as_deref() {
+                None | Some("log") => MailerKind::Log,
+                Some("file") => MailerKind::File(sources.get("MAIL_DIR", PathBuf::from("./mail"))?),
+                Some("smtp") => MailerKind::Smtp(sources.smtp()?),
+                Some(other) => {
+                    return Err(ConfigError::invalid(
+                        "MAILER",
+                        format!("expected log, file or smtp, got {}", other),
+                    ))
+                }
+            },
+            login_attempt_store: match sources.raw("LOGIN_ATTEMPT_STORE").as_deref() {
+                None | Some("postgres") => AttemptStoreKind::Postgres,
+                Some("memory") => AttemptStoreKind::Memory,
+                Some(other) => {
+                    return Err(ConfigError::invalid(
+                        "LOGIN_ATTEMPT_STORE",
+                        format!("expected postgres or memory, got {}", other),
+                    ))
+                }
+            },
+            lockout: LockoutPolicy {
+                account_threshold: sources
+                    .get("LOGIN_LOCKOUT_THRESHOLD", default_lockout.account_threshold)?,
+                ip_threshold: sources
+                    .get("LOGIN_LOCKOUT_IP_THRESHOLD", default_lockout.ip_threshold)?,
+                base_lockout: secs("LOGIN_LOCKOUT_BASE_SECS", default_lockout.base_lockout)?,
+                max_lockout: secs("LOGIN_LOCKOUT_MAX_SECS", default_lockout.max_lockout)?,
+                window: secs("LOGIN_LOCKOUT_WINDOW_SECS", default_lockout.window)?,
+            },
+            trusted_proxies: sources.get("TRUSTED_PROXIES", TrustedProxies::default())?,
+            rate_limit_auth_per_minute: sources.get("RATE_LIMIT_AUTH_PER_MINUTE", 20)?,
+            rate_limit_api_per_minute: sources.get("RATE_LIMIT_API_PER_MINUTE", 600)?,
+            password_hash_memory_kib: sources.get("PASSWORD_HASH_MEMORY_KIB", 19 * 1024)?,
+            password_hash_iterations: sources.get("PASSWORD_HASH_ITERATIONS", 2)?,
+            password_hash_parallelism: sources.get("PASSWORD_HASH_PARALLELISM", 1)?,
+            password_min_length: sources.get("PASSWORD_MIN_LENGTH", 8)?,
+            breached_passwords_file: sources.optional("BREACHED_PASSWORDS_FILE")?,
+            oidc_providers: sources
+                .list("OIDC_PROVIDERS")
+                .map(|name| sources.oidc_provider(&name.to_lowercase()))
+                .collect::<Result<_, _>>()?,
+        };
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks values that parse but make no sense.
+    fn validate(&self) -> Result<(), ConfigError> {
+        let positive = [
+            (
+                "DATABASE_MAX_CONNECTIONS",
+                self.database_max_connections as i64,
+            ),
+            ("ACCESS_TOKEN_TTL_MINUTES", self.access_token_ttl_minutes),
+            ("REFRESH_TOKEN_TTL_DAYS", self.refresh_token_ttl_days as i64),
+            (
+                "BACKGROUND_JOB_INTERVAL_SECS",
+                self.background_job_interval_secs as i64,
+            ),
+            (
+                "LOGIN_LOCKOUT_THRESHOLD",
+                self.lockout.account_threshold as i64,
+            ),
+            (
+                "LOGIN_LOCKOUT_IP_THRESHOLD",
+                self.lockout.ip_threshold as i64,
+            ),
+        ];
+        for (key, value) in positive {
+            if value <= 0 {
+                return Err(ConfigError::invalid(key, "must be greater than 0"));
+            }
+        }
+
+        if self.account_deletion_grace_days < 0 {
+            return Err(ConfigError::invalid(
+                "ACCOUNT_DELETION_GRACE_DAYS",
+                "must not be negative",
+            ));
+        }
+
+        if self.lockout.max_lockout < self.lockout.base_lockout {
+            return Err(ConfigError::invalid(
+                "LOGIN_LOCKOUT_MAX_SECS",
+                "must not be less than LOGIN_LOCKOUT_BASE_SECS",
+            ));
+        }
+
+        if !self.app_base_url.starts_with("http://") && !self.app_base_url.starts_with("https://") {
+            return Err(ConfigError::invalid(
+                "APP_BASE_URL",
+                "must start with http:// or https://",
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub fn access_token_ttl(&self) -> chrono::Duration {
+        chrono::Duration::minutes(self.access_token_ttl_minutes)
+    }
+
+    pub fn background_job_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.background_job_interval_secs)
+    }
+
+    /// Whether `action` is restricted to accounts with a verified email.
+    pub fn requires_verified_email(&self, action: VerifiedAction) -> bool {
+        self.require_verified_email.contains(&action)
+    }
+}
+
+/// The file and the variables, looked up by variable name. Empty values
+/// count as unset.
+struct Sources {
+    file: toml::Table,
+    vars: HashMap<String, String>,
+}
+
+impl Sources {
+    fn raw(&self, key: &str) -> Option<String> {
+        let value = match self.vars.get(key) {
+            Some(value) => Some(value.clone()),
+            None => self.file.get(&key.to_lowercase()).map(|value| match value {
+                toml::Value::String(s) => s.clone(),
+                toml::Value::Array(items) => items
+                    .iter()
+                    .map(|item| match item {
+                        toml::Value::String(s) => s.clone(),
+                        other => other.to_string(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(","),
+                other => other.to_string(),
+            }),
+        };
+
+        value
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+    }
+
+    fn optional<T>(&self, key: &str) -> Result<Option<T>, ConfigError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        self.raw(key)
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|e| ConfigError::invalid(key, format!("{} ({})", e, value)))
+            })
+            .transpose()
+    }
+
+    fn get<T>(&self, key: &str, default: T) -> Result<T, ConfigError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        Ok(self.optional(key)?.unwrap_or(default))
+    }
+
+    fn required(&self, key: &str) -> Result<String, ConfigError> {
+        self.raw(key)
+            .ok_or_else(|| ConfigError::Missing(key.to_string()))
+    }
+
+    /// A comma-separated list.
+    fn list(&self, key: &str) -> impl Iterator<Item = String> {
+        self.raw(key)
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(str::to_string)
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    /// `SMTP_HOST` and `MAIL_FROM`, and the optional `SMTP_TLS` (`starttls`,
+    /// `tls` or `none`), `SMTP_PORT`, `SMTP_USERNAME` and `SMTP_PASSWORD`.
+    fn smtp(&self) -> Result<SmtpSettings, ConfigError> {
+        let tls = match self.raw("SMTP_TLS").as_deref() {
+            None | Some("starttls") => SmtpTls::StartTls,
+            Some("tls") => SmtpTls::Tls,
+            Some("none") => SmtpTls::None,
+            Some(other) => {
+                return Err(ConfigError::invalid(
+                    "SMTP_TLS",
+                    format!("expected starttls, tls or none, got {}", other),
+                ))
+            }
+        };
+        let from = self.required("MAIL_FROM")?;
+
+        Ok(SmtpSettings {
+            host: self.required("SMTP_HOST")?,
+            port: self.get("SMTP_PORT", tls.default_port())?,
+            tls,
+            username: self.raw("SMTP_USERNAME"),
+            password: self.raw("SMTP_PASSWORD"),
+            from: from
+                .parse()
+                .map_err(|e| ConfigError::invalid("MAIL_FROM", format!("{} ({})", e, from)))?,
+        })
+    }
+
+    /// `OIDC_<NAME>_ISSUER`, `OIDC_<NAME>_CLIENT_ID` and the optional
+    /// `OIDC_<NAME>_CLIENT_SECRET` and `OIDC_<NAME>_SCOPES`.
+    fn oidc_provider(&self, name: &str) -> Result<ProviderConfig, ConfigError> {
+        let key = |setting: &str| format!("OIDC_{}_{}", name.to_uppercase(), setting);
+
+        Ok(ProviderConfig {
+            name: name.to_string(),
+            issuer: self.required(&key("ISSUER"))?,
+            client_id: self.required(&key("CLIENT_ID"))?,
+            client_secret: self.raw(&key("CLIENT_SECRET")),
+            scopes: self
+                .raw(&key("SCOPES"))
+                .unwrap_or_else(|| DEFAULT_SCOPES.to_string()),
+        })
+    }
+}
@@ -1,9 +1,95 @@
-use sqlx::{postgres::PgPoolOptions, PgPool};
+use sqlx::{
+    migrate::{Migrate, MigrateError, Migrator},
+    postgres::PgPoolOptions,
+    PgPool,
+};
 
-pub async fn create_pool(database_url: &str) -> PgPool {
+use crate::config::Config;
+
+/// The migrations in `migrations/`, embedded in the binary.
+pub static MIGRATOR: Migrator = sqlx::migrate!();
+
+pub async fn create_pool(config: &Config) -> Result<PgPool, sqlx::Error> {
     PgPoolOptions::new()
-        .max_connections(10)
-        .connect(database_url)
+        .max_connections(config.database_max_connections)
+        .connect(&config.database_url)
         .await
-        .expect("Failed to connect to database")
+}
+
+/// A migration and whether the database has it.
+#[derive(Debug)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    pub applied: bool,
+    /// Applied, but the file has changed since.
+    pub modified: bool,
+}
+
+/// Lists every migration, oldest first, with whether it has been applied.
+pub async fn migration_status(pool: &PgPool) -> Result<Vec<MigrationStatus>, MigrateError> {
+    let mut conn = pool.acquire().await?;
+    conn.ensure_migrations_table().await?;
+    let applied = conn.list_applied_migrations().await?;
+
+    Ok(MIGRATOR
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration())
+        .map(|m| {
+            let record = applied.iter().find(|a| a.version == m.version);
+            MigrationStatus {
+                version: m.version,
+                description: m.description.to_string(),
+                applied: record.is_some(),
+                modified: record.is_some_and(|a| a.checksum != m.checksum),
+            }
+        })
+        .collect())
+}
+
+/// The outcome of [`revert_latest_migration`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum Revert {
+    /// The migration with this version and description was reverted.
+    Reverted(i64, String),
+    /// The latest migration has no down script, so nothing was changed.
+    Irreversible(i64, String),
+    /// No migrations have been applied.
+    NothingApplied,
+}
+
+/// Reverts the most recently applied migration with its `.down.sql` script.
+/// Migrations written as a single `.sql` file cannot be reverted; every
+/// migration from 20260621090000 (disable users) on has a down script.
+pub async fn revert_latest_migration(pool: &PgPool) -> Result<Revert, MigrateError> {
+    let mut conn = pool.acquire().await?;
+    conn.ensure_migrations_table().await?;
+
+    let Some(latest) = conn
+        .list_applied_migrations()
+        .await?
+        .into_iter()
+        .map(|m| m.version)
+        .max()
+    else {
+        return Ok(Revert::NothingApplied);
+    };
+
+    let down = MIGRATOR
+        .iter()
+        .find(|m| m.version == latest && m.migration_type.is_down_migration());
+    match down {
+        Some(migration) => {
+            conn.revert(migration).await?;
+            Ok(Revert::Reverted(latest, migration.description.to_string()))
+        }
+        None => {
+            let description = MIGRATOR
+                .iter()
+                .find(|m| m.version == latest)
+                .map(|m| m.description.to_string())
+                .unwrap_or_default();
+            Ok(Revert::Irreversible(latest, description))
+        }
+    }
 }
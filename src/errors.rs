@@ -1,36 +1,323 @@
 use axum::{
-    http::StatusCode,
+    extract::rejection::{JsonRejection, PathRejection, QueryRejection},
+    http::{
+        header::{CONTENT_TYPE, RETRY_AFTER},
+        HeaderValue, StatusCode,
+    },
     response::{IntoResponse, Response},
     Json,
 };
+use serde::Serialize;
 use serde_json::json;
 use thiserror::Error;
+use validator::{ValidationError, ValidationErrors};
+
+use crate::{mailer::MailError, middleware::request_id::RequestContext};
+
+/// Postgres error codes, see <https://www.postgresql.org/docs/current/errcodes-appendix.html>.
+const UNIQUE_VIOLATION: &str = "23505";
+const FOREIGN_KEY_VIOLATION: &str = "23503";
+const CHECK_VIOLATION: &str = "23514";
 
 #[derive(Error, Debug)]
 pub enum AppError {
+    /// Database failures other than constraint violations, which become
+    /// [`AppError::Conflict`] or [`AppError::Validation`].
     #[error("Database error: {0}")]
-    Database(#[from] sqlx::Error),
+    Database(sqlx::Error),
+
+    #[error("Mail error: {0}")]
+    Mail(#[from] MailError),
+
+    /// Building an account export failed.
+    #[error("Export error: {0}")]
+    Export(std::io::Error),
+
+    #[error("Identity provider error: {0}")]
+    IdentityProvider(String),
 
     #[error("Authentication error: {0}")]
     Auth(String),
 
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+
     #[error("Not found: {0}")]
     NotFound(String),
 
     #[error("Bad request: {0}")]
     BadRequest(String),
+
+    /// No route answers the request's method at its path.
+    #[error("Method not allowed")]
+    MethodNotAllowed,
+
+    /// The request body is not JSON, e.g. its `Content-Type` is missing.
+    #[error("Unsupported media type: {0}")]
+    UnsupportedMediaType(String),
+
+    #[error("Payload too large")]
+    PayloadTooLarge,
+
+    /// The request clashes with existing data, e.g. an email already in use.
+    #[error("Conflict: {0}")]
+    Conflict(String),
+
+    /// The request is well-formed but its values are not acceptable.
+    #[error("Validation error: {0}")]
+    Validation(String),
+
+    /// Request fields that failed validation, listed in the response.
+    #[error("Invalid fields: {0:?}")]
+    InvalidFields(Vec<FieldError>),
+
+    #[error("Too many requests: {0}")]
+    TooManyRequests(String),
+
+    /// Too many failed logins; holds the seconds until the lockout ends.
+    #[error("Locked out for {0}s")]
+    LockedOut(u64),
+
+    /// Over the rate limit; holds the seconds until a request is allowed.
+    #[error("Rate limited for {0}s")]
+    RateLimited(u64),
+
+    /// A bug or misconfiguration. The message is logged, never sent.
+    #[error("Internal error: {0}")]
+    Internal(String),
+}
+
+impl From<sqlx::Error> for AppError {
+    fn from(error: sqlx::Error) -> Self {
+        let Some(db_error) = error.as_database_error() else {
+            return AppError::Database(error);
+        };
+
+        match db_error.code().as_deref() {
+            Some(UNIQUE_VIOLATION) => {
+                AppError::Conflict(conflict_message(db_error.constraint()).into())
+            }
+            // Deleting a row that is still referenced, or referencing a missing
+            // one. Postgres reports both the same way, apart from the wording
+            // of the message, which depends on the server's locale.
+            Some(FOREIGN_KEY_VIOLATION) => {
+                AppError::Conflict("A related record is missing or still in use".into())
+            }
+            Some(CHECK_VIOLATION) => AppError::Validation("A value is out of range".into()),
+            _ => AppError::Database(error),
+        }
+    }
+}
+
+impl From<JsonRejection> for AppError {
+    fn from(rejection: JsonRejection) -> Self {
+        match rejection {
+            JsonRejection::JsonDataError(e) => AppError::Validation(e.body_text()),
+            JsonRejection::JsonSyntaxError(e) => AppError::BadRequest(e.body_text()),
+            JsonRejection::MissingJsonContentType(e) => {
+                AppError::UnsupportedMediaType(e.body_text())
+            }
+            other => rejected(other.status(), other.body_text()),
+        }
+    }
+}
+
+impl From<PathRejection> for AppError {
+    fn from(rejection: PathRejection) -> Self {
+        rejected(rejection.status(), rejection.body_text())
+    }
+}
+
+impl From<QueryRejection> for AppError {
+    fn from(rejection: QueryRejection) -> Self {
+        rejected(rejection.status(), rejection.body_text())
+    }
 }
 
+/// Any other rejection by axum's extractors, by its status.
+fn rejected(status: StatusCode, text: String) -> AppError {
+    match status {
+        StatusCode::PAYLOAD_TOO_LARGE => AppError::PayloadTooLarge,
+        status if status.is_server_error() => AppError::Internal(text),
+        _ => AppError::BadRequest(text),
+    }
+}
+
+/// One invalid field of a request body.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldError {
+    pub field: String,
+    /// A stable identifier for the rule that failed, e.g. `too_long`.
+    pub code: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: &str, code: &str, message: impl Into<String>) -> Self {
+        Self {
+            field: field.to_string(),
+            code: code.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+impl From<ValidationErrors> for AppError {
+    fn from(errors: ValidationErrors) -> Self {
+        let mut fields: Vec<FieldError> = errors
+            .field_errors()
+            .into_iter()
+            .flat_map(|(field, errors)| {
+                errors.iter().map(move |error| {
+                    let message = match &error.message {
+                        Some(message) => message.to_string(),
+                        None => default_message(error),
+                    };
+                    FieldError::new(&field, &error.code, message)
+                })
+            })
+            .collect();
+        fields.sort_by(|a, b| a.field.cmp(&b.field));
+
+        AppError::InvalidFields(fields)
+    }
+}
+
+/// The message for a rule declared without one, from its parameters, so
+/// limits are only written down once.
+fn default_message(error: &ValidationError) -> String {
+    match (error.params.get("min"), error.params.get("max")) {
+        (None, Some(max)) => format!("Must be at most {} characters", max),
+        (Some(min), None) => format!("Must be at least {} characters", min),
+        _ => "The value is not valid".to_string(),
+    }
+}
+
+/// What clients are told for each unique constraint they can run into.
+fn conflict_message(constraint: Option<&str>) -> &'static str {
+    match constraint {
+        Some("users_email_key") => "Email is already in use",
+        Some("workspace_members_pkey") => "User is already a member",
+        Some("user_identities_provider_subject_key") => {
+            "This identity is already linked to an account"
+        }
+        _ => "The record already exists",
+    }
+}
+
+impl AppError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::Database(_)
+            | AppError::Mail(_)
+            | AppError::Export(_)
+            | AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::IdentityProvider(_) => StatusCode::BAD_GATEWAY,
+            AppError::Auth(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+            AppError::UnsupportedMediaType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            AppError::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::Validation(_) | AppError::InvalidFields(_) => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            AppError::TooManyRequests(_) | AppError::LockedOut(_) | AppError::RateLimited(_) => {
+                StatusCode::TOO_MANY_REQUESTS
+            }
+        }
+    }
+
+    /// A stable identifier for the kind of error, for clients to match on
+    /// instead of the message.
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::Database(_) | AppError::Internal(_) => "internal_error",
+            AppError::Mail(_) => "mail_failed",
+            AppError::Export(_) => "export_failed",
+            AppError::IdentityProvider(_) => "identity_provider_error",
+            AppError::Auth(_) => "unauthorized",
+            AppError::Forbidden(_) => "forbidden",
+            AppError::NotFound(_) => "not_found",
+            AppError::BadRequest(_) => "bad_request",
+            AppError::MethodNotAllowed => "method_not_allowed",
+            AppError::UnsupportedMediaType(_) => "unsupported_media_type",
+            AppError::PayloadTooLarge => "payload_too_large",
+            AppError::Conflict(_) => "conflict",
+            AppError::Validation(_) | AppError::InvalidFields(_) => "validation_failed",
+            AppError::TooManyRequests(_) => "too_many_requests",
+            AppError::LockedOut(_) => "locked_out",
+            AppError::RateLimited(_) => "rate_limited",
+        }
+    }
+
+    /// The message for the client. Server-side failures get a generic one, so
+    /// database and provider details never leak; the response logs them.
+    pub fn message(&self) -> String {
+        match self {
+            AppError::Database(_) | AppError::Internal(_) => "Internal server error".to_string(),
+            AppError::Mail(_) => "Failed to send email".to_string(),
+            AppError::Export(_) => "Failed to build export".to_string(),
+            AppError::IdentityProvider(_) => {
+                "Could not complete sign-in with the identity provider".to_string()
+            }
+            AppError::Auth(msg)
+            | AppError::Forbidden(msg)
+            | AppError::NotFound(msg)
+            | AppError::BadRequest(msg)
+            | AppError::UnsupportedMediaType(msg)
+            | AppError::Conflict(msg)
+            | AppError::Validation(msg)
+            | AppError::TooManyRequests(msg) => msg.clone(),
+            AppError::MethodNotAllowed => "This path does not support the method".to_string(),
+            AppError::PayloadTooLarge => "The request body is too large".to_string(),
+            AppError::InvalidFields(_) => "The request has invalid fields".to_string(),
+            AppError::LockedOut(_) => "Too many failed login attempts; try again later".to_string(),
+            AppError::RateLimited(_) => "Rate limit exceeded; try again later".to_string(),
+        }
+    }
+}
+
+/// The media type of error bodies, see RFC 7807.
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
 impl IntoResponse for AppError {
+    /// An RFC 7807 problem: `type`, `title`, `status` and `detail`, plus the
+    /// `code`, and the `instance` path and `request_id` of the failed request.
+    /// Validation failures list the invalid fields in `errors`.
     fn into_response(self) -> Response {
-        let (status, message) = match &self {
-            AppError::Database(_) => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
-            AppError::Auth(msg) => (StatusCode::UNAUTHORIZED, msg.clone()),
-            AppError::NotFound(msg) => (StatusCode::NOT_FOUND, msg.clone()),
-            AppError::BadRequest(msg) => (StatusCode::BAD_REQUEST, msg.clone()),
-        };
+        if self.status().is_server_error() {
+            tracing::error!("{}", self);
+        }
+
+        let status = self.status();
+        let mut body = json!({
+            "type": format!("urn:task-manager:problem:{}", self.code()),
+            "title": status.canonical_reason().unwrap_or("Error"),
+            "status": status.as_u16(),
+            "detail": self.message(),
+            "code": self.code(),
+        });
+        if let Some(context) = RequestContext::current() {
+            body["instance"] = json!(context.path);
+            body["request_id"] = json!(context.request_id);
+        }
+        if let AppError::InvalidFields(errors) = &self {
+            body["errors"] = json!(errors);
+        }
+
+        let mut response = (status, Json(body)).into_response();
+        response
+            .headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static(PROBLEM_JSON));
 
-        let body = Json(json!({ "error": message }));
-        (status, body).into_response()
+        if let AppError::LockedOut(retry_after) | AppError::RateLimited(retry_after) = self {
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from(retry_after));
+        }
+        response
     }
 }
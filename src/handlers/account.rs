@@ -0,0 +1,340 @@
+use std::sync::Arc;
+
+use axum::{extract::State, http::StatusCode};
+use serde_json::json;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    audit,
+    config::Config,
+    errors::AppError,
+    extract::Json,
+    mailer::{Email, Mailer},
+    middleware::auth::{AuthUser, CurrentToken},
+    models::user::{
+        AccountDeletion, ChangeEmailRequest, ChangePasswordRequest, ConfirmEmailChangeRequest,
+        DeleteAccountRequest, UserSettings,
+    },
+    password::{PasswordHasher, PasswordPolicy},
+    revocation::{self, RevocationStore},
+    tokens,
+    validation::{normalize_email, ValidatedJson},
+};
+
+const EMAIL_CHANGE_PURPOSE: &str = "email-change";
+const EMAIL_CHANGE_TTL_HOURS: i64 = 24;
+
+pub async fn get_settings(
+    State(pool): State<PgPool>,
+    AuthUser(user_id): AuthUser,
+) -> Result<Json<UserSettings>, AppError> {
+    let settings = sqlx::query_as::<_, UserSettings>(
+        "SELECT auto_archive_after_days FROM users WHERE id = $1",
+    )
+    .bind(user_id)
+    .fetch_optional(&pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound("User not found".into()))?;
+
+    Ok(Json(settings))
+}
+
+pub async fn update_settings(
+    State(pool): State<PgPool>,
+    AuthUser(user_id): AuthUser,
+    Json(body): Json<UserSettings>,
+) -> Result<Json<UserSettings>, AppError> {
+    if matches!(body.auto_archive_after_days, Some(days) if days <= 0) {
+        return Err(AppError::BadRequest(
+            "auto_archive_after_days must be a positive number of days".into(),
+        ));
+    }
+
+    let settings = sqlx::query_as::<_, UserSettings>(
+        "UPDATE users SET auto_archive_after_days = $1 WHERE id = $2
+         RETURNING auto_archive_after_days",
+    )
+    .bind(body.auto_archive_after_days)
+    .bind(user_id)
+    .fetch_optional(&pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound("User not found".into()))?;
+
+    Ok(Json(settings))
+}
+
+/// Fails unless `password` is the user's current password.
+pub async fn check_current_password(
+    pool: &PgPool,
+    hasher: &PasswordHasher,
+    user_id: Uuid,
+    password: &str,
+) -> Result<(), AppError> {
+    let password_hash =
+        sqlx::query_scalar::<_, String>("SELECT password_hash FROM users WHERE id = $1")
+            .bind(user_id)
+            .fetch_optional(pool)
+            .await?
+            .ok_or_else(|| AppError::NotFound("User not found".into()))?;
+
+    if !hasher.verify(password, &password_hash).await? {
+        return Err(AppError::BadRequest("Current password is incorrect".into()));
+    }
+
+    Ok(())
+}
+
+async fn ensure_email_available(pool: &PgPool, email: &str) -> Result<(), AppError> {
+    let taken =
+        sqlx::query_scalar::<_, bool>("SELECT EXISTS (SELECT 1 FROM users WHERE email = $1)")
+            .bind(email)
+            .fetch_one(pool)
+            .await?;
+
+    if taken {
+        return Err(AppError::Conflict("Email is already in use".into()));
+    }
+
+    Ok(())
+}
+
+/// Changes the password. The session making the request stays signed in;
+/// every other session is signed out.
+pub async fn change_password(
+    State(pool): State<PgPool>,
+    State(revocations): State<Arc<RevocationStore>>,
+    State(hasher): State<Arc<PasswordHasher>>,
+    State(policy): State<Arc<PasswordPolicy>>,
+    CurrentToken(claims): CurrentToken,
+    ValidatedJson(body): ValidatedJson<ChangePasswordRequest>,
+) -> Result<StatusCode, AppError> {
+    let user_id = claims.user_id()?;
+    check_current_password(&pool, &hasher, user_id, &body.current_password).await?;
+    policy.check("new_password", &body.new_password)?;
+
+    let mut tx = pool.begin().await?;
+
+    sqlx::query("UPDATE users SET password_hash = $1 WHERE id = $2")
+        .bind(hasher.hash(&body.new_password).await?)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    let revoked = revocation::revoke_other_sessions(&mut tx, user_id, claims.session_id()?).await?;
+    audit::record(&mut tx, user_id, "password_changed", json!({})).await?;
+
+    tx.commit().await?;
+    revocations.sessions_revoked(&revoked);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Starts an email change by sending a confirmation link to the new address.
+/// The account keeps its current email until the link is used.
+pub async fn request_email_change(
+    State(pool): State<PgPool>,
+    State(config): State<Arc<Config>>,
+    State(mailer): State<Arc<dyn Mailer>>,
+    State(hasher): State<Arc<PasswordHasher>>,
+    AuthUser(user_id): AuthUser,
+    ValidatedJson(body): ValidatedJson<ChangeEmailRequest>,
+) -> Result<StatusCode, AppError> {
+    check_current_password(&pool, &hasher, user_id, &body.password).await?;
+    let email = normalize_email(&body.email)?;
+    ensure_email_available(&pool, &email).await?;
+
+    let mut tx = pool.begin().await?;
+
+    sqlx::query("UPDATE users SET pending_email = $1 WHERE id = $2")
+        .bind(&email)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    audit::record(
+        &mut tx,
+        user_id,
+        "email_change_requested",
+        json!({ "to": email }),
+    )
+    .await?;
+
+    let token = tokens::sign(
+        &config.jwt_secret,
+        EMAIL_CHANGE_PURPOSE,
+        &format!("{}:{}", user_id, email),
+        chrono::Duration::hours(EMAIL_CHANGE_TTL_HOURS),
+    )?;
+
+    // Send after committing, so a slow mailer holds no connection or locks
+    tx.commit().await?;
+
+    let sent = mailer
+        .send(Email {
+            to: email.clone(),
+            subject: "Confirm your new email address".to_string(),
+            body: format!(
+                "Confirm that you want to use this address for your account: \
+                 {}/confirm-email-change?token={}\n\n\
+                 This link expires in {} hours. If you did not ask for this, you can ignore it.",
+                config.app_base_url, token, EMAIL_CHANGE_TTL_HOURS
+            ),
+        })
+        .await;
+
+    // A delivery failure leaves no pending change
+    if let Err(e) = sent {
+        sqlx::query("UPDATE users SET pending_email = NULL WHERE id = $1 AND pending_email = $2")
+            .bind(user_id)
+            .bind(&email)
+            .execute(&pool)
+            .await?;
+        return Err(e.into());
+    }
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// Switches the account to the address confirmed by the token. Only the most
+/// recently requested address can be confirmed. Other sessions are signed out
+/// and the old address is told about the change.
+pub async fn confirm_email_change(
+    State(pool): State<PgPool>,
+    State(config): State<Arc<Config>>,
+    State(mailer): State<Arc<dyn Mailer>>,
+    State(revocations): State<Arc<RevocationStore>>,
+    CurrentToken(claims): CurrentToken,
+    Json(body): Json<ConfirmEmailChangeRequest>,
+) -> Result<StatusCode, AppError> {
+    let user_id = claims.user_id()?;
+    let subject = tokens::verify(&config.jwt_secret, &body.token, EMAIL_CHANGE_PURPOSE)?;
+    let email = subject
+        .split_once(':')
+        .filter(|(id, _)| *id == user_id.to_string())
+        .map(|(_, email)| email.to_string())
+        .ok_or_else(|| AppError::BadRequest("Invalid or expired token".into()))?;
+
+    let mut tx = pool.begin().await?;
+
+    let previous = sqlx::query_scalar::<_, String>(
+        "SELECT email FROM users WHERE id = $1 AND pending_email = $2 FOR UPDATE",
+    )
+    .bind(user_id)
+    .bind(&email)
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| AppError::BadRequest("Invalid or expired token".into()))?;
+
+    ensure_email_available(&pool, &email).await?;
+
+    sqlx::query(
+        "UPDATE users SET email = pending_email, pending_email = NULL, email_verified_at = NOW()
+         WHERE id = $1",
+    )
+    .bind(user_id)
+    .execute(&mut *tx)
+    .await?;
+
+    let revoked = revocation::revoke_other_sessions(&mut tx, user_id, claims.session_id()?).await?;
+    audit::record(
+        &mut tx,
+        user_id,
+        "email_changed",
+        json!({ "from": previous, "to": email }),
+    )
+    .await?;
+
+    tx.commit().await?;
+    revocations.sessions_revoked(&revoked);
+
+    let notice = mailer
+        .send(Email {
+            to: previous,
+            subject: "Your email address was changed".to_string(),
+            body: format!(
+                "The email address of your account was changed to {}.\n\n\
+                 If you did not do this, reset your password and contact support.",
+                email
+            ),
+        })
+        .await;
+
+    if let Err(e) = notice {
+        tracing::error!("Failed to send email change notice: {}", e);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Schedules the account for deletion after the grace period and signs out
+/// every session. Logging in again and cancelling keeps the account.
+pub async fn delete_account(
+    State(pool): State<PgPool>,
+    State(config): State<Arc<Config>>,
+    State(revocations): State<Arc<RevocationStore>>,
+    State(hasher): State<Arc<PasswordHasher>>,
+    AuthUser(user_id): AuthUser,
+    ValidatedJson(body): ValidatedJson<DeleteAccountRequest>,
+) -> Result<(StatusCode, Json<AccountDeletion>), AppError> {
+    check_current_password(&pool, &hasher, user_id, &body.password).await?;
+
+    let mut tx = pool.begin().await?;
+
+    let deletion_scheduled_for = sqlx::query_scalar::<_, chrono::DateTime<chrono::Utc>>(
+        "UPDATE users
+         SET deletion_scheduled_for = COALESCE(
+            deletion_scheduled_for, NOW() + make_interval(days => $2)
+         )
+         WHERE id = $1
+         RETURNING deletion_scheduled_for",
+    )
+    .bind(user_id)
+    .bind(config.account_deletion_grace_days)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    revocation::revoke_all_tokens(&mut tx, user_id).await?;
+    audit::record(
+        &mut tx,
+        user_id,
+        "deletion_requested",
+        json!({ "scheduled_for": deletion_scheduled_for }),
+    )
+    .await?;
+
+    tx.commit().await?;
+    revocations.invalidate(user_id);
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(AccountDeletion {
+            deletion_scheduled_for,
+        }),
+    ))
+}
+
+/// Cancels a pending account deletion.
+pub async fn cancel_account_deletion(
+    State(pool): State<PgPool>,
+    AuthUser(user_id): AuthUser,
+) -> Result<StatusCode, AppError> {
+    let mut tx = pool.begin().await?;
+
+    let cancelled = sqlx::query(
+        "UPDATE users SET deletion_scheduled_for = NULL
+         WHERE id = $1 AND deletion_scheduled_for IS NOT NULL",
+    )
+    .bind(user_id)
+    .execute(&mut *tx)
+    .await?;
+
+    if cancelled.rows_affected() == 0 {
+        return Err(AppError::NotFound("No account deletion is pending".into()));
+    }
+
+    audit::record(&mut tx, user_id, "deletion_cancelled", json!({})).await?;
+    tx.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
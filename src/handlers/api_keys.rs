@@ -0,0 +1,90 @@
+use axum::{extract::State, http::StatusCode};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    errors::AppError,
+    extract::{Json, Path},
+    middleware::auth::{AuthUser, API_KEY_PREFIX},
+    models::api_key::{ApiKey, CreateApiKeyRequest, CreatedApiKey},
+    tokens,
+    validation::ValidatedJson,
+};
+
+/// Characters of the token kept in the clear to identify the key.
+const DISPLAY_PREFIX_LEN: usize = 12;
+
+/// Creates an API key. The token is returned once and only its hash is kept.
+pub async fn create_api_key(
+    State(pool): State<PgPool>,
+    AuthUser(user_id): AuthUser,
+    ValidatedJson(body): ValidatedJson<CreateApiKeyRequest>,
+) -> Result<(StatusCode, Json<CreatedApiKey>), AppError> {
+    if body.name.trim().is_empty() {
+        return Err(AppError::BadRequest(
+            "API key name must not be empty".into(),
+        ));
+    }
+    if body.scopes.is_empty() {
+        return Err(AppError::BadRequest(
+            "An API key needs at least one scope".into(),
+        ));
+    }
+    if matches!(body.expires_in_days, Some(days) if days <= 0) {
+        return Err(AppError::BadRequest(
+            "expires_in_days must be a positive number of days".into(),
+        ));
+    }
+
+    let token = format!("{}{}", API_KEY_PREFIX, tokens::generate_opaque());
+
+    let key = sqlx::query_as::<_, ApiKey>(
+        "INSERT INTO api_keys (user_id, name, prefix, token_hash, scopes, expires_at)
+         VALUES ($1, $2, $3, $4, $5, NOW() + make_interval(days => $6))
+         RETURNING id, name, prefix, scopes, expires_at, last_used_at, created_at",
+    )
+    .bind(user_id)
+    .bind(body.name.trim())
+    .bind(&token[..DISPLAY_PREFIX_LEN])
+    .bind(tokens::hash_opaque(&token))
+    .bind(&body.scopes)
+    .bind(body.expires_in_days)
+    .fetch_one(&pool)
+    .await?;
+
+    Ok((StatusCode::CREATED, Json(CreatedApiKey { key, token })))
+}
+
+pub async fn get_api_keys(
+    State(pool): State<PgPool>,
+    AuthUser(user_id): AuthUser,
+) -> Result<Json<Vec<ApiKey>>, AppError> {
+    let keys = sqlx::query_as::<_, ApiKey>(
+        "SELECT id, name, prefix, scopes, expires_at, last_used_at, created_at
+         FROM api_keys WHERE user_id = $1
+         ORDER BY created_at",
+    )
+    .bind(user_id)
+    .fetch_all(&pool)
+    .await?;
+
+    Ok(Json(keys))
+}
+
+pub async fn delete_api_key(
+    State(pool): State<PgPool>,
+    AuthUser(user_id): AuthUser,
+    Path(key_id): Path<Uuid>,
+) -> Result<StatusCode, AppError> {
+    let result = sqlx::query("DELETE FROM api_keys WHERE id = $1 AND user_id = $2")
+        .bind(key_id)
+        .bind(user_id)
+        .execute(&pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound("API key not found".into()));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
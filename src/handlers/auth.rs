@@ -1,49 +1,445 @@
-use axum::{extract::State, Json};
-use bcrypt::{hash, verify, DEFAULT_COST};
-use sqlx::PgPool;
+use std::sync::Arc;
+
+use axum::{extract::State, http::StatusCode};
+use jsonwebtoken::jwk::JwkSet;
+use serde_json::json;
+use sqlx::{PgConnection, PgExecutor, PgPool};
+use uuid::Uuid;
 
 use crate::{
+    audit,
+    config::Config,
     errors::AppError,
-    middleware::auth::create_jwt,
-    models::user::{AuthResponse, LoginRequest, RegisterRequest, User},
+    extract::{Json, Path},
+    handlers::{
+        two_factor, verification::send_verification_email, workspaces::create_personal_workspace,
+    },
+    keys::JwtKeys,
+    lockout::{LoginAttempt, LoginThrottle},
+    mailer::Mailer,
+    middleware::{
+        auth::{create_jwt, AuthUser, CurrentToken},
+        client::ClientInfo,
+    },
+    models::{
+        session::Session,
+        user::{
+            AuthResponse, LoginRequest, LoginResponse, LogoutRequest, RefreshRequest,
+            RegisterRequest, User,
+        },
+    },
+    password::{PasswordHasher, PasswordPolicy},
+    revocation::RevocationStore,
+    tokens,
+    totp::SecretCipher,
+    validation::{lookup_email, normalize_email, ValidatedJson},
 };
 
+async fn store_refresh_token(
+    conn: impl PgExecutor<'_>,
+    config: &Config,
+    user_id: Uuid,
+    session_id: Uuid,
+) -> Result<String, AppError> {
+    let refresh_token = tokens::generate_opaque();
+
+    sqlx::query(
+        "INSERT INTO refresh_tokens (user_id, session_id, token_hash, expires_at)
+         VALUES ($1, $2, $3, NOW() + make_interval(days => $4))",
+    )
+    .bind(user_id)
+    .bind(session_id)
+    .bind(tokens::hash_opaque(&refresh_token))
+    .bind(config.refresh_token_ttl_days)
+    .execute(conn)
+    .await?;
+
+    Ok(refresh_token)
+}
+
+async fn auth_response(
+    conn: impl PgExecutor<'_>,
+    config: &Config,
+    keys: &JwtKeys,
+    user_id: Uuid,
+    session_id: Uuid,
+    refresh_token: String,
+) -> Result<AuthResponse, AppError> {
+    let token_version =
+        sqlx::query_scalar::<_, i32>("SELECT token_version FROM users WHERE id = $1")
+            .bind(user_id)
+            .fetch_one(conn)
+            .await?;
+
+    Ok(AuthResponse {
+        token: create_jwt(
+            keys,
+            config.access_token_ttl(),
+            &user_id.to_string(),
+            session_id,
+            token_version,
+        )?,
+        refresh_token,
+        token_type: "Bearer".to_string(),
+        expires_in: config.access_token_ttl().num_seconds(),
+    })
+}
+
+/// Starts a new session for the client and issues its first access and
+/// refresh tokens. Disabled accounts get no session.
+pub async fn issue_tokens(
+    pool: &PgPool,
+    config: &Config,
+    keys: &JwtKeys,
+    user_id: Uuid,
+    client: &ClientInfo,
+) -> Result<AuthResponse, AppError> {
+    let disabled =
+        sqlx::query_scalar::<_, bool>("SELECT disabled_at IS NOT NULL FROM users WHERE id = $1")
+            .bind(user_id)
+            .fetch_one(pool)
+            .await?;
+    if disabled {
+        return Err(AppError::Forbidden("This account has been disabled".into()));
+    }
+
+    let session_id = sqlx::query_scalar::<_, Uuid>(
+        "INSERT INTO sessions (user_id, user_agent, ip_address, expires_at)
+         VALUES ($1, $2, $3, NOW() + make_interval(days => $4))
+         RETURNING id",
+    )
+    .bind(user_id)
+    .bind(&client.user_agent)
+    .bind(&client.ip_address)
+    .bind(config.refresh_token_ttl_days)
+    .fetch_one(pool)
+    .await?;
+
+    let refresh_token = store_refresh_token(pool, config, user_id, session_id).await?;
+    auth_response(pool, config, keys, user_id, session_id, refresh_token).await
+}
+
+/// Creates a user together with their personal workspace. Shared by `register`
+/// and flows that create accounts on the user's behalf, such as accepting an
+/// invitation.
+pub async fn create_user(
+    conn: &mut PgConnection,
+    hasher: &PasswordHasher,
+    email: &str,
+    password: &str,
+) -> Result<User, AppError> {
+    let email = normalize_email(email)?;
+    let password_hash = hasher.hash(password).await?;
+
+    let user = sqlx::query_as::<_, User>(
+        "INSERT INTO users (email, password_hash) VALUES ($1, $2) RETURNING *",
+    )
+    .bind(&email)
+    .bind(&password_hash)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    create_personal_workspace(conn, user.id).await?;
+
+    Ok(user)
+}
+
+/// Creates an account and emails a link to verify its address. The account
+/// can be used right away; `REQUIRE_VERIFIED_EMAIL` decides what stays locked
+/// until the address is verified.
+#[allow(clippy::too_many_arguments)]
 pub async fn register(
     State(pool): State<PgPool>,
-    Json(body): Json<RegisterRequest>,
+    State(config): State<Arc<Config>>,
+    State(mailer): State<Arc<dyn Mailer>>,
+    State(keys): State<Arc<JwtKeys>>,
+    State(hasher): State<Arc<PasswordHasher>>,
+    State(policy): State<Arc<PasswordPolicy>>,
+    client: ClientInfo,
+    ValidatedJson(body): ValidatedJson<RegisterRequest>,
 ) -> Result<Json<AuthResponse>, AppError> {
-    let password_hash = hash(&body.password, DEFAULT_COST)
-        .map_err(|_| AppError::BadRequest("Failed to hash password".into()))?;
+    policy.check("password", &body.password)?;
+
+    let mut tx = pool.begin().await?;
+    let user = create_user(&mut tx, &hasher, &body.email, &body.password).await?;
+    sqlx::query("UPDATE users SET verification_sent_at = NOW() WHERE id = $1")
+        .bind(user.id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    // The user can ask for another email, so delivery problems don't fail registration
+    if let Err(e) = send_verification_email(&config, mailer.as_ref(), user.id, &user.email).await {
+        tracing::error!("Failed to send verification email: {}", e);
+    }
+
+    Ok(Json(
+        issue_tokens(&pool, &config, &keys, user.id, &client).await?,
+    ))
+}
 
+/// Finds the account for an address typed at login or in a reset request.
+/// The address exactly as stored wins over its canonical form, so accounts
+/// whose stored address was never normalised, e.g. because it would have
+/// collided with another account, can still be reached.
+pub async fn find_user_by_email(
+    conn: impl PgExecutor<'_>,
+    email: &str,
+) -> Result<Option<User>, AppError> {
     let user = sqlx::query_as::<_, User>(
-        "INSERT INTO users (email, password_hash) VALUES ($1, $2) RETURNING *",
+        "SELECT * FROM users WHERE email = $1 OR email = $2
+         ORDER BY email = $1 DESC
+         LIMIT 1",
     )
-    .bind(&body.email)
-    .bind(&password_hash)
-    .fetch_one(&pool)
+    .bind(email.trim())
+    .bind(lookup_email(email))
+    .fetch_optional(conn)
     .await?;
 
-    let token = create_jwt(&user.id.to_string())?;
-    Ok(Json(AuthResponse { token }))
+    Ok(user)
 }
 
+/// Logs in with email and password. Accounts with two-factor authentication
+/// also need a code: either in the request, or in a second step with the
+/// challenge returned when it is missing. Repeated failures lock the account
+/// and the client's IP address out for a while, see [`LoginThrottle`].
+#[allow(clippy::too_many_arguments)]
 pub async fn login(
     State(pool): State<PgPool>,
-    Json(body): Json<LoginRequest>,
+    State(config): State<Arc<Config>>,
+    State(keys): State<Arc<JwtKeys>>,
+    State(throttle): State<Arc<LoginThrottle>>,
+    State(hasher): State<Arc<PasswordHasher>>,
+    State(cipher): State<Arc<SecretCipher>>,
+    client: ClientInfo,
+    ValidatedJson(body): ValidatedJson<LoginRequest>,
+) -> Result<Json<LoginResponse>, AppError> {
+    let ip_address = client.ip_address.as_deref();
+    let attempt = throttle
+        .begin(&lookup_email(&body.email), ip_address)
+        .await?;
+
+    let user = find_user_by_email(&pool, &body.email).await?;
+    let verified = match &user {
+        Some(user) => hasher.verify(&body.password, &user.password_hash).await?,
+        None => {
+            hasher.verify_unknown(&body.password).await?;
+            false
+        }
+    };
+
+    let user = match user {
+        Some(user) if verified => user,
+        user => {
+            login_failed(&pool, &throttle, attempt, user.map(|u| u.id), ip_address).await?;
+            return Err(AppError::Auth("Invalid email or password".into()));
+        }
+    };
+
+    // Only now is the password at hand to move an outdated hash to the
+    // current algorithm and parameters
+    if hasher.needs_rehash(&user.password_hash) {
+        sqlx::query("UPDATE users SET password_hash = $1 WHERE id = $2 AND password_hash = $3")
+            .bind(hasher.hash(&body.password).await?)
+            .bind(user.id)
+            .bind(&user.password_hash)
+            .execute(&pool)
+            .await?;
+    }
+
+    if user.totp_enabled_at.is_some() {
+        let Some(code) = &body.code else {
+            throttle.forgive(attempt).await?;
+            return Ok(Json(LoginResponse::TwoFactorRequired(
+                two_factor::challenge(&pool, &config, user.id).await?,
+            )));
+        };
+        if !two_factor::check_second_factor(&pool, &cipher, user.id, code).await? {
+            login_failed(&pool, &throttle, attempt, Some(user.id), ip_address).await?;
+            return Err(AppError::Auth("Invalid authentication code".into()));
+        }
+    }
+
+    throttle.succeeded(attempt).await?;
+
+    Ok(Json(LoginResponse::Tokens(
+        issue_tokens(&pool, &config, &keys, user.id, &client).await?,
+    )))
+}
+
+/// Finishes a failed login, and records on the account when it locks it out.
+pub async fn login_failed(
+    pool: &PgPool,
+    throttle: &LoginThrottle,
+    attempt: LoginAttempt,
+    user_id: Option<Uuid>,
+    ip_address: Option<&str>,
+) -> Result<(), AppError> {
+    let lockout = throttle.failed(attempt);
+
+    if let (Some(lockout), Some(user_id)) = (lockout, user_id) {
+        let mut conn = pool.acquire().await?;
+        audit::record(
+            &mut conn,
+            user_id,
+            "account_locked",
+            json!({ "locked_for_secs": lockout.as_secs(), "ip_address": ip_address }),
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Exchanges a refresh token for a new access/refresh token pair. Each refresh
+/// token works once; presenting one that was already rotated means it leaked,
+/// so its whole session is revoked and the client has to log in again.
+pub async fn refresh(
+    State(pool): State<PgPool>,
+    State(config): State<Arc<Config>>,
+    State(revocations): State<Arc<RevocationStore>>,
+    State(keys): State<Arc<JwtKeys>>,
+    client: ClientInfo,
+    Json(body): Json<RefreshRequest>,
 ) -> Result<Json<AuthResponse>, AppError> {
-    let user = sqlx::query_as::<_, User>("SELECT * FROM users WHERE email = $1")
-        .bind(&body.email)
+    let token_hash = tokens::hash_opaque(&body.refresh_token);
+
+    // The presented token is only used up together with issuing its
+    // replacement, so a failure in between leaves it valid for a retry
+    // instead of making the retry look like reuse
+    let mut tx = pool.begin().await?;
+
+    let rotated = sqlx::query_as::<_, (Uuid, Uuid)>(
+        "UPDATE refresh_tokens SET used_at = NOW()
+         WHERE token_hash = $1 AND used_at IS NULL AND revoked_at IS NULL AND expires_at > NOW()
+         RETURNING user_id, session_id",
+    )
+    .bind(&token_hash)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let Some((user_id, session_id)) = rotated else {
+        tx.rollback().await?;
+
+        let reused = sqlx::query_as::<_, (Uuid, Uuid)>(
+            "SELECT user_id, session_id FROM refresh_tokens
+             WHERE token_hash = $1 AND used_at IS NOT NULL",
+        )
+        .bind(&token_hash)
         .fetch_optional(&pool)
-        .await?
-        .ok_or_else(|| AppError::Auth("Invalid email or password".into()))?;
+        .await?;
+
+        if let Some((user_id, session_id)) = reused {
+            if revocations.revoke_session(user_id, session_id).await? {
+                tracing::warn!("Refresh token reuse detected; revoked session");
+            }
+        }
+
+        return Err(AppError::Auth("Invalid or expired refresh token".into()));
+    };
 
-    let valid = verify(&body.password, &user.password_hash)
-        .map_err(|_| AppError::Auth("Invalid email or password".into()))?;
+    sqlx::query(
+        "UPDATE sessions SET last_seen_at = NOW(),
+                expires_at = NOW() + make_interval(days => $2),
+                user_agent = COALESCE($3, user_agent),
+                ip_address = COALESCE($4, ip_address)
+         WHERE id = $1",
+    )
+    .bind(session_id)
+    .bind(config.refresh_token_ttl_days)
+    .bind(&client.user_agent)
+    .bind(&client.ip_address)
+    .execute(&mut *tx)
+    .await?;
+
+    let refresh_token = store_refresh_token(&mut *tx, &config, user_id, session_id).await?;
+    let response =
+        auth_response(&mut *tx, &config, &keys, user_id, session_id, refresh_token).await?;
+    tx.commit().await?;
+
+    Ok(Json(response))
+}
 
-    if !valid {
-        return Err(AppError::Auth("Invalid email or password".into()));
+/// Signs out the current session, revoking the presented access token and its
+/// refresh tokens. A refresh token in the body signs out its session as well.
+pub async fn logout(
+    State(pool): State<PgPool>,
+    State(revocations): State<Arc<RevocationStore>>,
+    CurrentToken(claims): CurrentToken,
+    body: Option<Json<LogoutRequest>>,
+) -> Result<StatusCode, AppError> {
+    let user_id = claims.user_id()?;
+    let session_id = claims.session_id()?;
+
+    revocations.revoke_session(user_id, session_id).await?;
+
+    if let Some(Json(LogoutRequest {
+        refresh_token: Some(refresh_token),
+    })) = body
+    {
+        let other = sqlx::query_scalar::<_, Uuid>(
+            "SELECT session_id FROM refresh_tokens WHERE token_hash = $1 AND user_id = $2",
+        )
+        .bind(tokens::hash_opaque(&refresh_token))
+        .bind(user_id)
+        .fetch_optional(&pool)
+        .await?;
+
+        if let Some(other) = other {
+            revocations.revoke_session(user_id, other).await?;
+        }
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Revokes every access and refresh token of the caller, on all devices.
+pub async fn logout_all(
+    State(revocations): State<Arc<RevocationStore>>,
+    AuthUser(user_id): AuthUser,
+) -> Result<StatusCode, AppError> {
+    revocations.revoke_all(user_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Lists the caller's active sessions, most recently used first.
+pub async fn get_sessions(
+    State(pool): State<PgPool>,
+    CurrentToken(claims): CurrentToken,
+) -> Result<Json<Vec<Session>>, AppError> {
+    let user_id = claims.user_id()?;
+    let session_id = claims.session_id()?;
+
+    let sessions = sqlx::query_as::<_, Session>(
+        "SELECT id, user_agent, ip_address, created_at, last_seen_at, expires_at,
+                id = $2 AS current
+         FROM sessions
+         WHERE user_id = $1 AND revoked_at IS NULL AND expires_at > NOW()
+         ORDER BY last_seen_at DESC",
+    )
+    .bind(user_id)
+    .bind(session_id)
+    .fetch_all(&pool)
+    .await?;
+
+    Ok(Json(sessions))
+}
+
+/// Signs out one of the caller's sessions, e.g. a lost device.
+pub async fn delete_session(
+    State(revocations): State<Arc<RevocationStore>>,
+    AuthUser(user_id): AuthUser,
+    Path(session_id): Path<Uuid>,
+) -> Result<StatusCode, AppError> {
+    if !revocations.revoke_session(user_id, session_id).await? {
+        return Err(AppError::NotFound("Session not found".into()));
     }
 
-    let token = create_jwt(&user.id.to_string())?;
-    Ok(Json(AuthResponse { token }))
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Publishes the public keys that verify access tokens, so other services
+/// can validate them without sharing a secret.
+pub async fn jwks(State(keys): State<Arc<JwtKeys>>) -> Json<JwkSet> {
+    Json(keys.jwks())
 }
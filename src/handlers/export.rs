@@ -0,0 +1,177 @@
+use std::io::{Cursor, Write};
+
+use axum::{
+    extract::State,
+    http::header,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use sqlx::PgPool;
+use zip::{write::SimpleFileOptions, ZipWriter};
+
+use crate::{
+    errors::AppError,
+    middleware::auth::CurrentToken,
+    models::{
+        api_key::ApiKey,
+        export::{
+            AccountExport, AuditEvent, ExportIdentity, ExportMembership, ExportProfile,
+            ExportTwoFactor,
+        },
+        session::Session,
+        task::{Task, TaskHistoryEntry},
+        workspace::Invitation,
+    },
+};
+
+/// Downloads everything stored about the caller as a zip archive: the full
+/// export as `export.json`, plus `tasks.csv` and `workspaces.csv` for use in
+/// spreadsheets.
+pub async fn export_account(
+    State(pool): State<PgPool>,
+    CurrentToken(claims): CurrentToken,
+) -> Result<Response, AppError> {
+    let user_id = claims.user_id()?;
+
+    let profile = sqlx::query_as::<_, ExportProfile>(
+        "SELECT id, email, created_at, email_verified_at, auto_archive_after_days,
+                deletion_scheduled_for
+         FROM users WHERE id = $1",
+    )
+    .bind(user_id)
+    .fetch_optional(&pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound("User not found".into()))?;
+
+    let workspaces = sqlx::query_as::<_, ExportMembership>(
+        "SELECT w.id AS workspace_id, w.name, w.is_personal, m.role, m.joined_at
+         FROM workspace_members m JOIN workspaces w ON w.id = m.workspace_id
+         WHERE m.user_id = $1
+         ORDER BY m.joined_at",
+    )
+    .bind(user_id)
+    .fetch_all(&pool)
+    .await?;
+
+    let tasks = sqlx::query_as::<_, Task>(
+        "SELECT * FROM tasks WHERE user_id = $1 OR assignee_id = $1 ORDER BY created_at",
+    )
+    .bind(user_id)
+    .fetch_all(&pool)
+    .await?;
+
+    let task_history = sqlx::query_as::<_, TaskHistoryEntry>(
+        "SELECT * FROM task_history WHERE actor_id = $1 ORDER BY created_at",
+    )
+    .bind(user_id)
+    .fetch_all(&pool)
+    .await?;
+
+    let sessions = sqlx::query_as::<_, Session>(
+        "SELECT id, user_agent, ip_address, created_at, last_seen_at, expires_at,
+                id = $2 AS current
+         FROM sessions WHERE user_id = $1
+         ORDER BY created_at",
+    )
+    .bind(user_id)
+    .bind(claims.session_id()?)
+    .fetch_all(&pool)
+    .await?;
+
+    let api_keys = sqlx::query_as::<_, ApiKey>(
+        "SELECT id, name, prefix, scopes, expires_at, last_used_at, created_at
+         FROM api_keys WHERE user_id = $1
+         ORDER BY created_at",
+    )
+    .bind(user_id)
+    .fetch_all(&pool)
+    .await?;
+
+    let identities = sqlx::query_as::<_, ExportIdentity>(
+        "SELECT provider, subject, email, created_at, last_login_at
+         FROM user_identities WHERE user_id = $1
+         ORDER BY created_at",
+    )
+    .bind(user_id)
+    .fetch_all(&pool)
+    .await?;
+
+    let invitations = sqlx::query_as::<_, Invitation>(
+        "SELECT id, workspace_id, email, role, invited_by, expires_at, accepted_at, created_at
+         FROM workspace_invitations
+         WHERE invited_by = $1 OR lower(email) = lower($2)
+         ORDER BY created_at",
+    )
+    .bind(user_id)
+    .bind(&profile.email)
+    .fetch_all(&pool)
+    .await?;
+
+    let two_factor = sqlx::query_as::<_, ExportTwoFactor>(
+        "SELECT totp_enabled_at AS enabled_at,
+                (SELECT COUNT(*) FROM recovery_codes
+                 WHERE user_id = $1 AND used_at IS NULL) AS unused_recovery_codes
+         FROM users WHERE id = $1",
+    )
+    .bind(user_id)
+    .fetch_one(&pool)
+    .await?;
+
+    let audit_events = sqlx::query_as::<_, AuditEvent>(
+        "SELECT id, action, details, created_at FROM audit_events
+         WHERE user_id = $1 ORDER BY created_at",
+    )
+    .bind(user_id)
+    .fetch_all(&pool)
+    .await?;
+
+    let export = AccountExport {
+        exported_at: chrono::Utc::now(),
+        profile,
+        workspaces,
+        tasks,
+        task_history,
+        sessions,
+        api_keys,
+        identities,
+        invitations,
+        two_factor,
+        audit_events,
+    };
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/zip"),
+            (
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"task-manager-export.zip\"",
+            ),
+        ],
+        build_archive(&export).map_err(AppError::Export)?,
+    )
+        .into_response())
+}
+
+fn build_archive(export: &AccountExport) -> std::io::Result<Vec<u8>> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default();
+
+    zip.start_file("export.json", options)?;
+    zip.write_all(&serde_json::to_vec_pretty(export)?)?;
+
+    zip.start_file("tasks.csv", options)?;
+    zip.write_all(&to_csv(&export.tasks)?)?;
+
+    zip.start_file("workspaces.csv", options)?;
+    zip.write_all(&to_csv(&export.workspaces)?)?;
+
+    Ok(zip.finish()?.into_inner())
+}
+
+fn to_csv<T: Serialize>(rows: &[T]) -> std::io::Result<Vec<u8>> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for row in rows {
+        writer.serialize(row)?;
+    }
+    writer.into_inner().map_err(|e| e.into_error())
+}
@@ -0,0 +1,217 @@
+use std::sync::Arc;
+
+use axum::{extract::State, http::StatusCode};
+use sqlx::PgPool;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    authz::{self, Permission, VerifiedAction},
+    config::Config,
+    errors::AppError,
+    extract::{Json, Path},
+    handlers::{
+        auth::{create_user, issue_tokens},
+        workspaces::ensure_shared,
+    },
+    keys::JwtKeys,
+    mailer::{Email, Mailer},
+    middleware::{client::ClientInfo, workspace::WorkspaceAccess},
+    models::{
+        user::User,
+        workspace::{
+            AcceptInvitationRequest, AcceptInvitationResponse, CreateInvitationRequest, Invitation,
+            Role,
+        },
+    },
+    password::{PasswordHasher, PasswordPolicy},
+    tokens,
+    validation::normalize_email,
+};
+
+const INVITATION_PURPOSE: &str = "invitation";
+const INVITATION_TTL_DAYS: i64 = 7;
+
+pub async fn create_invitation(
+    State(pool): State<PgPool>,
+    State(config): State<Arc<Config>>,
+    State(mailer): State<Arc<dyn Mailer>>,
+    access: WorkspaceAccess,
+    Json(body): Json<CreateInvitationRequest>,
+) -> Result<(StatusCode, Json<Invitation>), AppError> {
+    access.require(Permission::ManageMembers)?;
+    ensure_shared(&pool, access.workspace_id).await?;
+    authz::require_verified_email(
+        &pool,
+        &config,
+        access.user_id,
+        VerifiedAction::InviteMembers,
+    )
+    .await?;
+
+    let email = normalize_email(&body.email)?;
+    let role = body.role.unwrap_or(Role::Editor);
+    if role == Role::Owner && access.role != Role::Owner {
+        return Err(AppError::Forbidden("Only owners can invite owners".into()));
+    }
+
+    let already_member = sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS (
+            SELECT 1 FROM workspace_members m JOIN users u ON u.id = m.user_id
+            WHERE m.workspace_id = $1 AND u.email = $2
+        )",
+    )
+    .bind(access.workspace_id)
+    .bind(&email)
+    .fetch_one(&pool)
+    .await?;
+
+    if already_member {
+        return Err(AppError::Conflict("User is already a member".into()));
+    }
+
+    let mut tx = pool.begin().await?;
+
+    let invitation = sqlx::query_as::<_, Invitation>(
+        "INSERT INTO workspace_invitations (workspace_id, email, role, invited_by, expires_at)
+         VALUES ($1, $2, $3, $4, NOW() + make_interval(days => $5))
+         RETURNING *",
+    )
+    .bind(access.workspace_id)
+    .bind(&email)
+    .bind(role)
+    .bind(access.user_id)
+    .bind(INVITATION_TTL_DAYS as i32)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let workspace_name =
+        sqlx::query_scalar::<_, String>("SELECT name FROM workspaces WHERE id = $1")
+            .bind(access.workspace_id)
+            .fetch_one(&mut *tx)
+            .await?;
+
+    let token = tokens::sign(
+        &config.jwt_secret,
+        INVITATION_PURPOSE,
+        &invitation.id.to_string(),
+        chrono::Duration::days(INVITATION_TTL_DAYS),
+    )?;
+
+    // Send after committing, so a slow mailer holds no connection or locks
+    tx.commit().await?;
+
+    let sent = mailer
+        .send(Email {
+            to: invitation.email.clone(),
+            subject: format!("You're invited to {}", workspace_name),
+            body: format!(
+                "You have been invited to join the \"{}\" workspace as {}.\n\n\
+                 Accept the invitation: {}/accept-invitation?token={}\n\n\
+                 This invitation expires in {} days and can only be used once.",
+                workspace_name,
+                role.as_str(),
+                config.app_base_url,
+                token,
+                INVITATION_TTL_DAYS
+            ),
+        })
+        .await;
+
+    // A delivery failure leaves no dangling invitation
+    if let Err(e) = sent {
+        sqlx::query("DELETE FROM workspace_invitations WHERE id = $1")
+            .bind(invitation.id)
+            .execute(&pool)
+            .await?;
+        return Err(e.into());
+    }
+
+    Ok((StatusCode::CREATED, Json(invitation)))
+}
+
+/// Accepts an invitation, creating an account for the invited email if none
+/// exists yet. Possession of the emailed token proves control of the address,
+/// so no other authentication is required.
+#[allow(clippy::too_many_arguments)]
+pub async fn accept_invitation(
+    State(pool): State<PgPool>,
+    State(config): State<Arc<Config>>,
+    State(keys): State<Arc<JwtKeys>>,
+    State(hasher): State<Arc<PasswordHasher>>,
+    State(policy): State<Arc<PasswordPolicy>>,
+    client: ClientInfo,
+    Path(token): Path<String>,
+    body: Option<Json<AcceptInvitationRequest>>,
+) -> Result<Json<AcceptInvitationResponse>, AppError> {
+    let invitation_id = tokens::verify(&config.jwt_secret, &token, INVITATION_PURPOSE)?;
+    let invitation_id = Uuid::parse_str(&invitation_id)
+        .map_err(|_| AppError::BadRequest("Invalid or expired token".into()))?;
+    let body = body.map(|Json(body)| body).unwrap_or_default();
+    body.validate()?;
+
+    let mut tx = pool.begin().await?;
+
+    // Marking the invitation accepted up front makes the token single-use
+    let invitation = sqlx::query_as::<_, Invitation>(
+        "UPDATE workspace_invitations SET accepted_at = NOW()
+         WHERE id = $1 AND accepted_at IS NULL AND expires_at > NOW()
+         RETURNING *",
+    )
+    .bind(invitation_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| AppError::BadRequest("Invitation is invalid, expired or already used".into()))?;
+
+    let existing = sqlx::query_as::<_, User>("SELECT * FROM users WHERE email = $1")
+        .bind(&invitation.email)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+    let (user, created) = match existing {
+        Some(user) => (user, false),
+        None => {
+            let password = body.password.ok_or_else(|| {
+                AppError::BadRequest("A password is required to create an account".into())
+            })?;
+            policy.check("password", &password)?;
+            (
+                create_user(&mut tx, &hasher, &invitation.email, &password).await?,
+                true,
+            )
+        }
+    };
+
+    sqlx::query(
+        "INSERT INTO workspace_members (workspace_id, user_id, role) VALUES ($1, $2, $3)
+         ON CONFLICT (workspace_id, user_id) DO NOTHING",
+    )
+    .bind(invitation.workspace_id)
+    .bind(user.id)
+    .bind(invitation.role)
+    .execute(&mut *tx)
+    .await?;
+
+    // The emailed token proves the invitee controls the address
+    sqlx::query(
+        "UPDATE users SET email_verified_at = COALESCE(email_verified_at, NOW()) WHERE id = $1",
+    )
+    .bind(user.id)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    let auth = if created {
+        Some(issue_tokens(&pool, &config, &keys, user.id, &client).await?)
+    } else {
+        None
+    };
+
+    Ok(Json(AcceptInvitationResponse {
+        workspace_id: invitation.workspace_id,
+        user_id: user.id,
+        role: invitation.role,
+        auth,
+    }))
+}
@@ -1,2 +1,11 @@
+pub mod account;
+pub mod api_keys;
 pub mod auth;
+pub mod export;
+pub mod invitations;
+pub mod oidc;
+pub mod password_reset;
 pub mod tasks;
+pub mod two_factor;
+pub mod verification;
+pub mod workspaces;
@@ -0,0 +1,293 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    http::{header, HeaderMap},
+};
+use serde_json::json;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    audit,
+    config::Config,
+    errors::AppError,
+    extract::{Json, Path, Query},
+    handlers::{
+        auth::{create_user, issue_tokens},
+        two_factor,
+    },
+    keys::JwtKeys,
+    middleware::client::ClientInfo,
+    models::{
+        oidc::{AuthorizationUrl, OidcCallbackRequest},
+        user::LoginResponse,
+    },
+    oidc::{IdTokenClaims, OidcClient},
+    password::PasswordHasher,
+    tokens,
+    validation::normalize_email,
+};
+
+/// How long the user has to sign in at the provider and come back.
+const AUTH_REQUEST_TTL_MINUTES: i32 = 10;
+
+/// Holds the login's `state` in the browser that started it, so a callback
+/// replayed into another browser is refused.
+const STATE_COOKIE: &str = "oidc_state";
+
+/// Where the provider sends the user back to: the callback endpoint, which
+/// takes the `code` and `state` query parameters.
+fn redirect_uri(config: &Config, provider: &str) -> String {
+    format!("{}/auth/oidc/{}/callback", config.app_base_url, provider)
+}
+
+fn state_cookie(config: &Config, provider: &str, state: &str, max_age_secs: i32) -> String {
+    let secure = if config.app_base_url.starts_with("https://") {
+        "; Secure"
+    } else {
+        ""
+    };
+    format!(
+        "{}={}; Path=/auth/oidc/{}; Max-Age={}; HttpOnly; SameSite=Lax{}",
+        STATE_COOKIE, state, provider, max_age_secs, secure
+    )
+}
+
+/// The `state` in the request's cookie, if any.
+fn cookie_state(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .find_map(|cookie| {
+            cookie
+                .trim()
+                .strip_prefix(STATE_COOKIE)
+                .and_then(|rest| rest.strip_prefix('='))
+        })
+}
+
+/// Starts a login with an identity provider and returns the URL to send the
+/// user to. The response sets a cookie that the callback checks.
+pub async fn authorize(
+    State(pool): State<PgPool>,
+    State(config): State<Arc<Config>>,
+    State(oidc): State<Arc<OidcClient>>,
+    Path(provider): Path<String>,
+) -> Result<([(header::HeaderName, String); 1], Json<AuthorizationUrl>), AppError> {
+    let request = oidc
+        .authorization_request(&provider, &redirect_uri(&config, &provider))
+        .await?;
+
+    sqlx::query("DELETE FROM oidc_auth_requests WHERE expires_at < NOW()")
+        .execute(&pool)
+        .await?;
+
+    sqlx::query(
+        "INSERT INTO oidc_auth_requests (provider, state_hash, nonce, code_verifier, expires_at)
+         VALUES ($1, $2, $3, $4, NOW() + make_interval(mins => $5))",
+    )
+    .bind(&provider)
+    .bind(tokens::hash_opaque(&request.state))
+    .bind(&request.nonce)
+    .bind(&request.code_verifier)
+    .bind(AUTH_REQUEST_TTL_MINUTES)
+    .execute(&pool)
+    .await?;
+
+    Ok((
+        [(
+            header::SET_COOKIE,
+            state_cookie(
+                &config,
+                &provider,
+                &request.state,
+                AUTH_REQUEST_TTL_MINUTES * 60,
+            ),
+        )],
+        Json(AuthorizationUrl {
+            authorization_url: request.url,
+        }),
+    ))
+}
+
+/// Finishes a login with an identity provider, from the provider's redirect
+/// (`GET`, with query parameters) or from a frontend passing them on (`POST`,
+/// with a JSON body). Either way the request must carry the cookie set by
+/// [`authorize`]. Like `POST /auth/login`, it returns a two-factor challenge
+/// instead of tokens when the account has two-factor authentication enabled.
+#[allow(clippy::too_many_arguments)]
+pub async fn callback(
+    State(pool): State<PgPool>,
+    State(config): State<Arc<Config>>,
+    State(oidc): State<Arc<OidcClient>>,
+    State(keys): State<Arc<JwtKeys>>,
+    State(hasher): State<Arc<PasswordHasher>>,
+    client: ClientInfo,
+    Path(provider): Path<String>,
+    headers: HeaderMap,
+    query: Option<Query<OidcCallbackRequest>>,
+    body: Option<Json<OidcCallbackRequest>>,
+) -> Result<([(header::HeaderName, String); 1], Json<LoginResponse>), AppError> {
+    oidc.provider(&provider)?;
+
+    let body = match (body, query) {
+        (Some(Json(body)), _) | (None, Some(Query(body))) => body,
+        (None, None) => {
+            return Err(AppError::BadRequest(
+                "Expected the code and state parameters".into(),
+            ))
+        }
+    };
+    if cookie_state(&headers) != Some(body.state.as_str()) {
+        return Err(AppError::BadRequest(
+            "The login was not started in this browser".into(),
+        ));
+    }
+    let clear_cookie = [(header::SET_COOKIE, state_cookie(&config, &provider, "", 0))];
+
+    // Deleting the request makes each state usable once
+    let (nonce, code_verifier) = sqlx::query_as::<_, (String, String)>(
+        "DELETE FROM oidc_auth_requests
+         WHERE provider = $1 AND state_hash = $2 AND expires_at > NOW()
+         RETURNING nonce, code_verifier",
+    )
+    .bind(&provider)
+    .bind(tokens::hash_opaque(&body.state))
+    .fetch_optional(&pool)
+    .await?
+    .ok_or_else(|| AppError::BadRequest("Invalid or expired login state".into()))?;
+
+    let claims = oidc
+        .exchange_code(
+            &provider,
+            &body.code,
+            &redirect_uri(&config, &provider),
+            &code_verifier,
+            &nonce,
+        )
+        .await?;
+
+    let user_id = link_identity(&pool, &hasher, &provider, &claims).await?;
+
+    let two_factor_enabled = sqlx::query_scalar::<_, bool>(
+        "SELECT totp_enabled_at IS NOT NULL FROM users WHERE id = $1",
+    )
+    .bind(user_id)
+    .fetch_one(&pool)
+    .await?;
+
+    if two_factor_enabled {
+        return Ok((
+            clear_cookie,
+            Json(LoginResponse::TwoFactorRequired(
+                two_factor::challenge(&pool, &config, user_id).await?,
+            )),
+        ));
+    }
+
+    Ok((
+        clear_cookie,
+        Json(LoginResponse::Tokens(
+            issue_tokens(&pool, &config, &keys, user_id, &client).await?,
+        )),
+    ))
+}
+
+/// Finds the user linked to the external identity. The first login links it
+/// to the account with the same verified email, or creates one, so the
+/// provider must have verified the address.
+async fn link_identity(
+    pool: &PgPool,
+    hasher: &PasswordHasher,
+    provider: &str,
+    claims: &IdTokenClaims,
+) -> Result<Uuid, AppError> {
+    let mut tx = pool.begin().await?;
+
+    let linked = sqlx::query_scalar::<_, Uuid>(
+        "UPDATE user_identities SET last_login_at = NOW(), email = COALESCE($3, email)
+         WHERE provider = $1 AND subject = $2
+         RETURNING user_id",
+    )
+    .bind(provider)
+    .bind(&claims.sub)
+    .bind(&claims.email)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    if let Some(user_id) = linked {
+        tx.commit().await?;
+        return Ok(user_id);
+    }
+
+    let email = claims
+        .email
+        .as_deref()
+        .filter(|_| claims.email_verified)
+        .ok_or_else(|| {
+            AppError::BadRequest(
+                "The identity provider did not share a verified email address".into(),
+            )
+        })?;
+    let email = normalize_email(email)?;
+
+    let existing = sqlx::query_as::<_, (Uuid, bool)>(
+        "SELECT id, email_verified_at IS NOT NULL FROM users WHERE email = $1",
+    )
+    .bind(&email)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let user_id = match existing {
+        // Anyone can register an address they don't own, so an account that
+        // never proved it must not be handed the identity
+        Some((_, false)) => {
+            return Err(AppError::Conflict(
+                "An account with this email exists but has not verified it; \
+                 sign in with its password and verify the email first"
+                    .into(),
+            ))
+        }
+        Some((user_id, true)) => {
+            audit::record(
+                &mut tx,
+                user_id,
+                "identity_linked",
+                json!({ "provider": provider }),
+            )
+            .await?;
+            user_id
+        }
+        // The account gets a random password; a password reset sets a real one
+        None => {
+            create_user(&mut tx, hasher, &email, &tokens::generate_opaque())
+                .await?
+                .id
+        }
+    };
+
+    sqlx::query(
+        "UPDATE users SET email_verified_at = COALESCE(email_verified_at, NOW()) WHERE id = $1",
+    )
+    .bind(user_id)
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        "INSERT INTO user_identities (user_id, provider, subject, email, last_login_at)
+         VALUES ($1, $2, $3, $4, NOW())",
+    )
+    .bind(user_id)
+    .bind(provider)
+    .bind(&claims.sub)
+    .bind(&email)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(user_id)
+}
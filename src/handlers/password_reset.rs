@@ -0,0 +1,117 @@
+use std::sync::Arc;
+
+use axum::{extract::State, http::StatusCode};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    audit,
+    config::Config,
+    errors::AppError,
+    extract::Json,
+    handlers::auth::find_user_by_email,
+    lockout::LoginThrottle,
+    mailer::{Email, Mailer},
+    models::user::{PasswordResetConfirm, PasswordResetRequest},
+    password::{PasswordHasher, PasswordPolicy},
+    revocation::{self, RevocationStore},
+    tokens,
+    validation::ValidatedJson,
+};
+
+const PASSWORD_RESET_TTL_MINUTES: i32 = 60;
+
+/// Emails a password reset link if an account exists for the address. Always
+/// answers `202 Accepted` so the endpoint cannot be used to discover accounts.
+pub async fn request_password_reset(
+    State(pool): State<PgPool>,
+    State(config): State<Arc<Config>>,
+    State(mailer): State<Arc<dyn Mailer>>,
+    Json(body): Json<PasswordResetRequest>,
+) -> Result<StatusCode, AppError> {
+    let Some(user) = find_user_by_email(&pool, &body.email).await? else {
+        return Ok(StatusCode::ACCEPTED);
+    };
+
+    let token = tokens::generate_opaque();
+
+    sqlx::query(
+        "INSERT INTO password_reset_tokens (user_id, token_hash, expires_at)
+         VALUES ($1, $2, NOW() + make_interval(mins => $3))",
+    )
+    .bind(user.id)
+    .bind(tokens::hash_opaque(&token))
+    .bind(PASSWORD_RESET_TTL_MINUTES)
+    .execute(&pool)
+    .await?;
+
+    let sent = mailer
+        .send(Email {
+            to: user.email,
+            subject: "Reset your password".to_string(),
+            body: format!(
+                "Someone asked to reset the password for this account.\n\n\
+                 Choose a new password: {}/reset-password?token={}\n\n\
+                 This link expires in {} minutes and can only be used once. \
+                 If you did not ask for it, you can ignore this email.",
+                config.app_base_url, token, PASSWORD_RESET_TTL_MINUTES
+            ),
+        })
+        .await;
+
+    // Failing loudly here would reveal that the account exists
+    if let Err(e) = sent {
+        tracing::error!("Failed to send password reset email: {}", e);
+    }
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// Sets a new password using a reset token. The token works once, every
+/// session of the account is signed out, and a login lockout is lifted.
+pub async fn confirm_password_reset(
+    State(pool): State<PgPool>,
+    State(revocations): State<Arc<RevocationStore>>,
+    State(throttle): State<Arc<LoginThrottle>>,
+    State(hasher): State<Arc<PasswordHasher>>,
+    State(policy): State<Arc<PasswordPolicy>>,
+    ValidatedJson(body): ValidatedJson<PasswordResetConfirm>,
+) -> Result<StatusCode, AppError> {
+    policy.check("new_password", &body.new_password)?;
+
+    let mut tx = pool.begin().await?;
+
+    let user_id = sqlx::query_scalar::<_, Uuid>(
+        "UPDATE password_reset_tokens SET used_at = NOW()
+         WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW()
+         RETURNING user_id",
+    )
+    .bind(tokens::hash_opaque(&body.token))
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| AppError::BadRequest("Invalid or expired token".into()))?;
+
+    let email = sqlx::query_scalar::<_, String>(
+        "UPDATE users SET password_hash = $1 WHERE id = $2 RETURNING email",
+    )
+    .bind(hasher.hash(&body.new_password).await?)
+    .bind(user_id)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    // Any other links that were sent are no longer needed
+    sqlx::query(
+        "UPDATE password_reset_tokens SET used_at = NOW() WHERE user_id = $1 AND used_at IS NULL",
+    )
+    .bind(user_id)
+    .execute(&mut *tx)
+    .await?;
+
+    revocation::revoke_all_tokens(&mut tx, user_id).await?;
+    audit::record(&mut tx, user_id, "password_reset", serde_json::json!({})).await?;
+    tx.commit().await?;
+    revocations.invalidate(user_id);
+    throttle.unlock(&email).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -1,23 +1,60 @@
-use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    Json,
-};
-use sqlx::PgPool;
+use axum::{extract::State, http::StatusCode};
+use serde_json::json;
+use sqlx::{PgConnection, PgPool};
 use uuid::Uuid;
 
 use crate::{
+    authz::{authorize, authorize_task, member_role, Permission},
     errors::AppError,
+    extract::{Json, Path, Query},
+    handlers::workspaces::personal_workspace_id,
     middleware::auth::AuthUser,
-    models::task::{CreateTaskRequest, Task, UpdateTaskRequest},
+    models::task::{
+        AssignTaskRequest, CreateTaskRequest, Task, TaskHistoryEntry, TaskListQuery,
+        UpdateTaskRequest,
+    },
+    validation::ValidatedJson,
 };
 
+// The task can be deleted between the permission check and the query
+fn task_not_found() -> AppError {
+    AppError::NotFound("Task not found".into())
+}
+
 pub async fn get_tasks(
     State(pool): State<PgPool>,
     AuthUser(user_id): AuthUser,
+    Query(query): Query<TaskListQuery>,
+) -> Result<Json<Vec<Task>>, AppError> {
+    if let Some(workspace_id) = query.workspace_id {
+        authorize(&pool, user_id, workspace_id, Permission::View).await?;
+    }
+
+    let tasks = sqlx::query_as::<_, Task>(
+        "SELECT t.* FROM tasks t
+         JOIN workspace_members m ON m.workspace_id = t.workspace_id AND m.user_id = $1
+         WHERE (t.archived_at IS NOT NULL) = $2
+           AND ($3::uuid IS NULL OR t.workspace_id = $3)
+         ORDER BY t.created_at DESC",
+    )
+    .bind(user_id)
+    .bind(query.archived)
+    .bind(query.workspace_id)
+    .fetch_all(&pool)
+    .await?;
+
+    Ok(Json(tasks))
+}
+
+pub async fn get_assigned_tasks(
+    State(pool): State<PgPool>,
+    AuthUser(user_id): AuthUser,
 ) -> Result<Json<Vec<Task>>, AppError> {
     let tasks = sqlx::query_as::<_, Task>(
-        "SELECT * FROM tasks WHERE user_id = $1 ORDER BY created_at DESC",
+        "SELECT t.* FROM tasks t
+         JOIN workspace_members m ON m.workspace_id = t.workspace_id AND m.user_id = $1
+         WHERE t.assignee_id = $1 AND t.archived_at IS NULL
+         ORDER BY t.created_at DESC",
     )
     .bind(user_id)
     .fetch_all(&pool)
@@ -29,11 +66,20 @@ pub async fn get_tasks(
 pub async fn create_task(
     State(pool): State<PgPool>,
     AuthUser(user_id): AuthUser,
-    Json(body): Json<CreateTaskRequest>,
+    ValidatedJson(body): ValidatedJson<CreateTaskRequest>,
 ) -> Result<(StatusCode, Json<Task>), AppError> {
+    let workspace_id = match body.workspace_id {
+        Some(workspace_id) => workspace_id,
+        None => personal_workspace_id(&pool, user_id).await?,
+    };
+    authorize(&pool, user_id, workspace_id, Permission::EditTasks).await?;
+
     let task = sqlx::query_as::<_, Task>(
-        "INSERT INTO tasks (user_id, title, description) VALUES ($1, $2, $3) RETURNING *",
+        "INSERT INTO tasks (workspace_id, user_id, title, description)
+         VALUES ($1, $2, $3, $4)
+         RETURNING *",
     )
+    .bind(workspace_id)
     .bind(user_id)
     .bind(&body.title)
     .bind(&body.description)
@@ -47,24 +93,65 @@ pub async fn update_task(
     State(pool): State<PgPool>,
     AuthUser(user_id): AuthUser,
     Path(task_id): Path<Uuid>,
-    Json(body): Json<UpdateTaskRequest>,
+    ValidatedJson(body): ValidatedJson<UpdateTaskRequest>,
 ) -> Result<Json<Task>, AppError> {
+    authorize_task(&pool, user_id, task_id, Permission::EditTasks).await?;
+
     let task = sqlx::query_as::<_, Task>(
         "UPDATE tasks SET
             title = COALESCE($1, title),
             description = COALESCE($2, description),
-            done = COALESCE($3, done)
-         WHERE id = $4 AND user_id = $5
+            done = COALESCE($3, done),
+            completed_at = CASE
+                WHEN $3 IS NULL OR $3 = done THEN completed_at
+                WHEN $3 THEN NOW()
+                ELSE NULL
+            END
+         WHERE id = $4
          RETURNING *",
     )
     .bind(&body.title)
     .bind(&body.description)
     .bind(body.done)
     .bind(task_id)
-    .bind(user_id)
     .fetch_optional(&pool)
     .await?
-    .ok_or_else(|| AppError::NotFound("Task not found".into()))?;
+    .ok_or_else(task_not_found)?;
+
+    Ok(Json(task))
+}
+
+pub async fn archive_task(
+    State(pool): State<PgPool>,
+    AuthUser(user_id): AuthUser,
+    Path(task_id): Path<Uuid>,
+) -> Result<Json<Task>, AppError> {
+    authorize_task(&pool, user_id, task_id, Permission::EditTasks).await?;
+
+    let task = sqlx::query_as::<_, Task>(
+        "UPDATE tasks SET archived_at = COALESCE(archived_at, NOW()) WHERE id = $1 RETURNING *",
+    )
+    .bind(task_id)
+    .fetch_optional(&pool)
+    .await?
+    .ok_or_else(task_not_found)?;
+
+    Ok(Json(task))
+}
+
+pub async fn unarchive_task(
+    State(pool): State<PgPool>,
+    AuthUser(user_id): AuthUser,
+    Path(task_id): Path<Uuid>,
+) -> Result<Json<Task>, AppError> {
+    authorize_task(&pool, user_id, task_id, Permission::EditTasks).await?;
+
+    let task =
+        sqlx::query_as::<_, Task>("UPDATE tasks SET archived_at = NULL WHERE id = $1 RETURNING *")
+            .bind(task_id)
+            .fetch_optional(&pool)
+            .await?
+            .ok_or_else(task_not_found)?;
 
     Ok(Json(task))
 }
@@ -74,15 +161,150 @@ pub async fn delete_task(
     AuthUser(user_id): AuthUser,
     Path(task_id): Path<Uuid>,
 ) -> Result<StatusCode, AppError> {
-    let result = sqlx::query("DELETE FROM tasks WHERE id = $1 AND user_id = $2")
+    authorize_task(&pool, user_id, task_id, Permission::EditTasks).await?;
+
+    let result = sqlx::query("DELETE FROM tasks WHERE id = $1")
         .bind(task_id)
-        .bind(user_id)
         .execute(&pool)
         .await?;
-
     if result.rows_affected() == 0 {
-        return Err(AppError::NotFound("Task not found".into()));
+        return Err(task_not_found());
     }
 
     Ok(StatusCode::NO_CONTENT)
 }
+
+async fn record_history(
+    conn: &mut PgConnection,
+    task_id: Uuid,
+    actor_id: Uuid,
+    action: &str,
+    details: serde_json::Value,
+) -> Result<(), AppError> {
+    sqlx::query(
+        "INSERT INTO task_history (task_id, actor_id, action, details) VALUES ($1, $2, $3, $4)",
+    )
+    .bind(task_id)
+    .bind(actor_id)
+    .bind(action)
+    .bind(details)
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Assigns the task to a member of its workspace, replacing any previous assignee.
+pub async fn assign_task(
+    State(pool): State<PgPool>,
+    AuthUser(user_id): AuthUser,
+    Path(task_id): Path<Uuid>,
+    Json(body): Json<AssignTaskRequest>,
+) -> Result<Json<Task>, AppError> {
+    let workspace_id = authorize_task(&pool, user_id, task_id, Permission::EditTasks).await?;
+
+    // Anyone who can see the task may be assigned to it
+    if member_role(&pool, workspace_id, body.user_id)
+        .await?
+        .is_none()
+    {
+        return Err(AppError::BadRequest(
+            "Assignee must be a member of the task's workspace".into(),
+        ));
+    }
+
+    let mut tx = pool.begin().await?;
+
+    let previous = sqlx::query_scalar::<_, Option<Uuid>>(
+        "SELECT assignee_id FROM tasks WHERE id = $1 FOR UPDATE",
+    )
+    .bind(task_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(task_not_found)?;
+
+    let task =
+        sqlx::query_as::<_, Task>("UPDATE tasks SET assignee_id = $1 WHERE id = $2 RETURNING *")
+            .bind(body.user_id)
+            .bind(task_id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or_else(task_not_found)?;
+
+    if previous != Some(body.user_id) {
+        let action = if previous.is_some() {
+            "reassigned"
+        } else {
+            "assigned"
+        };
+        record_history(
+            &mut tx,
+            task_id,
+            user_id,
+            action,
+            json!({ "from": previous, "to": body.user_id }),
+        )
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(Json(task))
+}
+
+pub async fn unassign_task(
+    State(pool): State<PgPool>,
+    AuthUser(user_id): AuthUser,
+    Path(task_id): Path<Uuid>,
+) -> Result<Json<Task>, AppError> {
+    authorize_task(&pool, user_id, task_id, Permission::EditTasks).await?;
+
+    let mut tx = pool.begin().await?;
+
+    let previous = sqlx::query_scalar::<_, Option<Uuid>>(
+        "SELECT assignee_id FROM tasks WHERE id = $1 FOR UPDATE",
+    )
+    .bind(task_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(task_not_found)?;
+
+    let task =
+        sqlx::query_as::<_, Task>("UPDATE tasks SET assignee_id = NULL WHERE id = $1 RETURNING *")
+            .bind(task_id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or_else(task_not_found)?;
+
+    if let Some(previous) = previous {
+        record_history(
+            &mut tx,
+            task_id,
+            user_id,
+            "unassigned",
+            json!({ "from": previous, "to": null }),
+        )
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(Json(task))
+}
+
+pub async fn get_task_history(
+    State(pool): State<PgPool>,
+    AuthUser(user_id): AuthUser,
+    Path(task_id): Path<Uuid>,
+) -> Result<Json<Vec<TaskHistoryEntry>>, AppError> {
+    authorize_task(&pool, user_id, task_id, Permission::View).await?;
+
+    let history = sqlx::query_as::<_, TaskHistoryEntry>(
+        "SELECT * FROM task_history WHERE task_id = $1 ORDER BY created_at",
+    )
+    .bind(task_id)
+    .fetch_all(&pool)
+    .await?;
+
+    Ok(Json(history))
+}
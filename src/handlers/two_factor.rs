@@ -0,0 +1,392 @@
+use std::sync::Arc;
+
+use axum::{extract::State, http::StatusCode};
+use serde_json::json;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    audit,
+    config::Config,
+    errors::AppError,
+    extract::Json,
+    handlers::{
+        account::check_current_password,
+        auth::{issue_tokens, login_failed},
+    },
+    keys::JwtKeys,
+    lockout::LoginThrottle,
+    middleware::{auth::AuthUser, client::ClientInfo},
+    models::{
+        two_factor::{
+            ConfirmTwoFactorRequest, DisableTwoFactorRequest, RecoveryCodes, TwoFactorEnrollment,
+        },
+        user::{AuthResponse, TwoFactorChallenge, TwoFactorLoginRequest},
+    },
+    password::PasswordHasher,
+    tokens,
+    totp::{self, SecretCipher},
+    validation::{lookup_email, ValidatedJson},
+};
+
+const TWO_FACTOR_LOGIN_PURPOSE: &str = "two-factor-login";
+const CHALLENGE_TTL_MINUTES: i64 = 5;
+/// Codes accepted for one login challenge, right or wrong.
+pub const MAX_CHALLENGE_ATTEMPTS: i32 = 5;
+const RECOVERY_CODE_COUNT: usize = 10;
+
+fn now() -> u64 {
+    chrono::Utc::now().timestamp() as u64
+}
+
+/// Generates a recovery code with 80 bits of entropy, e.g. `k3jd-8xqa-2mfp-7vtc`.
+fn generate_recovery_code() -> String {
+    use rand::RngCore;
+
+    let mut bytes = [0u8; 10];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let encoded = data_encoding::BASE32_NOPAD
+        .encode(&bytes)
+        .to_ascii_lowercase();
+
+    encoded
+        .as_bytes()
+        .chunks(4)
+        .map(|chunk| std::str::from_utf8(chunk).expect("base32 is ASCII"))
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Recovery codes are accepted with or without dashes and in any case.
+fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| *c != '-' && !c.is_whitespace())
+        .collect::<String>()
+        .to_ascii_lowercase();
+
+    tokens::hash_opaque(&normalized)
+}
+
+/// Starts the second step of a login for an account with two-factor
+/// authentication enabled. The challenge can be used once, and is refused
+/// after [`MAX_CHALLENGE_ATTEMPTS`] codes.
+pub async fn challenge(
+    pool: &PgPool,
+    config: &Config,
+    user_id: Uuid,
+) -> Result<TwoFactorChallenge, AppError> {
+    let ttl = chrono::Duration::minutes(CHALLENGE_TTL_MINUTES);
+
+    sqlx::query("DELETE FROM two_factor_challenges WHERE user_id = $1 AND expires_at <= NOW()")
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    let challenge_id = sqlx::query_scalar::<_, Uuid>(
+        "INSERT INTO two_factor_challenges (user_id, expires_at)
+         VALUES ($1, NOW() + make_interval(mins => $2))
+         RETURNING id",
+    )
+    .bind(user_id)
+    .bind(CHALLENGE_TTL_MINUTES as i32)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(TwoFactorChallenge {
+        two_factor_required: true,
+        challenge_token: tokens::sign(
+            &config.jwt_secret,
+            TWO_FACTOR_LOGIN_PURPOSE,
+            &challenge_id.to_string(),
+            ttl,
+        )?,
+        expires_in: ttl.num_seconds(),
+    })
+}
+
+/// Checks an authenticator or recovery code for a user with two-factor
+/// authentication enabled. Accepted authenticator codes cannot be used again,
+/// and recovery codes are used up.
+pub async fn check_second_factor(
+    pool: &PgPool,
+    cipher: &SecretCipher,
+    user_id: Uuid,
+    code: &str,
+) -> Result<bool, AppError> {
+    let stored = sqlx::query_scalar::<_, String>(
+        "SELECT totp_secret FROM users WHERE id = $1 AND totp_enabled_at IS NOT NULL",
+    )
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    let Some(stored) = stored else {
+        return Ok(false);
+    };
+    let secret = cipher.open(user_id, &stored)?;
+
+    if let Some(step) = totp::verify(&secret, code, now(), totp::ALLOWED_DRIFT) {
+        // Secrets stored before they were encrypted are encrypted on first use
+        let stored = match totp::is_sealed(&stored) {
+            true => stored,
+            false => cipher.seal(user_id, &secret)?,
+        };
+        let accepted = sqlx::query(
+            "UPDATE users SET totp_last_step = $2, totp_secret = $3
+             WHERE id = $1 AND (totp_last_step IS NULL OR totp_last_step < $2)",
+        )
+        .bind(user_id)
+        .bind(step as i64)
+        .bind(&stored)
+        .execute(pool)
+        .await?
+        .rows_affected();
+
+        return Ok(accepted == 1);
+    }
+
+    let mut tx = pool.begin().await?;
+
+    let used = sqlx::query(
+        "UPDATE recovery_codes SET used_at = NOW()
+         WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL",
+    )
+    .bind(user_id)
+    .bind(hash_recovery_code(code))
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+
+    if used == 0 {
+        return Ok(false);
+    }
+
+    audit::record(&mut tx, user_id, "recovery_code_used", json!({})).await?;
+    tx.commit().await?;
+
+    Ok(true)
+}
+
+/// Second step of a two-factor login: exchanges the challenge from
+/// `POST /auth/login` and a code for tokens. Each code uses up one of the
+/// challenge's attempts, and a correct code uses up the challenge. Wrong codes
+/// count towards the account's login lockout like wrong passwords.
+pub async fn login_two_factor(
+    State(pool): State<PgPool>,
+    State(config): State<Arc<Config>>,
+    State(keys): State<Arc<JwtKeys>>,
+    State(throttle): State<Arc<LoginThrottle>>,
+    State(cipher): State<Arc<SecretCipher>>,
+    client: ClientInfo,
+    Json(body): Json<TwoFactorLoginRequest>,
+) -> Result<Json<AuthResponse>, AppError> {
+    let expired = || AppError::Auth("Login challenge expired, log in again".into());
+
+    let challenge_id = tokens::verify(
+        &config.jwt_secret,
+        &body.challenge_token,
+        TWO_FACTOR_LOGIN_PURPOSE,
+    )?;
+    let challenge_id = Uuid::parse_str(&challenge_id)
+        .map_err(|_| AppError::BadRequest("Invalid or expired token".into()))?;
+
+    let email = sqlx::query_scalar::<_, String>(
+        "SELECT users.email FROM two_factor_challenges
+         JOIN users ON users.id = two_factor_challenges.user_id
+         WHERE two_factor_challenges.id = $1 AND two_factor_challenges.expires_at > NOW()",
+    )
+    .bind(challenge_id)
+    .fetch_optional(&pool)
+    .await?
+    .ok_or_else(expired)?;
+
+    let ip_address = client.ip_address.as_deref();
+    let attempt = throttle.begin(&lookup_email(&email), ip_address).await?;
+
+    // Taking the attempt before checking the code keeps parallel guesses
+    // within the limit
+    let user_id = sqlx::query_scalar::<_, Uuid>(
+        "UPDATE two_factor_challenges SET attempts = attempts + 1
+         WHERE id = $1 AND expires_at > NOW() AND attempts < $2
+         RETURNING user_id",
+    )
+    .bind(challenge_id)
+    .bind(MAX_CHALLENGE_ATTEMPTS)
+    .fetch_optional(&pool)
+    .await?;
+    let Some(user_id) = user_id else {
+        throttle.forgive(attempt).await?;
+        return Err(expired());
+    };
+
+    if !check_second_factor(&pool, &cipher, user_id, &body.code).await? {
+        login_failed(&pool, &throttle, attempt, Some(user_id), ip_address).await?;
+        return Err(AppError::Auth("Invalid authentication code".into()));
+    }
+
+    let used = sqlx::query("DELETE FROM two_factor_challenges WHERE id = $1")
+        .bind(challenge_id)
+        .execute(&pool)
+        .await?
+        .rows_affected();
+    if used == 0 {
+        throttle.forgive(attempt).await?;
+        return Err(expired());
+    }
+
+    throttle.succeeded(attempt).await?;
+
+    Ok(Json(
+        issue_tokens(&pool, &config, &keys, user_id, &client).await?,
+    ))
+}
+
+/// Starts enrolment with a new secret. Two-factor authentication is only
+/// enabled once a code from it is confirmed; enrolling again replaces a
+/// pending secret.
+pub async fn enroll_two_factor(
+    State(pool): State<PgPool>,
+    State(cipher): State<Arc<SecretCipher>>,
+    AuthUser(user_id): AuthUser,
+) -> Result<Json<TwoFactorEnrollment>, AppError> {
+    let secret = totp::generate_secret();
+
+    let email = sqlx::query_scalar::<_, String>(
+        "UPDATE users SET totp_secret = $2, totp_last_step = NULL
+         WHERE id = $1 AND totp_enabled_at IS NULL
+         RETURNING email",
+    )
+    .bind(user_id)
+    .bind(cipher.seal(user_id, &secret)?)
+    .fetch_optional(&pool)
+    .await?
+    .ok_or_else(|| AppError::Conflict("Two-factor authentication is already enabled".into()))?;
+
+    Ok(Json(TwoFactorEnrollment {
+        otpauth_uri: totp::otpauth_uri(&secret, &email),
+        secret,
+    }))
+}
+
+/// Enables two-factor authentication once the user proves their
+/// authenticator works, and returns a fresh set of recovery codes.
+pub async fn confirm_two_factor(
+    State(pool): State<PgPool>,
+    State(cipher): State<Arc<SecretCipher>>,
+    AuthUser(user_id): AuthUser,
+    Json(body): Json<ConfirmTwoFactorRequest>,
+) -> Result<Json<RecoveryCodes>, AppError> {
+    let stored = sqlx::query_scalar::<_, Option<String>>(
+        "SELECT totp_secret FROM users WHERE id = $1 AND totp_enabled_at IS NULL",
+    )
+    .bind(user_id)
+    .fetch_optional(&pool)
+    .await?
+    .flatten()
+    .ok_or_else(|| AppError::BadRequest("No two-factor enrolment is pending".into()))?;
+    let secret = cipher.open(user_id, &stored)?;
+
+    let step = totp::verify(&secret, &body.code, now(), totp::ALLOWED_DRIFT)
+        .ok_or_else(|| AppError::BadRequest("Invalid authentication code".into()))?;
+
+    let mut tx = pool.begin().await?;
+
+    // Matching on the stored secret keeps a concurrent re-enrolment from
+    // being confirmed. A pending secret from before secrets were encrypted is
+    // encrypted here.
+    let enabled = sqlx::query(
+        "UPDATE users SET totp_enabled_at = NOW(), totp_last_step = $3, totp_secret = $4
+         WHERE id = $1 AND totp_secret = $2 AND totp_enabled_at IS NULL",
+    )
+    .bind(user_id)
+    .bind(&stored)
+    .bind(step as i64)
+    .bind(match totp::is_sealed(&stored) {
+        true => stored.clone(),
+        false => cipher.seal(user_id, &secret)?,
+    })
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+
+    if enabled == 0 {
+        return Err(AppError::BadRequest(
+            "No two-factor enrolment is pending".into(),
+        ));
+    }
+
+    sqlx::query("DELETE FROM recovery_codes WHERE user_id = $1")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    let recovery_codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+        .map(|_| generate_recovery_code())
+        .collect();
+    let hashes: Vec<String> = recovery_codes
+        .iter()
+        .map(|code| hash_recovery_code(code))
+        .collect();
+
+    sqlx::query("INSERT INTO recovery_codes (user_id, code_hash) SELECT $1, UNNEST($2::text[])")
+        .bind(user_id)
+        .bind(&hashes)
+        .execute(&mut *tx)
+        .await?;
+
+    audit::record(&mut tx, user_id, "two_factor_enabled", json!({})).await?;
+
+    tx.commit().await?;
+
+    Ok(Json(RecoveryCodes { recovery_codes }))
+}
+
+/// Turns two-factor authentication off. Needs the password and a current
+/// authenticator or recovery code.
+pub async fn disable_two_factor(
+    State(pool): State<PgPool>,
+    State(hasher): State<Arc<PasswordHasher>>,
+    State(cipher): State<Arc<SecretCipher>>,
+    AuthUser(user_id): AuthUser,
+    ValidatedJson(body): ValidatedJson<DisableTwoFactorRequest>,
+) -> Result<StatusCode, AppError> {
+    check_current_password(&pool, &hasher, user_id, &body.password).await?;
+
+    let enabled = sqlx::query_scalar::<_, bool>(
+        "SELECT totp_enabled_at IS NOT NULL FROM users WHERE id = $1",
+    )
+    .bind(user_id)
+    .fetch_one(&pool)
+    .await?;
+
+    if !enabled {
+        return Err(AppError::BadRequest(
+            "Two-factor authentication is not enabled".into(),
+        ));
+    }
+
+    if !check_second_factor(&pool, &cipher, user_id, &body.code).await? {
+        return Err(AppError::BadRequest("Invalid authentication code".into()));
+    }
+
+    let mut tx = pool.begin().await?;
+
+    sqlx::query(
+        "UPDATE users SET totp_secret = NULL, totp_enabled_at = NULL, totp_last_step = NULL
+         WHERE id = $1",
+    )
+    .bind(user_id)
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query("DELETE FROM recovery_codes WHERE user_id = $1")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    audit::record(&mut tx, user_id, "two_factor_disabled", json!({})).await?;
+
+    tx.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,122 @@
+use std::sync::Arc;
+
+use axum::{extract::State, http::StatusCode};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    config::Config,
+    errors::AppError,
+    extract::Json,
+    mailer::{Email, Mailer},
+    middleware::auth::AuthUser,
+    models::user::VerifyEmailRequest,
+    tokens,
+};
+
+const EMAIL_VERIFICATION_PURPOSE: &str = "email-verification";
+const EMAIL_VERIFICATION_TTL_HOURS: i64 = 24;
+/// Minimum time between two verification emails to the same account.
+const RESEND_INTERVAL_SECS: i32 = 60;
+
+/// Emails a signed verification link for the user's current address. The
+/// token names both the user and the address, so it stops working if the
+/// email changes before it is used.
+pub async fn send_verification_email(
+    config: &Config,
+    mailer: &dyn Mailer,
+    user_id: Uuid,
+    email: &str,
+) -> Result<(), AppError> {
+    let token = tokens::sign(
+        &config.jwt_secret,
+        EMAIL_VERIFICATION_PURPOSE,
+        &format!("{}:{}", user_id, email),
+        chrono::Duration::hours(EMAIL_VERIFICATION_TTL_HOURS),
+    )?;
+    mailer
+        .send(Email {
+            to: email.to_string(),
+            subject: "Verify your email address".to_string(),
+            body: format!(
+                "Confirm that this is your email address: {}/verify-email?token={}\n\n\
+                 This link expires in {} hours.",
+                config.app_base_url, token, EMAIL_VERIFICATION_TTL_HOURS
+            ),
+        })
+        .await?;
+
+    Ok(())
+}
+
+/// Marks the address named by a verification token as verified. Verifying
+/// twice is harmless.
+pub async fn verify_email(
+    State(pool): State<PgPool>,
+    State(config): State<Arc<Config>>,
+    Json(body): Json<VerifyEmailRequest>,
+) -> Result<StatusCode, AppError> {
+    let subject = tokens::verify(&config.jwt_secret, &body.token, EMAIL_VERIFICATION_PURPOSE)?;
+    let (user_id, email) = subject
+        .split_once(':')
+        .and_then(|(id, email)| Some((Uuid::parse_str(id).ok()?, email)))
+        .ok_or_else(|| AppError::BadRequest("Invalid or expired token".into()))?;
+
+    let updated = sqlx::query(
+        "UPDATE users SET email_verified_at = COALESCE(email_verified_at, NOW())
+         WHERE id = $1 AND email = $2",
+    )
+    .bind(user_id)
+    .bind(email)
+    .execute(&pool)
+    .await?;
+
+    // The account is gone or its address changed since the link was sent
+    if updated.rows_affected() == 0 {
+        return Err(AppError::BadRequest("Invalid or expired token".into()));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Sends a new verification email, at most once every `RESEND_INTERVAL_SECS`.
+pub async fn resend_verification(
+    State(pool): State<PgPool>,
+    State(config): State<Arc<Config>>,
+    State(mailer): State<Arc<dyn Mailer>>,
+    AuthUser(user_id): AuthUser,
+) -> Result<StatusCode, AppError> {
+    let email = sqlx::query_scalar::<_, String>(
+        "UPDATE users SET verification_sent_at = NOW()
+         WHERE id = $1 AND email_verified_at IS NULL
+           AND (verification_sent_at IS NULL
+                OR verification_sent_at < NOW() - make_interval(secs => $2))
+         RETURNING email",
+    )
+    .bind(user_id)
+    .bind(RESEND_INTERVAL_SECS as f64)
+    .fetch_optional(&pool)
+    .await?;
+
+    let Some(email) = email else {
+        let verified = sqlx::query_scalar::<_, bool>(
+            "SELECT email_verified_at IS NOT NULL FROM users WHERE id = $1",
+        )
+        .bind(user_id)
+        .fetch_one(&pool)
+        .await?;
+
+        if verified {
+            return Err(AppError::BadRequest(
+                "Email address is already verified".into(),
+            ));
+        }
+        return Err(AppError::TooManyRequests(
+            "A verification email was sent recently; try again later".into(),
+        ));
+    };
+
+    send_verification_email(&config, mailer.as_ref(), user_id, &email).await?;
+
+    Ok(StatusCode::ACCEPTED)
+}
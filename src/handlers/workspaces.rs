@@ -0,0 +1,337 @@
+use std::sync::Arc;
+
+use axum::{extract::State, http::StatusCode};
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+use crate::{
+    authz::{self, Permission, VerifiedAction},
+    config::Config,
+    errors::AppError,
+    extract::{Json, Path},
+    middleware::{auth::AuthUser, workspace::WorkspaceAccess},
+    models::workspace::{
+        AddMemberRequest, CreateWorkspaceRequest, Role, UpdateMemberRequest,
+        UpdateWorkspaceRequest, Workspace, WorkspaceMember,
+    },
+    validation::normalize_email,
+};
+
+/// Creates the personal workspace every user owns and adds them as its only member.
+pub async fn create_personal_workspace(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+) -> Result<Workspace, AppError> {
+    let workspace = sqlx::query_as::<_, Workspace>(
+        "INSERT INTO workspaces (name, created_by, is_personal)
+         VALUES ('Personal', $1, TRUE)
+         RETURNING *",
+    )
+    .bind(user_id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    sqlx::query(
+        "INSERT INTO workspace_members (workspace_id, user_id, role) VALUES ($1, $2, 'owner')",
+    )
+    .bind(workspace.id)
+    .bind(user_id)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(workspace)
+}
+
+pub async fn personal_workspace_id(pool: &PgPool, user_id: Uuid) -> Result<Uuid, AppError> {
+    sqlx::query_scalar::<_, Uuid>("SELECT id FROM workspaces WHERE created_by = $1 AND is_personal")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Personal workspace not found".into()))
+}
+
+pub async fn ensure_shared(pool: &PgPool, workspace_id: Uuid) -> Result<(), AppError> {
+    let is_personal =
+        sqlx::query_scalar::<_, bool>("SELECT is_personal FROM workspaces WHERE id = $1")
+            .bind(workspace_id)
+            .fetch_one(pool)
+            .await?;
+
+    if is_personal {
+        return Err(AppError::BadRequest(
+            "This action is not available for personal workspaces".into(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Rejects changes that would leave the workspace without an owner. Locks the
+/// owners' rows until `conn`'s transaction ends, so owners demoting or
+/// removing each other at the same time cannot both pass the check.
+async fn ensure_other_owner(conn: &mut PgConnection, workspace_id: Uuid) -> Result<(), AppError> {
+    let owners = sqlx::query_scalar::<_, Uuid>(
+        "SELECT user_id FROM workspace_members
+         WHERE workspace_id = $1 AND role = 'owner'
+         FOR UPDATE",
+    )
+    .bind(workspace_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    if owners.len() <= 1 {
+        return Err(AppError::BadRequest(
+            "A workspace must keep at least one owner".into(),
+        ));
+    }
+
+    Ok(())
+}
+
+pub async fn get_workspaces(
+    State(pool): State<PgPool>,
+    AuthUser(user_id): AuthUser,
+) -> Result<Json<Vec<Workspace>>, AppError> {
+    let workspaces = sqlx::query_as::<_, Workspace>(
+        "SELECT w.* FROM workspaces w
+         JOIN workspace_members m ON m.workspace_id = w.id
+         WHERE m.user_id = $1
+         ORDER BY w.is_personal DESC, w.created_at",
+    )
+    .bind(user_id)
+    .fetch_all(&pool)
+    .await?;
+
+    Ok(Json(workspaces))
+}
+
+pub async fn create_workspace(
+    State(pool): State<PgPool>,
+    State(config): State<Arc<Config>>,
+    AuthUser(user_id): AuthUser,
+    Json(body): Json<CreateWorkspaceRequest>,
+) -> Result<(StatusCode, Json<Workspace>), AppError> {
+    authz::require_verified_email(&pool, &config, user_id, VerifiedAction::CreateWorkspace).await?;
+
+    let mut tx = pool.begin().await?;
+
+    let workspace = sqlx::query_as::<_, Workspace>(
+        "INSERT INTO workspaces (name, created_by) VALUES ($1, $2) RETURNING *",
+    )
+    .bind(&body.name)
+    .bind(user_id)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        "INSERT INTO workspace_members (workspace_id, user_id, role) VALUES ($1, $2, 'owner')",
+    )
+    .bind(workspace.id)
+    .bind(user_id)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok((StatusCode::CREATED, Json(workspace)))
+}
+
+pub async fn update_workspace(
+    State(pool): State<PgPool>,
+    access: WorkspaceAccess,
+    Json(body): Json<UpdateWorkspaceRequest>,
+) -> Result<Json<Workspace>, AppError> {
+    access.require(Permission::ManageWorkspace)?;
+
+    let workspace =
+        sqlx::query_as::<_, Workspace>("UPDATE workspaces SET name = $1 WHERE id = $2 RETURNING *")
+            .bind(&body.name)
+            .bind(access.workspace_id)
+            .fetch_optional(&pool)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Workspace not found".into()))?;
+
+    Ok(Json(workspace))
+}
+
+pub async fn delete_workspace(
+    State(pool): State<PgPool>,
+    access: WorkspaceAccess,
+) -> Result<StatusCode, AppError> {
+    access.require(Permission::DeleteWorkspace)?;
+    ensure_shared(&pool, access.workspace_id).await?;
+
+    sqlx::query("DELETE FROM workspaces WHERE id = $1")
+        .bind(access.workspace_id)
+        .execute(&pool)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn get_members(
+    State(pool): State<PgPool>,
+    access: WorkspaceAccess,
+) -> Result<Json<Vec<WorkspaceMember>>, AppError> {
+    access.require(Permission::View)?;
+
+    let members = sqlx::query_as::<_, WorkspaceMember>(
+        "SELECT m.user_id, u.email, m.role, m.joined_at FROM workspace_members m
+         JOIN users u ON u.id = m.user_id
+         WHERE m.workspace_id = $1
+         ORDER BY m.joined_at",
+    )
+    .bind(access.workspace_id)
+    .fetch_all(&pool)
+    .await?;
+
+    Ok(Json(members))
+}
+
+pub async fn add_member(
+    State(pool): State<PgPool>,
+    State(config): State<Arc<Config>>,
+    access: WorkspaceAccess,
+    Json(body): Json<AddMemberRequest>,
+) -> Result<(StatusCode, Json<WorkspaceMember>), AppError> {
+    access.require(Permission::ManageMembers)?;
+    ensure_shared(&pool, access.workspace_id).await?;
+    authz::require_verified_email(
+        &pool,
+        &config,
+        access.user_id,
+        VerifiedAction::InviteMembers,
+    )
+    .await?;
+
+    let email = normalize_email(&body.email)?;
+    let role = body.role.unwrap_or(Role::Editor);
+    if role == Role::Owner && access.role != Role::Owner {
+        return Err(AppError::Forbidden("Only owners can add owners".into()));
+    }
+
+    let member = sqlx::query_as::<_, WorkspaceMember>(
+        "INSERT INTO workspace_members (workspace_id, user_id, role)
+         SELECT $1, id, $3 FROM users WHERE email = $2
+         ON CONFLICT (workspace_id, user_id) DO NOTHING
+         RETURNING user_id, $2 AS email, role, joined_at",
+    )
+    .bind(access.workspace_id)
+    .bind(&email)
+    .bind(role)
+    .fetch_optional(&pool)
+    .await?;
+
+    match member {
+        Some(member) => Ok((StatusCode::CREATED, Json(member))),
+        None => {
+            let exists = sqlx::query_scalar::<_, bool>(
+                "SELECT EXISTS (SELECT 1 FROM users WHERE email = $1)",
+            )
+            .bind(&email)
+            .fetch_one(&pool)
+            .await?;
+
+            if exists {
+                Err(AppError::Conflict("User is already a member".into()))
+            } else {
+                Err(AppError::NotFound("User not found".into()))
+            }
+        }
+    }
+}
+
+pub async fn update_member(
+    State(pool): State<PgPool>,
+    access: WorkspaceAccess,
+    Path((_, member_id)): Path<(Uuid, Uuid)>,
+    Json(body): Json<UpdateMemberRequest>,
+) -> Result<Json<WorkspaceMember>, AppError> {
+    access.require(Permission::ManageMembers)?;
+
+    let current = authz::member_role(&pool, access.workspace_id, member_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Member not found".into()))?;
+
+    if (current == Role::Owner || body.role == Role::Owner) && access.role != Role::Owner {
+        return Err(AppError::Forbidden(
+            "Only owners can grant or revoke the owner role".into(),
+        ));
+    }
+
+    let mut tx = pool.begin().await?;
+
+    if current == Role::Owner && body.role != Role::Owner {
+        ensure_other_owner(&mut tx, access.workspace_id).await?;
+    }
+
+    let member = sqlx::query_as::<_, WorkspaceMember>(
+        "UPDATE workspace_members m SET role = $3
+         FROM users u
+         WHERE u.id = m.user_id AND m.workspace_id = $1 AND m.user_id = $2
+         RETURNING m.user_id, u.email, m.role, m.joined_at",
+    )
+    .bind(access.workspace_id)
+    .bind(member_id)
+    .bind(body.role)
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| AppError::NotFound("Member not found".into()))?;
+
+    tx.commit().await?;
+
+    Ok(Json(member))
+}
+
+/// Removes a member. Any member may remove themselves to leave the workspace.
+pub async fn remove_member(
+    State(pool): State<PgPool>,
+    access: WorkspaceAccess,
+    Path((_, member_id)): Path<(Uuid, Uuid)>,
+) -> Result<StatusCode, AppError> {
+    if member_id != access.user_id {
+        access.require(Permission::ManageMembers)?;
+    }
+    ensure_shared(&pool, access.workspace_id).await?;
+
+    let current = authz::member_role(&pool, access.workspace_id, member_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Member not found".into()))?;
+
+    if current == Role::Owner && access.role != Role::Owner {
+        return Err(AppError::Forbidden("Only owners can remove owners".into()));
+    }
+
+    let mut tx = pool.begin().await?;
+
+    if current == Role::Owner {
+        ensure_other_owner(&mut tx, access.workspace_id).await?;
+    }
+
+    sqlx::query("DELETE FROM workspace_members WHERE workspace_id = $1 AND user_id = $2")
+        .bind(access.workspace_id)
+        .bind(member_id)
+        .execute(&mut *tx)
+        .await?;
+
+    // Former members can no longer see the workspace's tasks, so they cannot stay assigned
+    sqlx::query(
+        "WITH unassigned AS (
+            UPDATE tasks SET assignee_id = NULL
+            WHERE workspace_id = $1 AND assignee_id = $2
+            RETURNING id
+         )
+         INSERT INTO task_history (task_id, actor_id, action, details)
+         SELECT id, $3, 'unassigned', jsonb_build_object('from', $2, 'to', NULL)
+         FROM unassigned",
+    )
+    .bind(access.workspace_id)
+    .bind(member_id)
+    .bind(access.user_id)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
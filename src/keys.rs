@@ -0,0 +1,223 @@
+//! Keys for signing and verifying access tokens.
+//!
+//! Tokens are signed with RS256 or EdDSA keys loaded from PEM files, so other
+//! services can verify them with the public keys published at
+//! `/.well-known/jwks.json`. Without key files, tokens fall back to HS256
+//! with `JWT_SECRET`.
+
+use std::path::Path;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{
+    decode, decode_header, encode,
+    jwk::{
+        AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm,
+        OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+    },
+    Algorithm, DecodingKey, EncodingKey, Header, Validation,
+};
+use ring::signature::KeyPair as _;
+use serde::{de::DeserializeOwned, Serialize};
+use thiserror::Error;
+
+use crate::{config::Config, errors::AppError};
+
+#[derive(Error, Debug)]
+pub enum KeyError {
+    #[error("Failed to read key file: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Invalid key {0}: {1}")]
+    Invalid(String, String),
+}
+
+struct Key {
+    kid: Option<String>,
+    algorithm: Algorithm,
+    encoding: EncodingKey,
+    decoding: DecodingKey,
+    /// The public key for the JWKS; `None` for HMAC secrets.
+    jwk: Option<Jwk>,
+}
+
+impl Key {
+    fn hmac(secret: &[u8]) -> Self {
+        Self {
+            kid: None,
+            algorithm: Algorithm::HS256,
+            encoding: EncodingKey::from_secret(secret),
+            decoding: DecodingKey::from_secret(secret),
+            jwk: None,
+        }
+    }
+
+    /// Loads a PKCS#8 RSA or Ed25519 private key, or a PKCS#1 RSA key.
+    fn from_pem(kid: &str, pem: &str) -> Result<Self, KeyError> {
+        let invalid = |reason: &str| KeyError::Invalid(kid.to_string(), reason.to_string());
+
+        let parsed = pem::parse(pem).map_err(|e| invalid(&e.to_string()))?;
+        let der = parsed.contents();
+
+        let rsa = match parsed.tag() {
+            "RSA PRIVATE KEY" => Some(ring::rsa::KeyPair::from_der(der)),
+            "PRIVATE KEY" => match ring::rsa::KeyPair::from_pkcs8(der) {
+                Ok(key_pair) => Some(Ok(key_pair)),
+                Err(_) => None,
+            },
+            tag => return Err(invalid(&format!("unsupported PEM block {}", tag))),
+        };
+
+        let (algorithm, encoding, params) = match rsa {
+            Some(key_pair) => {
+                let key_pair = key_pair.map_err(|e| invalid(&e.to_string()))?;
+                let public = ring::rsa::PublicKeyComponents::<Vec<u8>>::from(key_pair.public());
+                let params = AlgorithmParameters::RSA(RSAKeyParameters {
+                    key_type: RSAKeyType::RSA,
+                    n: URL_SAFE_NO_PAD.encode(public.n),
+                    e: URL_SAFE_NO_PAD.encode(public.e),
+                });
+                let encoding = EncodingKey::from_rsa_pem(pem.as_bytes())
+                    .map_err(|e| invalid(&e.to_string()))?;
+                (Algorithm::RS256, encoding, params)
+            }
+            None => {
+                let key_pair = ring::signature::Ed25519KeyPair::from_pkcs8_maybe_unchecked(der)
+                    .map_err(|_| invalid("not an RSA or Ed25519 private key"))?;
+                let params = AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                    key_type: OctetKeyPairType::OctetKeyPair,
+                    curve: EllipticCurve::Ed25519,
+                    x: URL_SAFE_NO_PAD.encode(key_pair.public_key()),
+                });
+                (Algorithm::EdDSA, EncodingKey::from_ed_der(der), params)
+            }
+        };
+
+        let jwk = Jwk {
+            common: CommonParameters {
+                public_key_use: Some(PublicKeyUse::Signature),
+                key_algorithm: Some(match algorithm {
+                    Algorithm::RS256 => KeyAlgorithm::RS256,
+                    _ => KeyAlgorithm::EdDSA,
+                }),
+                key_id: Some(kid.to_string()),
+                ..Default::default()
+            },
+            algorithm: params,
+        };
+        let decoding = DecodingKey::from_jwk(&jwk).map_err(|e| invalid(&e.to_string()))?;
+
+        Ok(Self {
+            kid: Some(kid.to_string()),
+            algorithm,
+            encoding,
+            decoding,
+            jwk: Some(jwk),
+        })
+    }
+}
+
+/// The access token keys. The first key signs new tokens; the others are
+/// still accepted, so tokens signed before a rotation stay valid until they
+/// expire.
+pub struct JwtKeys {
+    keys: Vec<Key>,
+}
+
+impl JwtKeys {
+    pub fn hmac(secret: &str) -> Self {
+        Self {
+            keys: vec![Key::hmac(secret.as_bytes())],
+        }
+    }
+
+    /// Builds the key set from `(kid, PEM)` pairs, signing key first. Each
+    /// `kid` must be unique, or tokens could be checked with the wrong key.
+    pub fn from_pems(pems: &[(&str, &str)]) -> Result<Self, KeyError> {
+        if pems.is_empty() {
+            return Err(KeyError::Invalid(
+                "set".to_string(),
+                "at least one key is required".to_string(),
+            ));
+        }
+        for (i, (kid, _)) in pems.iter().enumerate() {
+            if pems[..i].iter().any(|(earlier, _)| earlier == kid) {
+                return Err(KeyError::Invalid(
+                    kid.to_string(),
+                    "another key has the same kid".to_string(),
+                ));
+            }
+        }
+
+        let keys = pems
+            .iter()
+            .map(|(kid, pem)| Key::from_pem(kid, pem))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { keys })
+    }
+
+    /// Loads private key files, signing key first. Each key's `kid` is its
+    /// file name without the extension, e.g. `2026-06.pem` is `2026-06`.
+    pub fn from_files<P: AsRef<Path>>(paths: &[P]) -> Result<Self, KeyError> {
+        let mut pems = Vec::new();
+        for path in paths {
+            let path = path.as_ref();
+            let kid = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .ok_or_else(|| {
+                    KeyError::Invalid(path.display().to_string(), "no file name".to_string())
+                })?
+                .to_string();
+            pems.push((kid, std::fs::read_to_string(path)?));
+        }
+
+        let pems: Vec<(&str, &str)> = pems
+            .iter()
+            .map(|(kid, pem)| (kid.as_str(), pem.as_str()))
+            .collect();
+        Self::from_pems(&pems)
+    }
+
+    /// The configured key files, or HS256 with the JWT secret when there
+    /// are none.
+    pub fn from_config(config: &Config) -> Result<Self, KeyError> {
+        if config.jwt_keys.is_empty() {
+            return Ok(Self::hmac(&config.jwt_secret));
+        }
+        Self::from_files(&config.jwt_keys)
+    }
+
+    pub fn sign<T: Serialize>(&self, claims: &T) -> Result<String, AppError> {
+        let key = &self.keys[0];
+        let mut header = Header::new(key.algorithm);
+        header.kid = key.kid.clone();
+
+        encode(&header, claims, &key.encoding)
+            .map_err(|e| AppError::Internal(format!("Failed to sign access token: {}", e)))
+    }
+
+    /// Verifies the token with the key its `kid` names, checking the
+    /// algorithm matches the key, and returns its claims.
+    pub fn verify<T: DeserializeOwned>(&self, token: &str) -> Result<T, AppError> {
+        let invalid = || AppError::Auth("Invalid or expired token".into());
+
+        let header = decode_header(token).map_err(|_| invalid())?;
+        let key = self
+            .keys
+            .iter()
+            .find(|key| key.kid == header.kid)
+            .ok_or_else(invalid)?;
+
+        decode::<T>(token, &key.decoding, &Validation::new(key.algorithm))
+            .map(|data| data.claims)
+            .map_err(|_| invalid())
+    }
+
+    /// The public keys, for `/.well-known/jwks.json`. Empty with HMAC.
+    pub fn jwks(&self) -> JwkSet {
+        JwkSet {
+            keys: self.keys.iter().filter_map(|key| key.jwk.clone()).collect(),
+        }
+    }
+}
@@ -1,6 +1,26 @@
 // This file makes the modules available as a library for testing
+pub mod admin;
+pub mod audit;
+pub mod authz;
+pub mod cli;
+pub mod config;
 pub mod db;
 pub mod errors;
+pub mod extract;
 pub mod handlers;
+pub mod keys;
+pub mod lockout;
+pub mod mailer;
 pub mod middleware;
 pub mod models;
+pub mod oidc;
+pub mod password;
+pub mod revocation;
+pub mod routes;
+pub mod scheduler;
+pub mod state;
+pub mod tokens;
+pub mod totp;
+pub mod validation;
+
+pub use routes::app;
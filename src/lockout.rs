@@ -0,0 +1,440 @@
+//! Brute-force protection for password login.
+//!
+//! Failed attempts are counted per account (by email address, so unknown
+//! addresses behave like real ones) and per client IP. Once a count reaches
+//! its threshold, further attempts are refused for a lockout that doubles with
+//! every further failure, up to a maximum. Each attempt is counted as failed
+//! before the password is checked, and taken back if it turns out valid, so
+//! parallel guesses cannot get past the threshold.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+use crate::{config::Config, errors::AppError, validation::lookup_email};
+
+/// Failed attempts recorded for one key since its count was last reset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Attempts {
+    pub failures: u32,
+    pub last_failure_at: DateTime<Utc>,
+}
+
+/// What [`AttemptStore::record_failure`] did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recorded {
+    /// The failure was counted.
+    Counted(Attempts),
+    /// The key is locked out for this long, so nothing was counted.
+    LockedOut(Duration),
+}
+
+/// Tells whether a key with these failures is locked out, and for how long.
+pub type LockedOut<'a> = &'a (dyn Fn(&Attempts) -> Option<Duration> + Send + Sync);
+
+/// Where failed attempts are counted. Use [`PgAttemptStore`] when several
+/// instances serve the API, so they share counts.
+#[async_trait]
+pub trait AttemptStore: Send + Sync {
+    async fn get(&self, key: &str) -> Result<Option<Attempts>, AppError>;
+
+    /// Counts a failure unless `locked_out` finds the key locked out, as one
+    /// step. The count starts again from one when the previous failure is
+    /// older than `window`.
+    async fn record_failure(
+        &self,
+        key: &str,
+        window: Duration,
+        locked_out: LockedOut<'_>,
+    ) -> Result<Recorded, AppError>;
+
+    /// Takes back one counted failure.
+    async fn forgive(&self, key: &str) -> Result<(), AppError>;
+
+    async fn clear(&self, key: &str) -> Result<(), AppError>;
+
+    /// Forgets keys whose last failure is older than `window`.
+    async fn purge(&self, window: Duration) -> Result<u64, AppError>;
+}
+
+fn is_stale(attempts: &Attempts, window: Duration) -> bool {
+    Utc::now() - attempts.last_failure_at > chrono::Duration::from_std(window).unwrap_or_default()
+}
+
+/// Which [`AttemptStore`] to count failed logins in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttemptStoreKind {
+    /// [`PgAttemptStore`], shared by all instances.
+    Postgres,
+    /// [`MemoryAttemptStore`].
+    Memory,
+}
+
+/// Keeps counts in process memory. Counts are lost on restart and not
+/// shared between instances.
+#[derive(Default)]
+pub struct MemoryAttemptStore {
+    attempts: Mutex<HashMap<String, Attempts>>,
+}
+
+#[async_trait]
+impl AttemptStore for MemoryAttemptStore {
+    async fn get(&self, key: &str) -> Result<Option<Attempts>, AppError> {
+        Ok(self.attempts.lock().unwrap().get(key).copied())
+    }
+
+    async fn record_failure(
+        &self,
+        key: &str,
+        window: Duration,
+        locked_out: LockedOut<'_>,
+    ) -> Result<Recorded, AppError> {
+        let mut attempts = self.attempts.lock().unwrap();
+        let previous = attempts
+            .get(key)
+            .filter(|previous| !is_stale(previous, window))
+            .copied();
+        if let Some(left) = previous.as_ref().and_then(locked_out) {
+            return Ok(Recorded::LockedOut(left));
+        }
+
+        let recorded = Attempts {
+            failures: previous.map_or(0, |previous| previous.failures) + 1,
+            last_failure_at: Utc::now(),
+        };
+        attempts.insert(key.to_string(), recorded);
+        Ok(Recorded::Counted(recorded))
+    }
+
+    async fn forgive(&self, key: &str) -> Result<(), AppError> {
+        if let Some(recorded) = self.attempts.lock().unwrap().get_mut(key) {
+            recorded.failures = recorded.failures.saturating_sub(1);
+        }
+        Ok(())
+    }
+
+    async fn clear(&self, key: &str) -> Result<(), AppError> {
+        self.attempts.lock().unwrap().remove(key);
+        Ok(())
+    }
+
+    async fn purge(&self, window: Duration) -> Result<u64, AppError> {
+        let mut attempts = self.attempts.lock().unwrap();
+        let before = attempts.len();
+        attempts.retain(|_, recorded| !is_stale(recorded, window));
+        Ok((before - attempts.len()) as u64)
+    }
+}
+
+/// Keeps counts in the `login_attempts` table.
+pub struct PgAttemptStore {
+    pool: PgPool,
+}
+
+impl PgAttemptStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl AttemptStore for PgAttemptStore {
+    async fn get(&self, key: &str) -> Result<Option<Attempts>, AppError> {
+        let row = sqlx::query_as::<_, (i32, DateTime<Utc>)>(
+            "SELECT failures, last_failure_at FROM login_attempts WHERE key = $1",
+        )
+        .bind(key)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|(failures, last_failure_at)| Attempts {
+            failures: failures as u32,
+            last_failure_at,
+        }))
+    }
+
+    async fn record_failure(
+        &self,
+        key: &str,
+        window: Duration,
+        locked_out: LockedOut<'_>,
+    ) -> Result<Recorded, AppError> {
+        let mut tx = self.pool.begin().await?;
+
+        // The row lock holds off other attempts at the key until this one
+        // is counted
+        sqlx::query(
+            "INSERT INTO login_attempts (key, failures, last_failure_at) VALUES ($1, 0, NOW())
+             ON CONFLICT (key) DO NOTHING",
+        )
+        .bind(key)
+        .execute(&mut *tx)
+        .await?;
+        let (failures, last_failure_at) = sqlx::query_as::<_, (i32, DateTime<Utc>)>(
+            "SELECT failures, last_failure_at FROM login_attempts WHERE key = $1 FOR UPDATE",
+        )
+        .bind(key)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let previous = Some(Attempts {
+            failures: failures as u32,
+            last_failure_at,
+        })
+        .filter(|previous| previous.failures > 0 && !is_stale(previous, window));
+        if let Some(left) = previous.as_ref().and_then(locked_out) {
+            return Ok(Recorded::LockedOut(left));
+        }
+
+        let failures = previous.map_or(0, |previous| previous.failures) + 1;
+        let last_failure_at = sqlx::query_scalar::<_, DateTime<Utc>>(
+            "UPDATE login_attempts SET failures = $2, last_failure_at = NOW()
+             WHERE key = $1
+             RETURNING last_failure_at",
+        )
+        .bind(key)
+        .bind(failures as i32)
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(Recorded::Counted(Attempts {
+            failures,
+            last_failure_at,
+        }))
+    }
+
+    async fn forgive(&self, key: &str) -> Result<(), AppError> {
+        sqlx::query(
+            "UPDATE login_attempts SET failures = GREATEST(failures - 1, 0) WHERE key = $1",
+        )
+        .bind(key)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn clear(&self, key: &str) -> Result<(), AppError> {
+        sqlx::query("DELETE FROM login_attempts WHERE key = $1")
+            .bind(key)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn purge(&self, window: Duration) -> Result<u64, AppError> {
+        let result = sqlx::query(
+            "DELETE FROM login_attempts WHERE last_failure_at < NOW() - make_interval(secs => $1)",
+        )
+        .bind(window.as_secs_f64())
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+}
+
+/// When failed logins lock an account or IP address out.
+#[derive(Debug, Clone)]
+pub struct LockoutPolicy {
+    /// Failures of one account that start a lockout.
+    pub account_threshold: u32,
+    /// Failures from one IP address, across accounts, that start a lockout.
+    pub ip_threshold: u32,
+    /// The first lockout; each further failure doubles it.
+    pub base_lockout: Duration,
+    pub max_lockout: Duration,
+    /// Failures are forgotten once none has happened for this long.
+    pub window: Duration,
+}
+
+impl Default for LockoutPolicy {
+    fn default() -> Self {
+        Self {
+            account_threshold: 5,
+            ip_threshold: 20,
+            base_lockout: Duration::from_secs(30),
+            max_lockout: Duration::from_secs(15 * 60),
+            window: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+impl LockoutPolicy {
+    /// How long `failures` lock out a key with the given threshold, if at all.
+    pub fn lockout(&self, failures: u32, threshold: u32) -> Option<Duration> {
+        if failures < threshold {
+            return None;
+        }
+
+        let doublings = (failures - threshold).min(31);
+        Some(
+            self.base_lockout
+                .saturating_mul(1 << doublings)
+                .min(self.max_lockout),
+        )
+    }
+}
+
+/// A login attempt counted by [`LoginThrottle::begin`], to finish with
+/// [`LoginThrottle::failed`], [`LoginThrottle::succeeded`] or
+/// [`LoginThrottle::forgive`].
+#[must_use]
+#[derive(Debug)]
+pub struct LoginAttempt {
+    email: String,
+    ip_address: Option<String>,
+    account_failures: u32,
+    ip_failures: Option<u32>,
+}
+
+/// Counts failed logins and refuses attempts while an account or IP address
+/// is locked out.
+pub struct LoginThrottle {
+    store: Arc<dyn AttemptStore>,
+    policy: LockoutPolicy,
+}
+
+/// Every path to an account's count goes through here, so the address the
+/// user typed and the one stored for them meet, whatever their case.
+fn account_key(email: &str) -> String {
+    format!("account:{}", lookup_email(email))
+}
+
+fn ip_key(ip_address: &str) -> String {
+    format!("ip:{}", ip_address)
+}
+
+impl LoginThrottle {
+    pub fn new(store: Arc<dyn AttemptStore>, policy: LockoutPolicy) -> Self {
+        Self { store, policy }
+    }
+
+    /// Builds the configured store and policy.
+    pub fn from_config(config: &Config, pool: PgPool) -> Self {
+        let store: Arc<dyn AttemptStore> = match config.login_attempt_store {
+            AttemptStoreKind::Memory => Arc::new(MemoryAttemptStore::default()),
+            AttemptStoreKind::Postgres => Arc::new(PgAttemptStore::new(pool)),
+        };
+
+        Self::new(store, config.lockout.clone())
+    }
+
+    /// Time left on the lockout of a key with these failures, if it is
+    /// locked out.
+    fn remaining(&self, attempts: &Attempts, threshold: u32) -> Option<Duration> {
+        let lockout = self.policy.lockout(attempts.failures, threshold)?;
+        let elapsed = (Utc::now() - attempts.last_failure_at)
+            .to_std()
+            .unwrap_or_default();
+        lockout.checked_sub(elapsed).filter(|left| !left.is_zero())
+    }
+
+    async fn record(&self, key: &str, threshold: u32) -> Result<Recorded, AppError> {
+        let locked_out = |attempts: &Attempts| self.remaining(attempts, threshold);
+        self.store
+            .record_failure(key, self.policy.window, &locked_out)
+            .await
+    }
+
+    /// Counts the attempt as failed, or refuses it with `429 Too Many
+    /// Requests` while the account or the client's IP address is locked out.
+    /// Call before checking the password, so a locked-out attacker learns
+    /// nothing from guessing.
+    pub async fn begin(
+        &self,
+        email: &str,
+        ip_address: Option<&str>,
+    ) -> Result<LoginAttempt, AppError> {
+        let locked_out = |left: Duration| AppError::LockedOut(left.as_secs_f64().ceil() as u64);
+
+        let ip_failures = match ip_address {
+            Some(ip_address) => {
+                match self
+                    .record(&ip_key(ip_address), self.policy.ip_threshold)
+                    .await?
+                {
+                    Recorded::Counted(attempts) => Some(attempts.failures),
+                    Recorded::LockedOut(left) => return Err(locked_out(left)),
+                }
+            }
+            None => None,
+        };
+
+        let account_failures = match self
+            .record(&account_key(email), self.policy.account_threshold)
+            .await?
+        {
+            Recorded::Counted(attempts) => attempts.failures,
+            Recorded::LockedOut(left) => {
+                // Refused attempts are not counted
+                if let Some(ip_address) = ip_address {
+                    self.store.forgive(&ip_key(ip_address)).await?;
+                }
+                return Err(locked_out(left));
+            }
+        };
+
+        Ok(LoginAttempt {
+            email: email.to_string(),
+            ip_address: ip_address.map(str::to_string),
+            account_failures,
+            ip_failures,
+        })
+    }
+
+    /// The attempt failed, as already counted. Returns the account's
+    /// lockout if this failure locked it out.
+    pub fn failed(&self, attempt: LoginAttempt) -> Option<Duration> {
+        if let (Some(ip_address), Some(failures)) = (&attempt.ip_address, attempt.ip_failures) {
+            if let Some(lockout) = self.policy.lockout(failures, self.policy.ip_threshold) {
+                tracing::warn!(
+                    "Locked out {} for {}s after {} failed logins",
+                    ip_address,
+                    lockout.as_secs(),
+                    failures
+                );
+            }
+        }
+
+        self.policy
+            .lockout(attempt.account_failures, self.policy.account_threshold)
+    }
+
+    /// The attempt logged in: resets the account's count and takes the
+    /// attempt back from the IP address. The address keeps its other
+    /// failures, so one valid account does not let an attacker keep guessing
+    /// at others.
+    pub async fn succeeded(&self, attempt: LoginAttempt) -> Result<(), AppError> {
+        self.unlock(&attempt.email).await?;
+        if let Some(ip_address) = &attempt.ip_address {
+            self.store.forgive(&ip_key(ip_address)).await?;
+        }
+        Ok(())
+    }
+
+    /// Takes the attempt back without logging in, e.g. when the password was
+    /// right and a second factor is still needed.
+    pub async fn forgive(&self, attempt: LoginAttempt) -> Result<(), AppError> {
+        self.store.forgive(&account_key(&attempt.email)).await?;
+        if let Some(ip_address) = &attempt.ip_address {
+            self.store.forgive(&ip_key(ip_address)).await?;
+        }
+        Ok(())
+    }
+
+    /// Resets the account's count, e.g. after a password reset.
+    pub async fn unlock(&self, email: &str) -> Result<(), AppError> {
+        self.store.clear(&account_key(email)).await
+    }
+
+    /// Drops counts that are past the window anyway.
+    pub async fn purge_expired(&self) -> Result<u64, AppError> {
+        self.store.purge(self.policy.window).await
+    }
+}
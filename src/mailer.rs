@@ -0,0 +1,250 @@
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use lettre::{
+    message::{header::ContentType, Mailbox},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use thiserror::Error;
+
+use crate::config::Config;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+impl Email {
+    /// The message with control characters taken out of the fields that
+    /// become headers. The subject often carries user input, such as a
+    /// workspace name, and a line break there would start a new header.
+    fn header_safe(self) -> Self {
+        Self {
+            to: self.to.chars().filter(|c| !c.is_control()).collect(),
+            subject: self
+                .subject
+                .chars()
+                .map(|c| if c.is_control() { ' ' } else { c })
+                .collect(),
+            body: self.body,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum MailError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Invalid address: {0}")]
+    Address(#[from] lettre::address::AddressError),
+
+    #[error("Invalid message: {0}")]
+    Message(#[from] lettre::error::Error),
+
+    #[error("SMTP error: {0}")]
+    Smtp(#[from] lettre::transport::smtp::Error),
+}
+
+/// Delivers outgoing email. Implementations are selected at startup, see [`from_config`].
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, email: Email) -> Result<(), MailError>;
+}
+
+/// Writes each message to the application log instead of delivering it.
+pub struct LogMailer;
+
+#[async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, email: Email) -> Result<(), MailError> {
+        let email = email.header_safe();
+        tracing::info!("Email to {} | {}\n{}", email.to, email.subject, email.body);
+        Ok(())
+    }
+}
+
+/// Writes each message as a `.eml` file in a directory, for local development.
+pub struct FileMailer {
+    dir: PathBuf,
+}
+
+impl FileMailer {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, email: Email) -> Result<(), MailError> {
+        let email = email.header_safe();
+        tokio::fs::create_dir_all(&self.dir).await?;
+
+        let file_name = format!(
+            "{}-{}.eml",
+            chrono::Utc::now().format("%Y%m%dT%H%M%S%.6f"),
+            uuid::Uuid::new_v4()
+        );
+        let contents = format!(
+            "To: {}\r\nSubject: {}\r\n\r\n{}\r\n",
+            email.to, email.subject, email.body
+        );
+        tokio::fs::write(self.dir.join(file_name), contents).await?;
+
+        Ok(())
+    }
+}
+
+/// Delivers messages through an SMTP server.
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    pub fn new(settings: &SmtpSettings) -> Result<Self, MailError> {
+        let mut builder = match settings.tls {
+            SmtpTls::StartTls => {
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&settings.host)?
+            }
+            SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&settings.host)?,
+            SmtpTls::None => {
+                AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&settings.host)
+            }
+        }
+        .port(settings.port);
+        if let Some(username) = &settings.username {
+            builder = builder.credentials(Credentials::new(
+                username.clone(),
+                settings.password.clone().unwrap_or_default(),
+            ));
+        }
+
+        Ok(Self {
+            transport: builder.build(),
+            from: settings.from.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, email: Email) -> Result<(), MailError> {
+        let email = email.header_safe();
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(email.to.parse()?)
+            .subject(email.subject)
+            .header(ContentType::TEXT_PLAIN)
+            .body(email.body)?;
+        self.transport.send(message).await?;
+
+        Ok(())
+    }
+}
+
+/// Keeps sent messages in memory. Stands in for SMTP in tests.
+#[derive(Default)]
+pub struct MemoryMailer {
+    sent: Mutex<Vec<Email>>,
+}
+
+impl MemoryMailer {
+    pub fn sent(&self) -> Vec<Email> {
+        self.sent.lock().unwrap().clone()
+    }
+
+    /// The most recent message sent to `to`, if any.
+    pub fn last_to(&self, to: &str) -> Option<Email> {
+        self.sent
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .find(|email| email.to == to)
+            .cloned()
+    }
+}
+
+#[async_trait]
+impl Mailer for MemoryMailer {
+    async fn send(&self, email: Email) -> Result<(), MailError> {
+        let email = email.header_safe();
+        self.sent.lock().unwrap().push(email);
+        Ok(())
+    }
+}
+
+/// Which [`Mailer`] delivers outgoing email.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MailerKind {
+    /// [`LogMailer`].
+    Log,
+    /// [`FileMailer`], writing to the directory.
+    File(PathBuf),
+    /// [`SmtpMailer`].
+    Smtp(SmtpSettings),
+}
+
+/// How [`SmtpMailer`] secures its connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmtpTls {
+    /// Upgrade a plain connection with STARTTLS, which the server must offer.
+    StartTls,
+    /// Connect with TLS from the start.
+    Tls,
+    /// Send in plain text, e.g. to a relay on localhost.
+    None,
+}
+
+impl SmtpTls {
+    /// The usual port for this kind of connection.
+    pub fn default_port(self) -> u16 {
+        match self {
+            SmtpTls::StartTls => 587,
+            SmtpTls::Tls => 465,
+            SmtpTls::None => 25,
+        }
+    }
+}
+
+/// The SMTP server [`SmtpMailer`] delivers through.
+#[derive(Clone, PartialEq, Eq)]
+pub struct SmtpSettings {
+    pub host: String,
+    pub port: u16,
+    pub tls: SmtpTls,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// The sender of every message.
+    pub from: Mailbox,
+}
+
+// Keeps the password out of logs
+impl std::fmt::Debug for SmtpSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SmtpSettings")
+            .field("host", &self.host)
+            .field("port", &self.port)
+            .field("tls", &self.tls)
+            .field("username", &self.username)
+            .field("from", &self.from)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Builds the configured mailer.
+pub fn from_config(config: &Config) -> Result<Arc<dyn Mailer>, MailError> {
+    Ok(match &config.mailer {
+        MailerKind::Log => Arc::new(LogMailer),
+        MailerKind::File(dir) => Arc::new(FileMailer::new(dir.clone())),
+        MailerKind::Smtp(settings) => Arc::new(SmtpMailer::new(settings)?),
+    })
+}
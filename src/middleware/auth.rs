@@ -1,69 +1,177 @@
+use std::sync::Arc;
+
 use axum::{
     async_trait,
-    extract::FromRequestParts,
+    extract::{FromRef, FromRequestParts},
     http::{request::Parts, HeaderMap},
 };
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
 use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
 use uuid::Uuid;
 
-use crate::errors::AppError;
+use crate::{
+    errors::AppError, keys::JwtKeys, models::api_key::Scope, revocation::RevocationStore, tokens,
+};
+
+/// Personal access tokens start with this, which tells them apart from JWTs.
+pub const API_KEY_PREFIX: &str = "pat_";
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String,
     pub exp: usize,
+    /// Unique token id, used to revoke this token on logout.
+    pub jti: String,
+    /// The session (device login) the token belongs to.
+    pub sid: String,
+    /// The user's `token_version` at issue time.
+    pub ver: i32,
+}
+
+impl Claims {
+    pub fn user_id(&self) -> Result<Uuid, AppError> {
+        Uuid::parse_str(&self.sub).map_err(|_| AppError::Auth("Invalid user ID in token".into()))
+    }
+
+    pub fn session_id(&self) -> Result<Uuid, AppError> {
+        Uuid::parse_str(&self.sid).map_err(|_| AppError::Auth("Invalid session ID".into()))
+    }
 }
 
+/// The authenticated caller, from either an access token (JWT) or an API key.
+///
+/// API keys are only accepted on routes layered with the [`Scope`] they need,
+/// e.g. `get(handler).layer(Extension(Scope::TasksRead))`; everywhere else,
+/// including `CurrentToken` routes, they are rejected.
 pub struct AuthUser(pub Uuid);
 
-pub fn create_jwt(user_id: &str) -> Result<String, AppError> {
-    let secret = std::env::var("JWT_SECRET").expect("JWT_SECRET must be set");
+/// The validated claims of the bearer token, for handlers that act on the
+/// token itself (e.g. logout).
+pub struct CurrentToken(pub Claims);
+
+/// Signs an access token that lives for `ttl`. Clients renew access tokens
+/// with a refresh token.
+pub fn create_jwt(
+    keys: &JwtKeys,
+    ttl: chrono::Duration,
+    user_id: &str,
+    session_id: Uuid,
+    token_version: i32,
+) -> Result<String, AppError> {
     let expiration = chrono::Utc::now()
-        .checked_add_signed(chrono::Duration::hours(24))
+        .checked_add_signed(ttl)
         .expect("valid timestamp")
         .timestamp() as usize;
 
     let claims = Claims {
         sub: user_id.to_string(),
         exp: expiration,
+        jti: Uuid::new_v4().to_string(),
+        sid: session_id.to_string(),
+        ver: token_version,
     };
 
-    encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(secret.as_bytes()),
+    keys.sign(&claims)
+}
+
+pub(crate) fn bearer_token(headers: &HeaderMap) -> Result<&str, AppError> {
+    headers
+        .get("Authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .ok_or_else(|| AppError::Auth("Missing or invalid Authorization header".into()))
+}
+
+/// Resolves an API key to its user, recording its use, and checks that it
+/// grants `required`. Keys of disabled accounts and accounts pending deletion
+/// do not work.
+async fn authenticate_api_key(
+    pool: &PgPool,
+    token: &str,
+    required: Option<Scope>,
+) -> Result<Uuid, AppError> {
+    let (user_id, scopes) = sqlx::query_as::<_, (Uuid, Vec<Scope>)>(
+        "UPDATE api_keys k SET last_used_at = NOW()
+         FROM users u
+         WHERE k.token_hash = $1 AND u.id = k.user_id
+           AND (k.expires_at IS NULL OR k.expires_at > NOW())
+           AND u.deletion_scheduled_for IS NULL
+           AND u.disabled_at IS NULL
+         RETURNING k.user_id, k.scopes",
     )
-    .map_err(|_| AppError::Auth("Failed to create token".into()))
+    .bind(tokens::hash_opaque(token))
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| AppError::Auth("Invalid or expired API key".into()))?;
+
+    let Some(required) = required else {
+        return Err(AppError::Forbidden(
+            "API keys cannot be used for this endpoint".into(),
+        ));
+    };
+
+    if !scopes.contains(&required) {
+        return Err(AppError::Forbidden(format!(
+            "API key lacks the {} scope",
+            required.as_str()
+        )));
+    }
+
+    Ok(user_id)
 }
 
 #[async_trait]
-impl<S> FromRequestParts<S> for AuthUser
+impl<S> FromRequestParts<S> for CurrentToken
 where
+    Arc<JwtKeys>: FromRef<S>,
+    Arc<RevocationStore>: FromRef<S>,
     S: Send + Sync,
 {
     type Rejection = AppError;
 
-    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
-        let secret = std::env::var("JWT_SECRET").expect("JWT_SECRET must be set");
-        let headers: &HeaderMap = &parts.headers;
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let token = bearer_token(&parts.headers)?;
+        let claims: Claims = Arc::<JwtKeys>::from_ref(state).verify(token)?;
 
-        let token = headers
-            .get("Authorization")
-            .and_then(|v| v.to_str().ok())
-            .and_then(|v| v.strip_prefix("Bearer "))
-            .ok_or_else(|| AppError::Auth("Missing or invalid Authorization header".into()))?;
+        let user_id = claims.user_id()?;
+        let jti =
+            Uuid::parse_str(&claims.jti).map_err(|_| AppError::Auth("Invalid token ID".into()))?;
+        let session_id = claims.session_id()?;
+
+        let revocations = Arc::<RevocationStore>::from_ref(state);
+        if revocations
+            .is_revoked(user_id, jti, session_id, claims.ver)
+            .await?
+        {
+            return Err(AppError::Auth("Token has been revoked".into()));
+        }
+
+        Ok(CurrentToken(claims))
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AuthUser
+where
+    PgPool: FromRef<S>,
+    Arc<JwtKeys>: FromRef<S>,
+    Arc<RevocationStore>: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
 
-        let token_data = decode::<Claims>(
-            token,
-            &DecodingKey::from_secret(secret.as_bytes()),
-            &Validation::default(),
-        )
-        .map_err(|_| AppError::Auth("Invalid or expired token".into()))?;
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let token = bearer_token(&parts.headers)?;
+        if token.starts_with(API_KEY_PREFIX) {
+            let pool = PgPool::from_ref(state);
+            let scope = parts.extensions.get::<Scope>().copied();
+            return authenticate_api_key(&pool, token, scope)
+                .await
+                .map(AuthUser);
+        }
 
-        let user_id = Uuid::parse_str(&token_data.claims.sub)
-            .map_err(|_| AppError::Auth("Invalid user ID in token".into()))?;
+        let CurrentToken(claims) = CurrentToken::from_request_parts(parts, state).await?;
 
-        Ok(AuthUser(user_id))
+        Ok(AuthUser(claims.user_id()?))
     }
 }
@@ -0,0 +1,123 @@
+use std::{
+    convert::Infallible,
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+    sync::Arc,
+};
+
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRef, FromRequestParts},
+    http::{header::USER_AGENT, request::Parts, Extensions, HeaderMap},
+};
+use ipnet::IpNet;
+
+use crate::config::Config;
+
+/// `TRUSTED_PROXIES`: the addresses and networks of reverse proxies whose
+/// `X-Forwarded-For` header is believed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrustedProxies(Vec<IpNet>);
+
+impl TrustedProxies {
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        self.0.iter().any(|net| net.contains(ip))
+    }
+}
+
+impl FromStr for TrustedProxies {
+    type Err = String;
+
+    /// Comma-separated addresses (`10.0.0.1`) and networks (`10.0.0.0/8`).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(|item| {
+                item.parse::<IpNet>()
+                    .or_else(|_| item.parse::<IpAddr>().map(IpNet::from))
+                    .map_err(|_| format!("not an address or network: {}", item))
+            })
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+}
+
+/// Where a request came from, recorded on sessions so users can recognise
+/// their devices and used to count failed logins and rate limit anonymous
+/// callers.
+#[derive(Debug, Clone, Default)]
+pub struct ClientInfo {
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+}
+
+impl ClientInfo {
+    /// The client's IP address is the peer's, unless the peer is a trusted
+    /// proxy: then it is the last address in `X-Forwarded-For` that is not
+    /// one of the trusted proxies, since anything before it could have been
+    /// sent by the client.
+    pub fn new(headers: &HeaderMap, extensions: &Extensions, proxies: &TrustedProxies) -> Self {
+        let user_agent = headers
+            .get(USER_AGENT)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+
+        let peer = extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+        let ip_address = peer
+            .map(|peer| {
+                if proxies.contains(&peer) {
+                    forwarded_for(headers, proxies).unwrap_or(peer)
+                } else {
+                    peer
+                }
+            })
+            .map(|ip| ip.to_string());
+
+        ClientInfo {
+            user_agent,
+            ip_address,
+        }
+    }
+}
+
+fn forwarded_for(headers: &HeaderMap, proxies: &TrustedProxies) -> Option<IpAddr> {
+    let hops = headers
+        .get_all("X-Forwarded-For")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|hop| hop.trim().parse::<IpAddr>().ok())
+        .collect::<Vec<_>>();
+
+    let mut client = None;
+    for hop in hops.into_iter().rev() {
+        // A hop that is not an address was not written by a trusted proxy
+        let hop = hop?;
+        client = Some(hop);
+        if !proxies.contains(&hop) {
+            break;
+        }
+    }
+    client
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ClientInfo
+where
+    Arc<Config>: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let config = Arc::<Config>::from_ref(state);
+        Ok(Self::new(
+            &parts.headers,
+            &parts.extensions,
+            &config.trusted_proxies,
+        ))
+    }
+}
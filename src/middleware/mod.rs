@@ -1 +1,5 @@
 pub mod auth;
+pub mod client;
+pub mod rate_limit;
+pub mod request_id;
+pub mod workspace;
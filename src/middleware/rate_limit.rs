@@ -0,0 +1,284 @@
+//! Token bucket rate limiting, per route group and caller.
+//!
+//! Each caller gets a bucket per route group that holds up to the group's
+//! limit of requests and refills continuously over its period. Buckets are
+//! kept in process memory, so with several instances each enforces its own
+//! limit, and their number is capped.
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{Request, State},
+    http::{HeaderMap, HeaderName, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use uuid::Uuid;
+
+use crate::{
+    config::Config,
+    errors::AppError,
+    middleware::{
+        auth::{bearer_token, Claims, API_KEY_PREFIX},
+        client::ClientInfo,
+    },
+    state::AppState,
+    tokens,
+};
+
+/// Buckets kept at most; past this the least recently used are dropped.
+const MAX_BUCKETS: usize = 10_000;
+/// How often buckets that are full again are dropped.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Routes that share a limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RouteGroup {
+    /// Unauthenticated auth endpoints (login, register, password reset, ...),
+    /// which attract credential stuffing and email flooding.
+    Auth,
+    /// Everything else.
+    Api,
+}
+
+/// `requests` per `period`, which is also the largest burst allowed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    pub requests: u32,
+    pub period: Duration,
+}
+
+impl RateLimit {
+    pub fn per_minute(requests: u32) -> Self {
+        Self {
+            requests,
+            period: Duration::from_secs(60),
+        }
+    }
+
+    fn refill_per_sec(&self) -> f64 {
+        self.requests as f64 / self.period.as_secs_f64()
+    }
+}
+
+/// The outcome of taking a request from a bucket, for the `RateLimit-*`
+/// response headers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Decision {
+    pub limit: RateLimit,
+    pub remaining: u32,
+    /// Seconds until the bucket is full again.
+    pub reset: u64,
+    /// Seconds until the next request is allowed, if this one was not.
+    pub retry_after: Option<u64>,
+}
+
+impl Decision {
+    fn set_headers(&self, headers: &mut HeaderMap) {
+        let mut set = |name: &'static str, value: String| {
+            if let Ok(value) = HeaderValue::from_str(&value) {
+                headers.insert(HeaderName::from_static(name), value);
+            }
+        };
+
+        set("ratelimit-limit", self.limit.requests.to_string());
+        set("ratelimit-remaining", self.remaining.to_string());
+        set("ratelimit-reset", self.reset.to_string());
+        set(
+            "ratelimit-policy",
+            format!("{};w={}", self.limit.requests, self.limit.period.as_secs()),
+        );
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, limit: &RateLimit, now: Instant) {
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * limit.refill_per_sec()).min(limit.requests as f64);
+        self.updated = now;
+    }
+}
+
+#[derive(Default)]
+struct Buckets {
+    buckets: HashMap<(RouteGroup, String), Bucket>,
+    swept: Option<Instant>,
+}
+
+impl Buckets {
+    /// Makes room for a new bucket. A bucket untouched for its group's
+    /// period is full again, as good as a new one, so those go first; past
+    /// `max`, so do the least recently used, a tenth at a time so this is
+    /// rare.
+    fn evict(&mut self, limits: &HashMap<RouteGroup, RateLimit>, max: usize, now: Instant) {
+        if self
+            .swept
+            .is_none_or(|swept| now.duration_since(swept) >= SWEEP_INTERVAL)
+        {
+            self.buckets.retain(|(group, _), bucket| {
+                limits
+                    .get(group)
+                    .is_some_and(|limit| now.duration_since(bucket.updated) < limit.period)
+            });
+            self.swept = Some(now);
+        }
+
+        if self.buckets.len() >= max {
+            let excess = self.buckets.len() + 1 - max + max / 10;
+            let mut updated: Vec<Instant> = self.buckets.values().map(|b| b.updated).collect();
+            let (_, cutoff, _) = updated.select_nth_unstable(excess - 1);
+            let cutoff = *cutoff;
+            self.buckets.retain(|_, bucket| bucket.updated > cutoff);
+        }
+    }
+}
+
+/// The limits of each route group and the callers' buckets. Groups without a
+/// limit are not limited.
+pub struct RateLimiter {
+    limits: HashMap<RouteGroup, RateLimit>,
+    buckets: Mutex<Buckets>,
+    max_buckets: usize,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self {
+            limits: HashMap::new(),
+            buckets: Mutex::default(),
+            max_buckets: MAX_BUCKETS,
+        }
+    }
+}
+
+impl RateLimiter {
+    pub fn with_limit(mut self, group: RouteGroup, limit: RateLimit) -> Self {
+        self.limits.insert(group, limit);
+        self
+    }
+
+    /// Caps the buckets kept, 10,000 by default.
+    pub fn with_max_buckets(mut self, max_buckets: usize) -> Self {
+        self.max_buckets = max_buckets.max(1);
+        self
+    }
+
+    /// The configured per-minute limits; `0` turns a limit off.
+    pub fn from_config(config: &Config) -> Self {
+        let mut limiter = Self::default();
+        for (group, requests) in [
+            (RouteGroup::Auth, config.rate_limit_auth_per_minute),
+            (RouteGroup::Api, config.rate_limit_api_per_minute),
+        ] {
+            if requests > 0 {
+                limiter = limiter.with_limit(group, RateLimit::per_minute(requests));
+            }
+        }
+        limiter
+    }
+
+    /// Takes a request from the caller's bucket. `None` when the group has no
+    /// limit.
+    pub fn acquire(&self, group: RouteGroup, caller: &str) -> Option<Decision> {
+        let limit = *self.limits.get(&group)?;
+        let capacity = limit.requests as f64;
+        let rate = limit.refill_per_sec();
+        let now = Instant::now();
+
+        let mut buckets = self.buckets.lock().unwrap();
+        let key = (group, caller.to_string());
+        if !buckets.buckets.contains_key(&key) {
+            buckets.evict(&self.limits, self.max_buckets, now);
+        }
+
+        let bucket = buckets.buckets.entry(key).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+        bucket.refill(&limit, now);
+
+        let allowed = bucket.tokens >= 1.0;
+        if allowed {
+            bucket.tokens -= 1.0;
+        }
+
+        Some(Decision {
+            limit,
+            remaining: bucket.tokens.floor() as u32,
+            reset: ((capacity - bucket.tokens) / rate).ceil() as u64,
+            retry_after: (!allowed).then(|| (((1.0 - bucket.tokens) / rate).ceil() as u64).max(1)),
+        })
+    }
+}
+
+/// Who the request is counted against: the user of a valid access token or
+/// API key, like [`AuthUser`](crate::middleware::auth::AuthUser) but without
+/// the revocation check, or else the client's IP address, which is only taken
+/// from `X-Forwarded-For` behind a trusted proxy.
+async fn caller(state: &AppState, headers: &HeaderMap, client: &ClientInfo) -> String {
+    if let Ok(token) = bearer_token(headers) {
+        let user_id = if token.starts_with(API_KEY_PREFIX) {
+            sqlx::query_scalar::<_, Uuid>("SELECT user_id FROM api_keys WHERE token_hash = $1")
+                .bind(tokens::hash_opaque(token))
+                .fetch_optional(&state.pool)
+                .await
+                .unwrap_or_else(|e| {
+                    tracing::error!("Rate limiter could not look up API key: {}", e);
+                    None
+                })
+        } else {
+            state
+                .jwt_keys
+                .verify::<Claims>(token)
+                .ok()
+                .and_then(|claims| claims.user_id().ok())
+        };
+
+        if let Some(user_id) = user_id {
+            return format!("user:{}", user_id);
+        }
+    }
+
+    format!("ip:{}", client.ip_address.as_deref().unwrap_or("unknown"))
+}
+
+/// Middleware that limits a route group, e.g.
+/// `router.route_layer(from_fn_with_state((state, RouteGroup::Auth), rate_limit))`.
+/// Limited responses carry `RateLimit-Limit`, `RateLimit-Remaining`,
+/// `RateLimit-Reset` and `RateLimit-Policy`; refused ones are
+/// `429 Too Many Requests` with `Retry-After`.
+pub async fn rate_limit(
+    State((state, group)): State<(AppState, RouteGroup)>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !state.rate_limiter.limits.contains_key(&group) {
+        return next.run(request).await;
+    }
+
+    let client = ClientInfo::new(
+        request.headers(),
+        request.extensions(),
+        &state.config.trusted_proxies,
+    );
+    let caller = caller(&state, request.headers(), &client).await;
+    let Some(decision) = state.rate_limiter.acquire(group, &caller) else {
+        return next.run(request).await;
+    };
+
+    let mut response = match decision.retry_after {
+        Some(retry_after) => AppError::RateLimited(retry_after).into_response(),
+        None => next.run(request).await,
+    };
+    decision.set_headers(response.headers_mut());
+    response
+}
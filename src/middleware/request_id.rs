@@ -0,0 +1,63 @@
+//! Request ids, for matching an error a client reports to the server logs.
+
+use axum::{
+    extract::Request,
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use tracing::Instrument;
+use uuid::Uuid;
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Incoming ids longer than this are replaced rather than echoed.
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+/// The request being handled, for error responses, which have no access to
+/// the request itself.
+#[derive(Debug, Clone)]
+pub struct RequestContext {
+    pub request_id: String,
+    pub path: String,
+}
+
+tokio::task_local! {
+    static CURRENT: RequestContext;
+}
+
+impl RequestContext {
+    /// The context of the request this task is handling, if any.
+    pub fn current() -> Option<Self> {
+        CURRENT.try_with(Clone::clone).ok()
+    }
+}
+
+/// Middleware that gives every request an id: the caller's `X-Request-Id`,
+/// when it is a short printable string, or a new UUID. The id is logged with
+/// the request and returned in the `X-Request-Id` response header.
+pub async fn request_id(request: Request, next: Next) -> Response {
+    let request_id = request
+        .headers()
+        .get(&REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.is_empty() && v.len() <= MAX_REQUEST_ID_LENGTH)
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+    let context = RequestContext {
+        request_id: request_id.clone(),
+        path: request.uri().path().to_string(),
+    };
+
+    let span = tracing::info_span!("request", request_id = %request_id);
+    let mut response = CURRENT
+        .scope(context, next.run(request))
+        .instrument(span)
+        .await;
+
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
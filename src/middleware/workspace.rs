@@ -0,0 +1,65 @@
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{
+    async_trait,
+    extract::{FromRef, FromRequestParts, Path},
+    http::request::Parts,
+};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    authz::{self, Permission},
+    errors::AppError,
+    keys::JwtKeys,
+    middleware::auth::AuthUser,
+    models::workspace::Role,
+    revocation::RevocationStore,
+};
+
+/// The authenticated caller's membership in the workspace named by the
+/// `:workspace_id` path segment. Extraction fails with `NotFound` for non-members.
+pub struct WorkspaceAccess {
+    pub workspace_id: Uuid,
+    pub user_id: Uuid,
+    pub role: Role,
+}
+
+impl WorkspaceAccess {
+    pub fn require(&self, permission: Permission) -> Result<(), AppError> {
+        authz::check(self.role, permission).map(|_| ())
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for WorkspaceAccess
+where
+    PgPool: FromRef<S>,
+    Arc<JwtKeys>: FromRef<S>,
+    Arc<RevocationStore>: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let AuthUser(user_id) = AuthUser::from_request_parts(parts, state).await?;
+
+        let Path(params) = Path::<HashMap<String, String>>::from_request_parts(parts, state)
+            .await
+            .map_err(|_| AppError::BadRequest("Invalid path parameters".into()))?;
+
+        let workspace_id = params
+            .get("workspace_id")
+            .and_then(|id| Uuid::parse_str(id).ok())
+            .ok_or_else(|| AppError::BadRequest("Invalid workspace id".into()))?;
+
+        let pool = PgPool::from_ref(state);
+        let role = authz::authorize(&pool, user_id, workspace_id, Permission::View).await?;
+
+        Ok(WorkspaceAccess {
+            workspace_id,
+            user_id,
+            role,
+        })
+    }
+}
@@ -0,0 +1,65 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::validation::MAX_API_KEY_NAME_LENGTH;
+
+/// What an API key may be used for. Routes declare the scope they need;
+/// API keys are rejected on routes that declare none.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "api_scope")]
+pub enum Scope {
+    #[serde(rename = "tasks:read")]
+    #[sqlx(rename = "tasks:read")]
+    TasksRead,
+    #[serde(rename = "tasks:write")]
+    #[sqlx(rename = "tasks:write")]
+    TasksWrite,
+    #[serde(rename = "workspaces:read")]
+    #[sqlx(rename = "workspaces:read")]
+    WorkspacesRead,
+    #[serde(rename = "workspaces:write")]
+    #[sqlx(rename = "workspaces:write")]
+    WorkspacesWrite,
+}
+
+impl Scope {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Scope::TasksRead => "tasks:read",
+            Scope::TasksWrite => "tasks:write",
+            Scope::WorkspacesRead => "workspaces:read",
+            Scope::WorkspacesWrite => "workspaces:write",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ApiKey {
+    pub id: Uuid,
+    pub name: String,
+    /// The first characters of the token, to tell keys apart.
+    pub prefix: String,
+    pub scopes: Vec<Scope>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateApiKeyRequest {
+    #[validate(length(max = MAX_API_KEY_NAME_LENGTH, code = "too_long"))]
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    /// Never expires when omitted.
+    pub expires_in_days: Option<i32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreatedApiKey {
+    #[serde(flatten)]
+    pub key: ApiKey,
+    /// The full token. It is only shown once.
+    pub token: String,
+}
@@ -0,0 +1,74 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::models::{
+    api_key::ApiKey,
+    session::Session,
+    task::{Task, TaskHistoryEntry},
+    workspace::{Invitation, Role},
+};
+
+/// Everything stored about a user, as returned by `GET /me/export`.
+#[derive(Debug, Serialize)]
+pub struct AccountExport {
+    pub exported_at: DateTime<Utc>,
+    pub profile: ExportProfile,
+    pub workspaces: Vec<ExportMembership>,
+    /// Tasks the user created or is assigned to.
+    pub tasks: Vec<Task>,
+    /// Task changes made by the user.
+    pub task_history: Vec<TaskHistoryEntry>,
+    pub sessions: Vec<Session>,
+    /// API keys, without their tokens.
+    pub api_keys: Vec<ApiKey>,
+    /// Single sign-on identities linked to the account.
+    pub identities: Vec<ExportIdentity>,
+    /// Invitations the user sent or that were addressed to them.
+    pub invitations: Vec<Invitation>,
+    pub two_factor: ExportTwoFactor,
+    pub audit_events: Vec<AuditEvent>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct ExportProfile {
+    pub id: Uuid,
+    pub email: String,
+    pub created_at: DateTime<Utc>,
+    pub email_verified_at: Option<DateTime<Utc>>,
+    pub auto_archive_after_days: Option<i32>,
+    pub deletion_scheduled_for: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct ExportMembership {
+    pub workspace_id: Uuid,
+    pub name: String,
+    pub is_personal: bool,
+    pub role: Role,
+    pub joined_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct ExportIdentity {
+    pub provider: String,
+    pub subject: String,
+    pub email: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_login_at: Option<DateTime<Utc>>,
+}
+
+/// Whether two-factor authentication is on, without the secret or the codes.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct ExportTwoFactor {
+    pub enabled_at: Option<DateTime<Utc>>,
+    pub unused_recovery_codes: i64,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct AuditEvent {
+    pub id: Uuid,
+    pub action: String,
+    pub details: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+}
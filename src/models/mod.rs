@@ -1,2 +1,8 @@
+pub mod api_key;
+pub mod export;
+pub mod oidc;
+pub mod session;
 pub mod task;
+pub mod two_factor;
 pub mod user;
+pub mod workspace;
@@ -0,0 +1,14 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize)]
+pub struct AuthorizationUrl {
+    /// Where to send the user's browser to sign in with the provider.
+    pub authorization_url: String,
+}
+
+/// The query parameters the provider appends to the redirect URI.
+#[derive(Debug, Deserialize)]
+pub struct OidcCallbackRequest {
+    pub code: String,
+    pub state: String,
+}
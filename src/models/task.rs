@@ -1,26 +1,74 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
+use validator::Validate;
+
+use crate::validation::{not_blank, MAX_DESCRIPTION_LENGTH, MAX_TITLE_LENGTH};
 
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
 pub struct Task {
     pub id: Uuid,
-    pub user_id: Uuid,
+    pub workspace_id: Uuid,
+    /// The user who created the task, if their account still exists.
+    pub user_id: Option<Uuid>,
+    pub assignee_id: Option<Uuid>,
     pub title: String,
     pub description: Option<String>,
     pub done: bool,
     pub created_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+    pub archived_at: Option<DateTime<Utc>>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Validate)]
 pub struct CreateTaskRequest {
+    #[validate(
+        custom(function = "not_blank"),
+        length(max = MAX_TITLE_LENGTH, code = "too_long")
+    )]
     pub title: String,
+    #[validate(length(
+        max = MAX_DESCRIPTION_LENGTH,
+        code = "too_long"
+    ))]
     pub description: Option<String>,
+    /// Defaults to the caller's personal workspace.
+    pub workspace_id: Option<Uuid>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Validate)]
 pub struct UpdateTaskRequest {
+    #[validate(
+        custom(function = "not_blank"),
+        length(max = MAX_TITLE_LENGTH, code = "too_long")
+    )]
     pub title: Option<String>,
+    #[validate(length(
+        max = MAX_DESCRIPTION_LENGTH,
+        code = "too_long"
+    ))]
     pub description: Option<String>,
     pub done: Option<bool>,
 }
+
+#[derive(Debug, Default, Deserialize)]
+pub struct TaskListQuery {
+    #[serde(default)]
+    pub archived: bool,
+    pub workspace_id: Option<Uuid>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AssignTaskRequest {
+    pub user_id: Uuid,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct TaskHistoryEntry {
+    pub id: Uuid,
+    pub task_id: Uuid,
+    pub actor_id: Option<Uuid>,
+    pub action: String,
+    pub details: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+}
@@ -0,0 +1,32 @@
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use crate::validation::MAX_PASSWORD_LENGTH;
+
+#[derive(Debug, Serialize)]
+pub struct TwoFactorEnrollment {
+    /// Base32 secret, for authenticator apps that cannot scan the URI.
+    pub secret: String,
+    /// `otpauth://` URI to show as a QR code.
+    pub otpauth_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ConfirmTwoFactorRequest {
+    pub code: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RecoveryCodes {
+    /// Single-use codes that stand in for an authenticator code. They are
+    /// shown once; only their hashes are stored.
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct DisableTwoFactorRequest {
+    #[validate(length(max = MAX_PASSWORD_LENGTH, code = "too_long"))]
+    pub password: String,
+    /// Authenticator or recovery code.
+    pub code: String,
+}
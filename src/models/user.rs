@@ -1,6 +1,9 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
+use validator::Validate;
+
+use crate::validation::{valid_email, MAX_EMAIL_LENGTH, MAX_PASSWORD_LENGTH};
 
 #[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
 pub struct User {
@@ -8,21 +11,136 @@ pub struct User {
     pub email: String,
     pub password_hash: String,
     pub created_at: DateTime<Utc>,
+    pub auto_archive_after_days: Option<i32>,
+    pub email_verified_at: Option<DateTime<Utc>>,
+    /// Set once TOTP two-factor authentication is confirmed.
+    pub totp_enabled_at: Option<DateTime<Utc>>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Validate)]
 pub struct RegisterRequest {
+    #[validate(
+        custom(function = "valid_email"),
+        length(max = MAX_EMAIL_LENGTH, code = "too_long")
+    )]
     pub email: String,
+    #[validate(length(
+        max = MAX_PASSWORD_LENGTH,
+        code = "too_long"
+    ))]
     pub password: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Validate)]
 pub struct LoginRequest {
     pub email: String,
+    #[validate(length(max = MAX_PASSWORD_LENGTH, code = "too_long"))]
     pub password: String,
+    /// Authenticator or recovery code, for accounts with two-factor
+    /// authentication. Without it those logins return a challenge instead.
+    pub code: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
 pub struct AuthResponse {
+    /// Short-lived access token (JWT).
+    pub token: String,
+    /// Long-lived opaque token for `POST /auth/refresh`.
+    pub refresh_token: String,
+    pub token_type: String,
+    /// Access token lifetime in seconds.
+    pub expires_in: i64,
+}
+
+/// Returned by login instead of tokens when the account has two-factor
+/// authentication and no code was given.
+#[derive(Debug, Serialize)]
+pub struct TwoFactorChallenge {
+    pub two_factor_required: bool,
+    /// Short-lived token for `POST /auth/login/2fa`.
+    pub challenge_token: String,
+    /// Challenge token lifetime in seconds.
+    pub expires_in: i64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum LoginResponse {
+    Tokens(AuthResponse),
+    TwoFactorRequired(TwoFactorChallenge),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TwoFactorLoginRequest {
+    pub challenge_token: String,
+    /// Authenticator or recovery code.
+    pub code: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct LogoutRequest {
+    /// Also revoke this refresh token and every token rotated from it.
+    pub refresh_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyEmailRequest {
     pub token: String,
 }
+
+#[derive(Debug, Deserialize)]
+pub struct PasswordResetRequest {
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct PasswordResetConfirm {
+    pub token: String,
+    #[validate(length(max = MAX_PASSWORD_LENGTH, code = "too_long"))]
+    pub new_password: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct ChangePasswordRequest {
+    #[validate(length(max = MAX_PASSWORD_LENGTH, code = "too_long"))]
+    pub current_password: String,
+    #[validate(length(max = MAX_PASSWORD_LENGTH, code = "too_long"))]
+    pub new_password: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct ChangeEmailRequest {
+    /// The new address; it only takes effect once confirmed.
+    pub email: String,
+    #[validate(length(max = MAX_PASSWORD_LENGTH, code = "too_long"))]
+    pub password: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ConfirmEmailChangeRequest {
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct DeleteAccountRequest {
+    #[validate(length(max = MAX_PASSWORD_LENGTH, code = "too_long"))]
+    pub password: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AccountDeletion {
+    /// When the account and its data will be purged, unless cancelled before.
+    pub deletion_scheduled_for: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct UserSettings {
+    /// Archive completed tasks this many days after they were marked done.
+    /// `None` disables auto-archiving.
+    pub auto_archive_after_days: Option<i32>,
+}
@@ -0,0 +1,109 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use validator::Validate;
+
+use crate::{models::user::AuthResponse, validation::MAX_PASSWORD_LENGTH};
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Workspace {
+    pub id: Uuid,
+    pub name: String,
+    pub created_by: Option<Uuid>,
+    pub is_personal: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A member's role within a workspace, ordered from least to most privileged.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, sqlx::Type,
+)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "workspace_role", rename_all = "lowercase")]
+pub enum Role {
+    Viewer,
+    /// Has the same permissions as `Viewer` until tasks can be commented on.
+    Commenter,
+    Editor,
+    Admin,
+    Owner,
+}
+
+impl Role {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Commenter => "commenter",
+            Role::Editor => "editor",
+            Role::Admin => "admin",
+            Role::Owner => "owner",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct WorkspaceMember {
+    pub user_id: Uuid,
+    pub email: String,
+    pub role: Role,
+    pub joined_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateWorkspaceRequest {
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateWorkspaceRequest {
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AddMemberRequest {
+    pub email: String,
+    /// Defaults to `editor`.
+    pub role: Option<Role>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateMemberRequest {
+    pub role: Role,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Invitation {
+    pub id: Uuid,
+    pub workspace_id: Uuid,
+    pub email: String,
+    pub role: Role,
+    pub invited_by: Option<Uuid>,
+    pub expires_at: DateTime<Utc>,
+    pub accepted_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateInvitationRequest {
+    pub email: String,
+    /// Defaults to `editor`.
+    pub role: Option<Role>,
+}
+
+#[derive(Debug, Default, Deserialize, Validate)]
+pub struct AcceptInvitationRequest {
+    /// Required when no account exists yet for the invited email.
+    #[validate(length(max = MAX_PASSWORD_LENGTH, code = "too_long"))]
+    pub password: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AcceptInvitationResponse {
+    pub workspace_id: Uuid,
+    pub user_id: Uuid,
+    pub role: Role,
+    /// Set when accepting the invitation created a new account.
+    #[serde(flatten)]
+    pub auth: Option<AuthResponse>,
+}
@@ -0,0 +1,303 @@
+//! OpenID Connect client for signing in with external identity providers,
+//! using the authorization code flow with PKCE.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use jsonwebtoken::{
+    decode, decode_header,
+    jwk::{Jwk, JwkSet},
+    Algorithm, DecodingKey, Validation,
+};
+use serde::Deserialize;
+
+use crate::{errors::AppError, tokens};
+
+pub const DEFAULT_SCOPES: &str = "openid email profile";
+
+/// A configured identity provider. `name` is the key used in URLs, e.g.
+/// `/auth/oidc/acme/authorize`.
+#[derive(Debug, Clone)]
+pub struct ProviderConfig {
+    pub name: String,
+    pub issuer: String,
+    pub client_id: String,
+    /// Confidential clients send this to the token endpoint; public clients
+    /// rely on PKCE alone.
+    pub client_secret: Option<String>,
+    pub scopes: String,
+}
+
+impl ProviderConfig {
+    pub fn new(name: &str, issuer: &str, client_id: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            issuer: issuer.to_string(),
+            client_id: client_id.to_string(),
+            client_secret: None,
+            scopes: DEFAULT_SCOPES.to_string(),
+        }
+    }
+
+    pub fn with_client_secret(mut self, client_secret: &str) -> Self {
+        self.client_secret = Some(client_secret.to_string());
+        self
+    }
+}
+
+/// The parts of the provider's discovery document the login flow needs.
+#[derive(Debug, Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+struct Discovered {
+    metadata: ProviderMetadata,
+    jwks: RwLock<JwkSet>,
+}
+
+/// Everything needed to send the user to the provider and to finish the
+/// login when they come back.
+#[derive(Debug)]
+pub struct AuthorizationRequest {
+    pub url: String,
+    pub state: String,
+    pub nonce: String,
+    pub code_verifier: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IdTokenClaims {
+    pub sub: String,
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: bool,
+    pub nonce: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+/// Configured providers, with their discovery documents and signing keys
+/// cached after first use.
+pub struct OidcClient {
+    providers: HashMap<String, ProviderConfig>,
+    http: reqwest::Client,
+    discovered: RwLock<HashMap<String, Arc<Discovered>>>,
+}
+
+impl OidcClient {
+    pub fn new(providers: Vec<ProviderConfig>) -> Self {
+        Self {
+            providers: providers
+                .into_iter()
+                .map(|provider| (provider.name.clone(), provider))
+                .collect(),
+            http: reqwest::Client::new(),
+            discovered: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn provider(&self, name: &str) -> Result<&ProviderConfig, AppError> {
+        self.providers
+            .get(name)
+            .ok_or_else(|| AppError::NotFound("Unknown identity provider".into()))
+    }
+
+    async fn fetch<T: serde::de::DeserializeOwned>(&self, url: &str) -> Result<T, AppError> {
+        self.http
+            .get(url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| AppError::IdentityProvider(e.to_string()))?
+            .json()
+            .await
+            .map_err(|e| AppError::IdentityProvider(e.to_string()))
+    }
+
+    async fn discover(&self, provider: &ProviderConfig) -> Result<Arc<Discovered>, AppError> {
+        if let Some(discovered) = self.discovered.read().unwrap().get(&provider.name) {
+            return Ok(discovered.clone());
+        }
+
+        let metadata: ProviderMetadata = self
+            .fetch(&format!(
+                "{}/.well-known/openid-configuration",
+                provider.issuer.trim_end_matches('/')
+            ))
+            .await?;
+
+        if metadata.issuer != provider.issuer {
+            return Err(AppError::IdentityProvider(format!(
+                "discovery document is for issuer {}",
+                metadata.issuer
+            )));
+        }
+
+        let jwks: JwkSet = self.fetch(&metadata.jwks_uri).await?;
+        let discovered = Arc::new(Discovered {
+            metadata,
+            jwks: RwLock::new(jwks),
+        });
+
+        self.discovered
+            .write()
+            .unwrap()
+            .insert(provider.name.clone(), discovered.clone());
+
+        Ok(discovered)
+    }
+
+    /// Builds the URL that sends the user to the provider, with a fresh
+    /// state, nonce and PKCE challenge.
+    pub async fn authorization_request(
+        &self,
+        provider_name: &str,
+        redirect_uri: &str,
+    ) -> Result<AuthorizationRequest, AppError> {
+        use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+        use sha2::{Digest, Sha256};
+
+        let provider = self.provider(provider_name)?;
+        let discovered = self.discover(provider).await?;
+
+        let state = tokens::generate_opaque();
+        let nonce = tokens::generate_opaque();
+        let code_verifier = tokens::generate_opaque();
+        let code_challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()));
+
+        let mut url = reqwest::Url::parse(&discovered.metadata.authorization_endpoint)
+            .map_err(|e| AppError::IdentityProvider(e.to_string()))?;
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &provider.client_id)
+            .append_pair("redirect_uri", redirect_uri)
+            .append_pair("scope", &provider.scopes)
+            .append_pair("state", &state)
+            .append_pair("nonce", &nonce)
+            .append_pair("code_challenge", &code_challenge)
+            .append_pair("code_challenge_method", "S256");
+
+        Ok(AuthorizationRequest {
+            url: url.into(),
+            state,
+            nonce,
+            code_verifier,
+        })
+    }
+
+    /// Redeems an authorization code and returns the claims of the validated
+    /// ID token.
+    pub async fn exchange_code(
+        &self,
+        provider_name: &str,
+        code: &str,
+        redirect_uri: &str,
+        code_verifier: &str,
+        nonce: &str,
+    ) -> Result<IdTokenClaims, AppError> {
+        let provider = self.provider(provider_name)?;
+        let discovered = self.discover(provider).await?;
+
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", redirect_uri),
+            ("client_id", provider.client_id.as_str()),
+            ("code_verifier", code_verifier),
+        ];
+        if let Some(secret) = &provider.client_secret {
+            form.push(("client_secret", secret.as_str()));
+        }
+
+        let response = self
+            .http
+            .post(&discovered.metadata.token_endpoint)
+            .form(&form)
+            .send()
+            .await
+            .map_err(|e| AppError::IdentityProvider(e.to_string()))?;
+
+        // Rejected codes are the user's problem (expired, reused), not an outage
+        if response.status().is_client_error() {
+            return Err(AppError::Auth(
+                "The identity provider rejected the login".into(),
+            ));
+        }
+
+        let tokens: TokenResponse = response
+            .error_for_status()
+            .map_err(|e| AppError::IdentityProvider(e.to_string()))?
+            .json()
+            .await
+            .map_err(|e| AppError::IdentityProvider(e.to_string()))?;
+
+        let claims = self
+            .validate_id_token(provider, &discovered, &tokens.id_token)
+            .await?;
+
+        if claims.nonce.as_deref() != Some(nonce) {
+            return Err(AppError::Auth("Invalid ID token".into()));
+        }
+
+        Ok(claims)
+    }
+
+    /// Checks the ID token's signature against the provider's JWKS, and its
+    /// issuer, audience and expiry. Keys are fetched again once when the
+    /// token names an unknown key, so provider key rotation is picked up.
+    async fn validate_id_token(
+        &self,
+        provider: &ProviderConfig,
+        discovered: &Discovered,
+        id_token: &str,
+    ) -> Result<IdTokenClaims, AppError> {
+        let invalid = || AppError::Auth("Invalid ID token".into());
+
+        let header = decode_header(id_token).map_err(|_| invalid())?;
+        // Symmetric algorithms would let anyone holding the client secret forge tokens
+        if matches!(
+            header.alg,
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+        ) {
+            return Err(invalid());
+        }
+
+        let find_key = |jwks: &JwkSet| -> Option<Jwk> {
+            match &header.kid {
+                Some(kid) => jwks.find(kid).cloned(),
+                None if jwks.keys.len() == 1 => jwks.keys.first().cloned(),
+                None => None,
+            }
+        };
+
+        let cached = find_key(&discovered.jwks.read().unwrap());
+        let jwk = match cached {
+            Some(jwk) => jwk,
+            None => {
+                let jwks: JwkSet = self.fetch(&discovered.metadata.jwks_uri).await?;
+                let jwk = find_key(&jwks).ok_or_else(invalid)?;
+                *discovered.jwks.write().unwrap() = jwks;
+                jwk
+            }
+        };
+
+        let key = DecodingKey::from_jwk(&jwk).map_err(|_| invalid())?;
+        let mut validation = Validation::new(header.alg);
+        validation.set_audience(&[&provider.client_id]);
+        validation.set_issuer(&[&provider.issuer]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+
+        decode::<IdTokenClaims>(id_token, &key, &validation)
+            .map(|data| data.claims)
+            .map_err(|_| invalid())
+    }
+}
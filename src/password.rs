@@ -0,0 +1,210 @@
+//! Password hashing and the password policy.
+//!
+//! New hashes use Argon2id. Hashes from before the switch are bcrypt; they
+//! still verify, and are replaced with Argon2id hashes the next time their
+//! owner logs in. Hashing is slow on purpose, so it runs on the blocking
+//! thread pool rather than holding up other requests.
+
+use std::{collections::HashSet, sync::OnceLock};
+
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, SaltString},
+    Algorithm, Argon2, Params, PasswordHasher as _, PasswordVerifier as _, Version,
+};
+
+use crate::{
+    config::Config,
+    errors::{AppError, FieldError},
+    validation::MAX_PASSWORD_LENGTH,
+};
+
+/// The most common passwords from public breach corpora, one per line.
+const BREACHED_PASSWORDS: &str = include_str!("../data/breached_passwords.txt");
+
+/// Hashes and verifies passwords with the configured Argon2id parameters.
+pub struct PasswordHasher {
+    params: Params,
+    /// Checked instead of a real hash when there is no account.
+    dummy_hash: OnceLock<String>,
+}
+
+impl Default for PasswordHasher {
+    /// The OWASP recommendation: 19 MiB of memory, 2 iterations, 1 lane.
+    fn default() -> Self {
+        Self::new(19 * 1024, 2, 1).expect("valid default Argon2 parameters")
+    }
+}
+
+impl PasswordHasher {
+    pub fn new(memory_kib: u32, iterations: u32, parallelism: u32) -> Result<Self, AppError> {
+        let params = Params::new(memory_kib, iterations, parallelism, None)
+            .map_err(|e| AppError::Internal(format!("Invalid Argon2 parameters: {}", e)))?;
+
+        Ok(Self {
+            params,
+            dummy_hash: OnceLock::new(),
+        })
+    }
+
+    pub fn from_config(config: &Config) -> Result<Self, AppError> {
+        Self::new(
+            config.password_hash_memory_kib,
+            config.password_hash_iterations,
+            config.password_hash_parallelism,
+        )
+    }
+
+    fn argon2(&self) -> Argon2<'static> {
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, self.params.clone())
+    }
+
+    pub async fn hash(&self, password: &str) -> Result<String, AppError> {
+        let argon2 = self.argon2();
+        let password = password.to_string();
+
+        blocking(move || {
+            let salt = SaltString::generate(&mut OsRng);
+            argon2
+                .hash_password(password.as_bytes(), &salt)
+                .map(|hash| hash.to_string())
+                .map_err(|e| AppError::Internal(format!("Failed to hash password: {}", e)))
+        })
+        .await?
+    }
+
+    /// Checks a password against an Argon2 or legacy bcrypt hash.
+    pub async fn verify(&self, password: &str, password_hash: &str) -> Result<bool, AppError> {
+        let argon2 = self.argon2();
+        let password = password.to_string();
+        let password_hash = password_hash.to_string();
+
+        blocking(move || {
+            if is_bcrypt(&password_hash) {
+                return bcrypt::verify(&password, &password_hash).unwrap_or(false);
+            }
+
+            PasswordHash::new(&password_hash)
+                .map(|hash| argon2.verify_password(password.as_bytes(), &hash).is_ok())
+                .unwrap_or(false)
+        })
+        .await
+    }
+
+    /// Takes as long as [`verify`](Self::verify), for a login to an account
+    /// that does not exist, so response times do not reveal which do.
+    pub async fn verify_unknown(&self, password: &str) -> Result<(), AppError> {
+        let dummy_hash = match self.dummy_hash.get() {
+            Some(dummy_hash) => dummy_hash.clone(),
+            None => {
+                let dummy_hash = self.hash("no account has this password").await?;
+                self.dummy_hash.get_or_init(|| dummy_hash).clone()
+            }
+        };
+
+        self.verify(password, &dummy_hash).await?;
+        Ok(())
+    }
+
+    /// Whether the hash should be replaced: it is bcrypt, or Argon2 with other
+    /// parameters than the configured ones.
+    pub fn needs_rehash(&self, password_hash: &str) -> bool {
+        let Ok(hash) = PasswordHash::new(password_hash) else {
+            return true;
+        };
+
+        hash.algorithm != Algorithm::Argon2id.ident()
+            || hash.version != Some(Version::V0x13.into())
+            || Params::try_from(&hash).map_or(true, |params| {
+                params.m_cost() != self.params.m_cost()
+                    || params.t_cost() != self.params.t_cost()
+                    || params.p_cost() != self.params.p_cost()
+            })
+    }
+}
+
+async fn blocking<T, F>(f: F) -> Result<T, AppError>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| AppError::Internal(format!("Password hashing failed: {}", e)))
+}
+
+fn is_bcrypt(password_hash: &str) -> bool {
+    ["$2a$", "$2b$", "$2x$", "$2y$"]
+        .iter()
+        .any(|prefix| password_hash.starts_with(prefix))
+}
+
+/// What a new password must meet: a minimum length, and not being one of the
+/// commonly breached passwords.
+pub struct PasswordPolicy {
+    min_length: usize,
+    breached: HashSet<String>,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self::new(8, BREACHED_PASSWORDS)
+    }
+}
+
+impl PasswordPolicy {
+    /// `breached` holds one password per line; blank lines and lines starting
+    /// with `#` are skipped.
+    pub fn new(min_length: usize, breached: &str) -> Self {
+        Self {
+            min_length,
+            breached: breached
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(str::to_lowercase)
+                .collect(),
+        }
+    }
+
+    /// The configured minimum length, with the configured breached password
+    /// file if there is one, or else the built-in list.
+    pub fn from_config(config: &Config) -> std::io::Result<Self> {
+        match &config.breached_passwords_file {
+            Some(path) => Ok(Self::new(
+                config.password_min_length,
+                &std::fs::read_to_string(path)?,
+            )),
+            None => Ok(Self::new(config.password_min_length, BREACHED_PASSWORDS)),
+        }
+    }
+
+    /// Refuses a password that breaks the policy as an invalid `field`.
+    pub fn check(&self, field: &str, password: &str) -> Result<(), AppError> {
+        let length = password.chars().count();
+        if length as u64 > MAX_PASSWORD_LENGTH {
+            return Err(AppError::InvalidFields(vec![FieldError::new(
+                field,
+                "too_long",
+                format!("Must be at most {} characters", MAX_PASSWORD_LENGTH),
+            )]));
+        }
+
+        if length < self.min_length {
+            return Err(AppError::InvalidFields(vec![FieldError::new(
+                field,
+                "too_short",
+                format!("Password must be at least {} characters", self.min_length),
+            )]));
+        }
+
+        if self.breached.contains(&password.to_lowercase()) {
+            return Err(AppError::InvalidFields(vec![FieldError::new(
+                field,
+                "too_common",
+                "This password is too common; choose another one",
+            )]));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,294 @@
+use std::{
+    collections::HashMap,
+    sync::RwLock,
+    time::{Duration, Instant},
+};
+
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+use crate::errors::AppError;
+
+/// How long a user's token version is trusted before re-reading Postgres.
+/// Revocations made by this process take effect immediately; those made by
+/// other instances within this window.
+const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(30);
+
+/// The default lifetime of access tokens.
+const DEFAULT_ACCESS_TOKEN_TTL_MINUTES: i64 = 15;
+
+/// Server-side revocation of access tokens, backed by Postgres with an
+/// in-memory cache in front of it.
+///
+/// A token is revoked when its `jti` was revoked individually, when its session
+/// was revoked (logout, remote sign-out), or when its `ver` is older than the
+/// user's current `token_version` (logout everywhere, password change).
+pub struct RevocationStore {
+    pool: PgPool,
+    cache_ttl: Duration,
+    /// How long access tokens live, and so how long revoked sessions must be
+    /// remembered.
+    access_token_ttl: chrono::Duration,
+    /// Revoked jtis, with the token's expiry as a unix timestamp.
+    revoked: RwLock<HashMap<Uuid, i64>>,
+    /// Revoked sessions, with the time after which none of their access tokens
+    /// can still be valid anyway.
+    revoked_sessions: RwLock<HashMap<Uuid, i64>>,
+    /// Current token version per user and when it was read.
+    versions: RwLock<HashMap<Uuid, (i32, Instant)>>,
+}
+
+impl RevocationStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self::with_cache_ttl(pool, DEFAULT_CACHE_TTL)
+    }
+
+    pub fn with_cache_ttl(pool: PgPool, cache_ttl: Duration) -> Self {
+        Self {
+            pool,
+            cache_ttl,
+            access_token_ttl: chrono::Duration::minutes(DEFAULT_ACCESS_TOKEN_TTL_MINUTES),
+            revoked: RwLock::new(HashMap::new()),
+            revoked_sessions: RwLock::new(HashMap::new()),
+            versions: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Sets the access token lifetime, when it is not the default.
+    pub fn with_access_token_ttl(mut self, access_token_ttl: chrono::Duration) -> Self {
+        self.access_token_ttl = access_token_ttl;
+        self
+    }
+
+    pub async fn is_revoked(
+        &self,
+        user_id: Uuid,
+        jti: Uuid,
+        session_id: Uuid,
+        token_version: i32,
+    ) -> Result<bool, AppError> {
+        if self.revoked.read().unwrap().contains_key(&jti)
+            || self
+                .revoked_sessions
+                .read()
+                .unwrap()
+                .contains_key(&session_id)
+        {
+            return Ok(true);
+        }
+
+        let cached = self.versions.read().unwrap().get(&user_id).copied();
+        if let Some((version, read_at)) = cached {
+            if read_at.elapsed() < self.cache_ttl {
+                return Ok(version != token_version);
+            }
+        }
+
+        let row = sqlx::query_as::<_, (i32, Option<i64>, bool)>(
+            "SELECT u.token_version, EXTRACT(EPOCH FROM r.expires_at)::BIGINT,
+                    s.id IS NULL OR s.revoked_at IS NOT NULL
+             FROM users u
+             LEFT JOIN revoked_tokens r ON r.jti = $2 AND r.user_id = u.id
+             LEFT JOIN sessions s ON s.id = $3 AND s.user_id = u.id
+             WHERE u.id = $1",
+        )
+        .bind(user_id)
+        .bind(jti)
+        .bind(session_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        // Tokens of deleted users are never valid
+        let Some((version, revoked_until, session_revoked)) = row else {
+            return Ok(true);
+        };
+
+        self.versions
+            .write()
+            .unwrap()
+            .insert(user_id, (version, Instant::now()));
+
+        if let Some(expires_at) = revoked_until {
+            self.revoked.write().unwrap().insert(jti, expires_at);
+            return Ok(true);
+        }
+
+        if session_revoked {
+            self.cache_revoked_session(session_id);
+            return Ok(true);
+        }
+
+        Ok(version != token_version)
+    }
+
+    /// Revokes a single access token.
+    pub async fn revoke(&self, user_id: Uuid, jti: Uuid, expires_at: i64) -> Result<(), AppError> {
+        sqlx::query(
+            "INSERT INTO revoked_tokens (jti, user_id, expires_at)
+             VALUES ($1, $2, to_timestamp($3))
+             ON CONFLICT (jti) DO NOTHING",
+        )
+        .bind(jti)
+        .bind(user_id)
+        .bind(expires_at as f64)
+        .execute(&self.pool)
+        .await?;
+
+        self.revoked.write().unwrap().insert(jti, expires_at);
+        Ok(())
+    }
+
+    /// Revokes a session and every token issued for it. Returns `false` if the
+    /// user has no such active session.
+    pub async fn revoke_session(&self, user_id: Uuid, session_id: Uuid) -> Result<bool, AppError> {
+        let mut tx = self.pool.begin().await?;
+
+        let revoked = sqlx::query(
+            "UPDATE sessions SET revoked_at = NOW()
+             WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL",
+        )
+        .bind(session_id)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+        if revoked.rows_affected() == 0 {
+            return Ok(false);
+        }
+
+        sqlx::query(
+            "UPDATE refresh_tokens SET revoked_at = NOW()
+             WHERE session_id = $1 AND revoked_at IS NULL",
+        )
+        .bind(session_id)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        self.cache_revoked_session(session_id);
+        Ok(true)
+    }
+
+    fn cache_revoked_session(&self, session_id: Uuid) {
+        let until = (chrono::Utc::now() + self.access_token_ttl).timestamp();
+        self.revoked_sessions
+            .write()
+            .unwrap()
+            .insert(session_id, until);
+    }
+
+    /// Revokes every access and refresh token issued to the user so far.
+    pub async fn revoke_all(&self, user_id: Uuid) -> Result<(), AppError> {
+        let mut conn = self.pool.acquire().await?;
+        let version = revoke_all_tokens(&mut conn, user_id).await?;
+
+        self.versions
+            .write()
+            .unwrap()
+            .insert(user_id, (version, Instant::now()));
+        Ok(())
+    }
+
+    /// Records sessions revoked through [`revoke_other_sessions`] once its
+    /// transaction commits, so this instance rejects their tokens right away.
+    pub fn sessions_revoked(&self, session_ids: &[Uuid]) {
+        for session_id in session_ids {
+            self.cache_revoked_session(*session_id);
+        }
+    }
+
+    /// Forgets the cached token version of a user, so the next check reads
+    /// Postgres. Call after [`revoke_all_tokens`] once its transaction commits.
+    pub fn invalidate(&self, user_id: Uuid) {
+        self.versions.write().unwrap().remove(&user_id);
+    }
+
+    /// Drops revocation records for tokens that have expired anyway, along
+    /// with expired sessions and revoked ones whose access tokens have all
+    /// expired.
+    pub async fn purge_expired(&self) -> Result<u64, AppError> {
+        let tokens = sqlx::query("DELETE FROM revoked_tokens WHERE expires_at < NOW()")
+            .execute(&self.pool)
+            .await?;
+
+        let sessions = sqlx::query(
+            "DELETE FROM sessions
+             WHERE expires_at < NOW() OR revoked_at < NOW() - make_interval(secs => $1)",
+        )
+        .bind(self.access_token_ttl.num_seconds() as f64)
+        .execute(&self.pool)
+        .await?;
+
+        let now = chrono::Utc::now().timestamp();
+        self.revoked
+            .write()
+            .unwrap()
+            .retain(|_, expires_at| *expires_at >= now);
+        self.revoked_sessions
+            .write()
+            .unwrap()
+            .retain(|_, until| *until >= now);
+        self.versions
+            .write()
+            .unwrap()
+            .retain(|_, (_, read_at)| read_at.elapsed() < self.cache_ttl);
+
+        Ok(tokens.rows_affected() + sessions.rows_affected())
+    }
+}
+
+/// Bumps the user's token version and revokes their sessions, returning
+/// the new version. Must be called when the password is reset; use it
+/// directly inside a transaction, or through [`RevocationStore::revoke_all`].
+pub async fn revoke_all_tokens(conn: &mut PgConnection, user_id: Uuid) -> Result<i32, AppError> {
+    let version = sqlx::query_scalar::<_, i32>(
+        "UPDATE users SET token_version = token_version + 1 WHERE id = $1 RETURNING token_version",
+    )
+    .bind(user_id)
+    .fetch_one(&mut *conn)
+    .await?;
+
+    sqlx::query("UPDATE sessions SET revoked_at = NOW() WHERE user_id = $1 AND revoked_at IS NULL")
+        .bind(user_id)
+        .execute(&mut *conn)
+        .await?;
+
+    sqlx::query(
+        "UPDATE refresh_tokens SET revoked_at = NOW() WHERE user_id = $1 AND revoked_at IS NULL",
+    )
+    .bind(user_id)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(version)
+}
+
+/// Revokes every session of the user except `keep`, returning the revoked
+/// session ids. Used when credentials change from a session that stays signed in.
+pub async fn revoke_other_sessions(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    keep: Uuid,
+) -> Result<Vec<Uuid>, AppError> {
+    let revoked = sqlx::query_scalar::<_, Uuid>(
+        "UPDATE sessions SET revoked_at = NOW()
+         WHERE user_id = $1 AND id <> $2 AND revoked_at IS NULL
+         RETURNING id",
+    )
+    .bind(user_id)
+    .bind(keep)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    sqlx::query(
+        "UPDATE refresh_tokens SET revoked_at = NOW()
+         WHERE user_id = $1 AND session_id <> $2 AND revoked_at IS NULL",
+    )
+    .bind(user_id)
+    .bind(keep)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(revoked)
+}
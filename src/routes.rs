@@ -0,0 +1,311 @@
+//! The route table and the application router built from it.
+//!
+//! Both the server and the tests call [`app`], so they serve the same routes
+//! behind the same layers. [`routes`] lists every route with who may call it,
+//! for checks such as "every route needs credentials unless it is public".
+
+use axum::{
+    handler::Handler,
+    http::Method,
+    middleware,
+    routing::{on, MethodFilter, MethodRouter},
+    Extension, Router,
+};
+use tower_http::trace::TraceLayer;
+
+use crate::{
+    errors::AppError,
+    handlers::{
+        account, api_keys, auth, export, invitations, oidc, password_reset, tasks, two_factor,
+        verification, workspaces,
+    },
+    middleware::{
+        rate_limit::{rate_limit, RouteGroup},
+        request_id::request_id,
+    },
+    models::api_key::Scope,
+    state::AppState,
+};
+
+/// Who may call a route.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    /// Anyone; the request itself carries whatever proof is needed, such as
+    /// a password or an emailed token.
+    Public,
+    /// Callers with an access token, or an API key when the route has a
+    /// [`Scope`].
+    Authenticated,
+}
+
+/// One method and path of the API.
+pub struct Route {
+    pub method: Method,
+    pub path: &'static str,
+    pub group: RouteGroup,
+    pub access: Access,
+    /// The API key scope the route accepts, if API keys may call it.
+    pub scope: Option<Scope>,
+    handler: MethodRouter<AppState>,
+}
+
+impl Route {
+    fn new<H, T>(
+        access: Access,
+        group: RouteGroup,
+        method: Method,
+        path: &'static str,
+        handler: H,
+    ) -> Self
+    where
+        H: Handler<T, AppState>,
+        T: 'static,
+    {
+        let filter = MethodFilter::try_from(method.clone()).expect("unsupported route method");
+
+        Self {
+            method,
+            path,
+            group,
+            access,
+            scope: None,
+            handler: on(filter, handler),
+        }
+    }
+
+    fn public<H, T>(group: RouteGroup, method: Method, path: &'static str, handler: H) -> Self
+    where
+        H: Handler<T, AppState>,
+        T: 'static,
+    {
+        Self::new(Access::Public, group, method, path, handler)
+    }
+
+    fn authenticated<H, T>(
+        group: RouteGroup,
+        method: Method,
+        path: &'static str,
+        handler: H,
+    ) -> Self
+    where
+        H: Handler<T, AppState>,
+        T: 'static,
+    {
+        Self::new(Access::Authenticated, group, method, path, handler)
+    }
+
+    /// Lets API keys with `scope` call the route.
+    fn scope(mut self, scope: Scope) -> Self {
+        self.scope = Some(scope);
+        self.handler = self.handler.layer(Extension(scope));
+        self
+    }
+}
+
+/// Every route of the API.
+pub fn routes() -> Vec<Route> {
+    use RouteGroup::{Api, Auth};
+    use Scope::{TasksRead, TasksWrite, WorkspacesRead, WorkspacesWrite};
+    use {Method as M, Route as R};
+
+    vec![
+        // Auth endpoints for callers without a token get a tighter rate limit
+        R::public(Auth, M::POST, "/auth/register", auth::register),
+        R::public(Auth, M::POST, "/auth/login", auth::login),
+        R::public(
+            Auth,
+            M::POST,
+            "/auth/login/2fa",
+            two_factor::login_two_factor,
+        ),
+        R::public(
+            Auth,
+            M::GET,
+            "/auth/oidc/:provider/authorize",
+            oidc::authorize,
+        ),
+        R::public(
+            Auth,
+            M::GET,
+            "/auth/oidc/:provider/callback",
+            oidc::callback,
+        ),
+        R::public(
+            Auth,
+            M::POST,
+            "/auth/oidc/:provider/callback",
+            oidc::callback,
+        ),
+        R::public(Auth, M::POST, "/auth/refresh", auth::refresh),
+        R::public(
+            Auth,
+            M::POST,
+            "/auth/verify-email",
+            verification::verify_email,
+        ),
+        R::authenticated(
+            Auth,
+            M::POST,
+            "/auth/verify-email/resend",
+            verification::resend_verification,
+        ),
+        R::public(
+            Auth,
+            M::POST,
+            "/auth/password-reset/request",
+            password_reset::request_password_reset,
+        ),
+        R::public(
+            Auth,
+            M::POST,
+            "/auth/password-reset/confirm",
+            password_reset::confirm_password_reset,
+        ),
+        R::public(Api, M::GET, "/.well-known/jwks.json", auth::jwks),
+        R::authenticated(Api, M::POST, "/auth/logout", auth::logout),
+        R::authenticated(Api, M::POST, "/auth/logout-all", auth::logout_all),
+        R::authenticated(Api, M::GET, "/auth/sessions", auth::get_sessions),
+        R::authenticated(Api, M::DELETE, "/auth/sessions/:id", auth::delete_session),
+        // Tasks
+        R::authenticated(Api, M::GET, "/tasks", tasks::get_tasks).scope(TasksRead),
+        R::authenticated(Api, M::POST, "/tasks", tasks::create_task).scope(TasksWrite),
+        R::authenticated(Api, M::PUT, "/tasks/:id", tasks::update_task).scope(TasksWrite),
+        R::authenticated(Api, M::DELETE, "/tasks/:id", tasks::delete_task).scope(TasksWrite),
+        R::authenticated(Api, M::POST, "/tasks/:id/archive", tasks::archive_task).scope(TasksWrite),
+        R::authenticated(Api, M::POST, "/tasks/:id/unarchive", tasks::unarchive_task)
+            .scope(TasksWrite),
+        R::authenticated(Api, M::PUT, "/tasks/:id/assignee", tasks::assign_task).scope(TasksWrite),
+        R::authenticated(Api, M::DELETE, "/tasks/:id/assignee", tasks::unassign_task)
+            .scope(TasksWrite),
+        R::authenticated(Api, M::GET, "/tasks/:id/history", tasks::get_task_history)
+            .scope(TasksRead),
+        // Workspaces
+        R::authenticated(Api, M::GET, "/workspaces", workspaces::get_workspaces)
+            .scope(WorkspacesRead),
+        R::authenticated(Api, M::POST, "/workspaces", workspaces::create_workspace)
+            .scope(WorkspacesWrite),
+        R::authenticated(
+            Api,
+            M::PUT,
+            "/workspaces/:workspace_id",
+            workspaces::update_workspace,
+        )
+        .scope(WorkspacesWrite),
+        R::authenticated(
+            Api,
+            M::DELETE,
+            "/workspaces/:workspace_id",
+            workspaces::delete_workspace,
+        )
+        .scope(WorkspacesWrite),
+        R::authenticated(
+            Api,
+            M::GET,
+            "/workspaces/:workspace_id/members",
+            workspaces::get_members,
+        )
+        .scope(WorkspacesRead),
+        R::authenticated(
+            Api,
+            M::POST,
+            "/workspaces/:workspace_id/members",
+            workspaces::add_member,
+        )
+        .scope(WorkspacesWrite),
+        R::authenticated(
+            Api,
+            M::PUT,
+            "/workspaces/:workspace_id/members/:user_id",
+            workspaces::update_member,
+        )
+        .scope(WorkspacesWrite),
+        R::authenticated(
+            Api,
+            M::DELETE,
+            "/workspaces/:workspace_id/members/:user_id",
+            workspaces::remove_member,
+        )
+        .scope(WorkspacesWrite),
+        R::authenticated(
+            Api,
+            M::POST,
+            "/workspaces/:workspace_id/invitations",
+            invitations::create_invitation,
+        )
+        .scope(WorkspacesWrite),
+        // Authenticated by the signed invitation token
+        R::public(
+            Api,
+            M::POST,
+            "/invitations/:token/accept",
+            invitations::accept_invitation,
+        ),
+        // The caller's account
+        R::authenticated(Api, M::GET, "/me/assigned", tasks::get_assigned_tasks).scope(TasksRead),
+        R::authenticated(Api, M::GET, "/me/settings", account::get_settings),
+        R::authenticated(Api, M::PUT, "/me/settings", account::update_settings),
+        R::authenticated(Api, M::DELETE, "/me", account::delete_account),
+        R::authenticated(
+            Api,
+            M::DELETE,
+            "/me/deletion",
+            account::cancel_account_deletion,
+        ),
+        R::authenticated(Api, M::GET, "/me/export", export::export_account),
+        R::authenticated(Api, M::GET, "/me/api-keys", api_keys::get_api_keys),
+        R::authenticated(Api, M::POST, "/me/api-keys", api_keys::create_api_key),
+        R::authenticated(Api, M::DELETE, "/me/api-keys/:id", api_keys::delete_api_key),
+        R::authenticated(Api, M::POST, "/me/2fa", two_factor::enroll_two_factor),
+        R::authenticated(Api, M::DELETE, "/me/2fa", two_factor::disable_two_factor),
+        R::authenticated(
+            Api,
+            M::POST,
+            "/me/2fa/confirm",
+            two_factor::confirm_two_factor,
+        ),
+        R::authenticated(Api, M::POST, "/me/password", account::change_password),
+        R::authenticated(Api, M::POST, "/me/email", account::request_email_change),
+        R::authenticated(
+            Api,
+            M::POST,
+            "/me/email/confirm",
+            account::confirm_email_change,
+        ),
+    ]
+}
+
+/// Answers a path no route matches, with a problem document like any other
+/// error.
+async fn not_found() -> AppError {
+    AppError::NotFound("No such resource".into())
+}
+
+async fn method_not_allowed() -> AppError {
+    AppError::MethodNotAllowed
+}
+
+/// Builds the application: every route in [`routes`], rate limited by group,
+/// with request ids and tracing around it all.
+pub fn app(state: AppState) -> Router {
+    let mut app = Router::new();
+
+    for group in [RouteGroup::Auth, RouteGroup::Api] {
+        let grouped = routes()
+            .into_iter()
+            .filter(|route| route.group == group)
+            .fold(Router::new(), |router, route| {
+                router.route(route.path, route.handler)
+            })
+            .route_layer(middleware::from_fn_with_state(
+                (state.clone(), group),
+                rate_limit,
+            ));
+        app = app.merge(grouped);
+    }
+
+    app.fallback(not_found)
+        .method_not_allowed_fallback(method_not_allowed)
+        .with_state(state)
+        .layer(TraceLayer::new_for_http())
+        .layer(middleware::from_fn(request_id))
+}
@@ -0,0 +1,171 @@
+use std::{sync::Arc, time::Duration};
+
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+use crate::{lockout::LoginThrottle, revocation::RevocationStore};
+
+/// Archives done tasks in personal workspaces whose owner has auto-archiving
+/// enabled and whose `completed_at` is older than the owner's configured
+/// number of days. The setting is personal, so it never touches shared
+/// workspaces, where it would archive tasks for every other member.
+/// Returns the number of tasks archived.
+pub async fn archive_due_tasks(pool: &PgPool) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE tasks t SET archived_at = NOW()
+         FROM workspaces w
+         JOIN users u ON u.id = w.created_by
+         WHERE t.workspace_id = w.id
+           AND w.is_personal
+           AND u.auto_archive_after_days IS NOT NULL
+           AND t.done
+           AND t.archived_at IS NULL
+           AND t.completed_at <= NOW() - make_interval(days => u.auto_archive_after_days)",
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Spawns a background task that runs [`archive_due_tasks`] every `period`.
+pub fn spawn_auto_archive(pool: PgPool, period: Duration) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            match archive_due_tasks(&pool).await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!("Auto-archived {} completed tasks", count),
+                Err(e) => tracing::error!("Auto-archive run failed: {}", e),
+            }
+        }
+    })
+}
+
+/// Deletes a user and everything that belongs only to them. Workspaces left
+/// without other members are deleted with their tasks, and shared workspaces
+/// they alone owned pass to the highest-ranked remaining member. Tasks they
+/// created in shared workspaces stay, without a creator.
+pub async fn purge_account(conn: &mut PgConnection, user_id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE workspace_members m SET role = 'owner'
+         FROM (
+            SELECT DISTINCT ON (o.workspace_id) o.workspace_id, o.user_id
+            FROM workspace_members o
+            JOIN workspace_members me
+              ON me.workspace_id = o.workspace_id AND me.user_id = $1 AND me.role = 'owner'
+            WHERE o.user_id <> $1
+              AND NOT EXISTS (
+                SELECT 1 FROM workspace_members x
+                WHERE x.workspace_id = o.workspace_id AND x.role = 'owner' AND x.user_id <> $1
+              )
+            ORDER BY o.workspace_id, o.role, o.joined_at
+         ) heir
+         WHERE m.workspace_id = heir.workspace_id AND m.user_id = heir.user_id",
+    )
+    .bind(user_id)
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query(
+        "DELETE FROM workspaces w
+         WHERE EXISTS (
+            SELECT 1 FROM workspace_members m WHERE m.workspace_id = w.id AND m.user_id = $1
+         )
+         AND NOT EXISTS (
+            SELECT 1 FROM workspace_members m WHERE m.workspace_id = w.id AND m.user_id <> $1
+         )",
+    )
+    .bind(user_id)
+    .execute(&mut *conn)
+    .await?;
+
+    // Pending invitations name the user's address
+    sqlx::query(
+        "DELETE FROM workspace_invitations WHERE email = (SELECT email FROM users WHERE id = $1)",
+    )
+    .bind(user_id)
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query("DELETE FROM users WHERE id = $1")
+        .bind(user_id)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(())
+}
+
+/// Purges accounts whose deletion grace period has ended. An account that
+/// fails to purge is logged and left for the next run. Returns the number of
+/// accounts deleted.
+pub async fn purge_deleted_accounts(pool: &PgPool) -> Result<u64, sqlx::Error> {
+    let due =
+        sqlx::query_scalar::<_, Uuid>("SELECT id FROM users WHERE deletion_scheduled_for <= NOW()")
+            .fetch_all(pool)
+            .await?;
+
+    let mut purged = 0;
+    for user_id in due {
+        match purge_one(pool, user_id).await {
+            Ok(()) => purged += 1,
+            Err(e) => tracing::error!("Failed to purge account {}: {}", user_id, e),
+        }
+    }
+
+    Ok(purged)
+}
+
+async fn purge_one(pool: &PgPool, user_id: Uuid) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    purge_account(&mut tx, user_id).await?;
+    tx.commit().await
+}
+
+/// Spawns a background task that runs [`purge_deleted_accounts`] every `period`.
+pub fn spawn_account_purge(pool: PgPool, period: Duration) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            match purge_deleted_accounts(&pool).await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!("Purged {} deleted accounts", count),
+                Err(e) => tracing::error!("Account purge failed: {}", e),
+            }
+        }
+    })
+}
+
+/// Spawns a background task that drops expired token revocations every `period`.
+pub fn spawn_revocation_cleanup(
+    revocations: Arc<RevocationStore>,
+    period: Duration,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            if let Err(e) = revocations.purge_expired().await {
+                tracing::error!("Revocation cleanup failed: {}", e);
+            }
+        }
+    })
+}
+
+/// Spawns a background task that drops stale failed login counts every `period`.
+pub fn spawn_login_attempt_cleanup(
+    throttle: Arc<LoginThrottle>,
+    period: Duration,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            if let Err(e) = throttle.purge_expired().await {
+                tracing::error!("Login attempt cleanup failed: {}", e);
+            }
+        }
+    })
+}
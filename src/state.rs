@@ -0,0 +1,158 @@
+use std::sync::Arc;
+
+use axum::extract::FromRef;
+use sqlx::PgPool;
+
+use crate::{
+    config::{Config, ConfigError},
+    keys::JwtKeys,
+    lockout::LoginThrottle,
+    mailer::Mailer,
+    middleware::rate_limit::RateLimiter,
+    oidc::OidcClient,
+    password::{PasswordHasher, PasswordPolicy},
+    revocation::RevocationStore,
+    totp::SecretCipher,
+};
+
+/// Shared application state. Handlers extract the parts they need, e.g.
+/// `State<PgPool>`.
+#[derive(Clone)]
+pub struct AppState {
+    pub config: Arc<Config>,
+    pub pool: PgPool,
+    pub mailer: Arc<dyn Mailer>,
+    pub revocations: Arc<RevocationStore>,
+    pub oidc: Arc<OidcClient>,
+    pub jwt_keys: Arc<JwtKeys>,
+    pub login_throttle: Arc<LoginThrottle>,
+    pub rate_limiter: Arc<RateLimiter>,
+    pub password_hasher: Arc<PasswordHasher>,
+    pub password_policy: Arc<PasswordPolicy>,
+    pub totp_cipher: Arc<SecretCipher>,
+}
+
+impl AppState {
+    /// Builds every part from the configuration. Fails when a file it names,
+    /// such as a signing key, cannot be loaded.
+    pub fn new(config: Config, pool: PgPool, mailer: Arc<dyn Mailer>) -> Result<Self, ConfigError> {
+        let jwt_keys =
+            JwtKeys::from_config(&config).map_err(|e| ConfigError::invalid("JWT_KEYS", e))?;
+        let password_hasher = PasswordHasher::from_config(&config)
+            .map_err(|e| ConfigError::invalid("PASSWORD_HASH_MEMORY_KIB", e))?;
+        let password_policy = PasswordPolicy::from_config(&config)
+            .map_err(|e| ConfigError::invalid("BREACHED_PASSWORDS_FILE", e))?;
+
+        Ok(Self {
+            revocations: Arc::new(
+                RevocationStore::new(pool.clone()).with_access_token_ttl(config.access_token_ttl()),
+            ),
+            login_throttle: Arc::new(LoginThrottle::from_config(&config, pool.clone())),
+            rate_limiter: Arc::new(RateLimiter::from_config(&config)),
+            oidc: Arc::new(OidcClient::new(config.oidc_providers.clone())),
+            jwt_keys: Arc::new(jwt_keys),
+            password_hasher: Arc::new(password_hasher),
+            password_policy: Arc::new(password_policy),
+            totp_cipher: Arc::new(SecretCipher::from_config(&config)),
+            config: Arc::new(config),
+            pool,
+            mailer,
+        })
+    }
+
+    /// Replaces the configured access token keys.
+    pub fn with_jwt_keys(mut self, jwt_keys: JwtKeys) -> Self {
+        self.jwt_keys = Arc::new(jwt_keys);
+        self
+    }
+
+    /// Replaces the configured failed login tracking.
+    pub fn with_login_throttle(mut self, login_throttle: LoginThrottle) -> Self {
+        self.login_throttle = Arc::new(login_throttle);
+        self
+    }
+
+    /// Replaces the configured password hashing parameters.
+    pub fn with_password_hasher(mut self, password_hasher: PasswordHasher) -> Self {
+        self.password_hasher = Arc::new(password_hasher);
+        self
+    }
+
+    /// Replaces the configured password policy.
+    pub fn with_password_policy(mut self, password_policy: PasswordPolicy) -> Self {
+        self.password_policy = Arc::new(password_policy);
+        self
+    }
+
+    /// Replaces the configured rate limits.
+    pub fn with_rate_limiter(mut self, rate_limiter: RateLimiter) -> Self {
+        self.rate_limiter = Arc::new(rate_limiter);
+        self
+    }
+
+    /// Replaces the configured identity providers.
+    pub fn with_oidc(mut self, oidc: OidcClient) -> Self {
+        self.oidc = Arc::new(oidc);
+        self
+    }
+}
+
+impl FromRef<AppState> for Arc<Config> {
+    fn from_ref(state: &AppState) -> Self {
+        state.config.clone()
+    }
+}
+
+impl FromRef<AppState> for PgPool {
+    fn from_ref(state: &AppState) -> Self {
+        state.pool.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<dyn Mailer> {
+    fn from_ref(state: &AppState) -> Self {
+        state.mailer.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<RevocationStore> {
+    fn from_ref(state: &AppState) -> Self {
+        state.revocations.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<OidcClient> {
+    fn from_ref(state: &AppState) -> Self {
+        state.oidc.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<JwtKeys> {
+    fn from_ref(state: &AppState) -> Self {
+        state.jwt_keys.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<LoginThrottle> {
+    fn from_ref(state: &AppState) -> Self {
+        state.login_throttle.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<PasswordHasher> {
+    fn from_ref(state: &AppState) -> Self {
+        state.password_hasher.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<PasswordPolicy> {
+    fn from_ref(state: &AppState) -> Self {
+        state.password_policy.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<SecretCipher> {
+    fn from_ref(state: &AppState) -> Self {
+        state.totp_cipher.clone()
+    }
+}
@@ -0,0 +1,78 @@
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+
+use crate::errors::AppError;
+
+/// Claims of a signed single-purpose token, such as a workspace invitation.
+/// Each purpose is signed with its own key derived from the JWT secret, so these
+/// tokens are never accepted as access tokens or replayed in another flow.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SignedClaims {
+    pub sub: String,
+    pub purpose: String,
+    pub exp: usize,
+}
+
+fn purpose_key(secret: &str, purpose: &str) -> Vec<u8> {
+    format!("{}:{}", secret, purpose).into_bytes()
+}
+
+pub fn sign(
+    secret: &str,
+    purpose: &str,
+    subject: &str,
+    ttl: chrono::Duration,
+) -> Result<String, AppError> {
+    let expiration = chrono::Utc::now()
+        .checked_add_signed(ttl)
+        .expect("valid timestamp")
+        .timestamp() as usize;
+
+    let claims = SignedClaims {
+        sub: subject.to_string(),
+        purpose: purpose.to_string(),
+        exp: expiration,
+    };
+
+    encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(&purpose_key(secret, purpose)),
+    )
+    .map_err(|e| AppError::Internal(format!("Failed to sign token: {}", e)))
+}
+
+/// Verifies the signature, expiry and purpose of `token` and returns its subject.
+pub fn verify(secret: &str, token: &str, purpose: &str) -> Result<String, AppError> {
+    let claims = decode::<SignedClaims>(
+        token,
+        &DecodingKey::from_secret(&purpose_key(secret, purpose)),
+        &Validation::default(),
+    )
+    .map_err(|_| AppError::BadRequest("Invalid or expired token".into()))?
+    .claims;
+
+    if claims.purpose != purpose {
+        return Err(AppError::BadRequest("Invalid or expired token".into()));
+    }
+
+    Ok(claims.sub)
+}
+
+/// Generates a random, URL-safe opaque token with 256 bits of entropy.
+pub fn generate_opaque() -> String {
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+    use rand::RngCore;
+
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Hashes an opaque token for storage. Opaque tokens are high-entropy, so a
+/// fast unsalted hash is enough to keep the stored values useless if leaked.
+pub fn hash_opaque(token: &str) -> String {
+    use sha2::{Digest, Sha256};
+
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
@@ -0,0 +1,184 @@
+//! Time-based one-time passwords (RFC 6238) as used by authenticator apps:
+//! HMAC-SHA1, 6 digits, 30 second steps.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN},
+    rand::{SecureRandom, SystemRandom},
+};
+use sha1::Sha1;
+use subtle::ConstantTimeEq;
+use uuid::Uuid;
+
+use crate::{config::Config, errors::AppError};
+
+pub const DIGITS: u32 = 6;
+pub const PERIOD_SECS: u64 = 30;
+
+/// Steps accepted on either side of the current one, to tolerate clocks that
+/// are slightly off and codes entered just as they roll over.
+pub const ALLOWED_DRIFT: u64 = 1;
+
+const ISSUER: &str = "Task Manager";
+const SECRET_BYTES: usize = 20;
+/// Marks a stored secret as encrypted. Secrets stored before encryption are
+/// plain base32, which never contains a colon.
+const SEALED_PREFIX: &str = "v1:";
+
+/// Everything but RFC 3986 unreserved characters.
+const URI_COMPONENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Generates a random 160-bit secret, base32-encoded for authenticator apps.
+pub fn generate_secret() -> String {
+    use rand::RngCore;
+
+    let mut bytes = [0u8; SECRET_BYTES];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    BASE32_NOPAD.encode(&bytes)
+}
+
+/// The `otpauth://` URI authenticator apps read from a QR code.
+pub fn otpauth_uri(secret: &str, account: &str) -> String {
+    let issuer = utf8_percent_encode(ISSUER, URI_COMPONENT);
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        issuer,
+        utf8_percent_encode(account, URI_COMPONENT),
+        secret,
+        issuer,
+        DIGITS,
+        PERIOD_SECS
+    )
+}
+
+/// The time step containing `unix_time`.
+pub fn step_at(unix_time: u64) -> u64 {
+    unix_time / PERIOD_SECS
+}
+
+/// The code for a time step, or `None` if the secret is not valid base32.
+pub fn code_for_step(secret: &str, step: u64) -> Option<String> {
+    let key = BASE32_NOPAD.decode(secret.as_bytes()).ok()?;
+    let mut mac = Hmac::<Sha1>::new_from_slice(&key).ok()?;
+    mac.update(&step.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+
+    // Dynamic truncation, RFC 4226 section 5.3
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        digest[offset] & 0x7f,
+        digest[offset + 1],
+        digest[offset + 2],
+        digest[offset + 3],
+    ]);
+
+    Some(format!(
+        "{:0width$}",
+        binary % 10u32.pow(DIGITS),
+        width = DIGITS as usize
+    ))
+}
+
+/// Checks `code` against the steps within `drift` of `unix_time` and returns
+/// the step it matched. Callers store that step so a code cannot be used twice.
+pub fn verify(secret: &str, code: &str, unix_time: u64, drift: u64) -> Option<u64> {
+    let code = code.trim();
+    if code.len() != DIGITS as usize || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let current = step_at(unix_time);
+    (current.saturating_sub(drift)..=current + drift).find(|&step| {
+        code_for_step(secret, step)
+            .is_some_and(|expected| expected.as_bytes().ct_eq(code.as_bytes()).into())
+    })
+}
+
+/// Encrypts TOTP secrets for storage with AES-256-GCM, so that reading the
+/// database is not enough to produce codes. Each secret is bound to its
+/// user, and cannot be moved to another account.
+pub struct SecretCipher {
+    key: LessSafeKey,
+}
+
+impl SecretCipher {
+    pub fn new(key: &[u8; 32]) -> Self {
+        let key = UnboundKey::new(&AES_256_GCM, key).expect("AES-256 takes a 32-byte key");
+        Self {
+            key: LessSafeKey::new(key),
+        }
+    }
+
+    /// Uses `TOTP_ENCRYPTION_KEY`, or a key derived from `JWT_SECRET` when it
+    /// is not set.
+    pub fn from_config(config: &Config) -> Self {
+        use sha2::{Digest, Sha256};
+
+        match &config.totp_encryption_key {
+            Some(key) => Self::new(key),
+            None => {
+                let mut hasher = Sha256::new();
+                hasher.update(b"task-manager totp secret encryption\0");
+                hasher.update(config.jwt_secret.as_bytes());
+                Self::new(&hasher.finalize().into())
+            }
+        }
+    }
+
+    /// The secret as it is stored for `user_id`.
+    pub fn seal(&self, user_id: Uuid, secret: &str) -> Result<String, AppError> {
+        let failed = |_| AppError::Internal("Failed to encrypt a two-factor secret".into());
+
+        let mut nonce = [0u8; NONCE_LEN];
+        SystemRandom::new().fill(&mut nonce).map_err(failed)?;
+        let mut sealed = secret.as_bytes().to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(user_id.as_bytes()),
+                &mut sealed,
+            )
+            .map_err(failed)?;
+
+        Ok(format!(
+            "{}{}",
+            SEALED_PREFIX,
+            STANDARD.encode([nonce.as_slice(), &sealed].concat())
+        ))
+    }
+
+    /// The secret in a value stored for `user_id`. A value stored before
+    /// secrets were encrypted is returned as it is; see [`is_sealed`].
+    pub fn open(&self, user_id: Uuid, stored: &str) -> Result<String, AppError> {
+        let Some(encoded) = stored.strip_prefix(SEALED_PREFIX) else {
+            return Ok(stored.to_string());
+        };
+        let failed = || AppError::Internal("Failed to decrypt a two-factor secret".into());
+
+        let mut sealed = STANDARD.decode(encoded).map_err(|_| failed())?;
+        if sealed.len() < NONCE_LEN {
+            return Err(failed());
+        }
+        let mut ciphertext = sealed.split_off(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(&sealed).map_err(|_| failed())?;
+        let secret = self
+            .key
+            .open_in_place(nonce, Aad::from(user_id.as_bytes()), &mut ciphertext)
+            .map_err(|_| failed())?;
+
+        String::from_utf8(secret.to_vec()).map_err(|_| failed())
+    }
+}
+
+/// Whether a stored secret is encrypted, rather than left from before
+/// secrets were.
+pub fn is_sealed(stored: &str) -> bool {
+    stored.starts_with(SEALED_PREFIX)
+}
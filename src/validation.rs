@@ -0,0 +1,89 @@
+use axum::{
+    async_trait,
+    extract::{FromRequest, Request},
+};
+use email_address::{EmailAddress, Options};
+use serde::de::DeserializeOwned;
+use validator::{Validate, ValidationError};
+
+use crate::{errors::AppError, extract::Json};
+
+/// The longest task title, in characters.
+pub const MAX_TITLE_LENGTH: u64 = 200;
+/// The longest task description, in characters.
+pub const MAX_DESCRIPTION_LENGTH: u64 = 10_000;
+/// The longest email address, per RFC 5321.
+pub const MAX_EMAIL_LENGTH: u64 = 254;
+/// The longest password. Minimums are up to the [`PasswordPolicy`](crate::password::PasswordPolicy).
+pub const MAX_PASSWORD_LENGTH: u64 = 128;
+/// The longest API key name, in characters.
+pub const MAX_API_KEY_NAME_LENGTH: u64 = 100;
+
+/// A JSON body that is checked against its `#[validate(...)]` rules before
+/// the handler runs. Invalid fields are refused with
+/// `422 Unprocessable Entity` and listed in the problem's `errors`.
+pub struct ValidatedJson<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for ValidatedJson<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<T>::from_request(request, state).await?;
+        value.validate()?;
+
+        Ok(ValidatedJson(value))
+    }
+}
+
+/// Validates an email address and returns its canonical form: surrounding
+/// whitespace removed and the domain lowercased. The local part is kept as
+/// typed, since mail servers may treat it case-sensitively.
+pub fn normalize_email(email: &str) -> Result<String, AppError> {
+    let options = Options::default()
+        .with_required_tld()
+        .without_domain_literal()
+        .without_display_text();
+
+    let address = EmailAddress::parse_with_options(email.trim(), options)
+        .map_err(|_| AppError::BadRequest("Invalid email address".into()))?;
+
+    Ok(format!(
+        "{}@{}",
+        address.local_part(),
+        address.domain().to_lowercase()
+    ))
+}
+
+/// The form of a typed address to look an existing account up by: the
+/// canonical form, or the address as typed when it does not validate.
+/// Accounts created before validation may have such addresses.
+pub fn lookup_email(email: &str) -> String {
+    normalize_email(email).unwrap_or_else(|_| email.trim().to_string())
+}
+
+/// A `#[validate(custom(...))]` rule for text that must not be empty or only
+/// whitespace.
+pub fn not_blank(value: &str) -> Result<(), ValidationError> {
+    if value.trim().is_empty() {
+        let mut error = ValidationError::new("blank");
+        error.message = Some("Must not be blank".into());
+        return Err(error);
+    }
+    Ok(())
+}
+
+/// A `#[validate(custom(...))]` rule for email addresses that
+/// [`normalize_email`] accepts.
+pub fn valid_email(value: &str) -> Result<(), ValidationError> {
+    if normalize_email(value).is_err() {
+        let mut error = ValidationError::new("invalid_email");
+        error.message = Some("Must be a valid email address".into());
+        return Err(error);
+    }
+    Ok(())
+}
@@ -0,0 +1,185 @@
+// Admin command tests: the operations behind `task-manager user ...`,
+// `tasks purge-archived` and `migrate`, checked through the API.
+//
+// Needs the database, like integration_tests:
+//   cargo test --test admin_tests
+//
+use axum::http::StatusCode;
+use serde_json::json;
+use sqlx::PgPool;
+use task_manager::{
+    admin,
+    db::{self, Revert},
+    errors::AppError,
+};
+
+mod common;
+use common::*;
+
+async fn login(app: &axum::Router, email: &str, password: &str) -> StatusCode {
+    send_json(
+        app,
+        "POST",
+        "/auth/login",
+        None,
+        Some(json!({ "email": email, "password": password })),
+    )
+    .await
+    .0
+}
+
+#[sqlx::test]
+async fn test_create_user(pool: PgPool) {
+    let state = test_state(pool);
+    let app = create_test_app_with_state(state.clone());
+
+    let user = admin::create_user(&state, "ops@Example.COM", "adminpass123", true)
+        .await
+        .unwrap();
+    assert_eq!(user.email, "ops@example.com");
+    assert!(user.email_verified_at.is_some());
+    assert_eq!(
+        login(&app, "ops@example.com", "adminpass123").await,
+        StatusCode::OK
+    );
+
+    // Same rules as registering
+    let result = admin::create_user(&state, "ops@example.com", "adminpass123", false).await;
+    assert!(matches!(result, Err(AppError::Conflict(_))));
+    let result = admin::create_user(&state, "weak@example.com", "short", false).await;
+    assert!(matches!(result, Err(AppError::InvalidFields(_))));
+}
+
+#[sqlx::test]
+async fn test_reset_password(pool: PgPool) {
+    let state = test_state(pool);
+    let app = create_test_app_with_state(state.clone());
+    let token = create_test_user_with_token(&app, "forgot@example.com").await;
+
+    admin::reset_password(&state, "forgot@example.com", "replacement123")
+        .await
+        .unwrap();
+
+    let (status, _) = send_json(&app, "GET", "/tasks", Some(&token), None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        login(&app, "forgot@example.com", "testpassword123").await,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        login(&app, "forgot@example.com", "replacement123").await,
+        StatusCode::OK
+    );
+
+    let result = admin::reset_password(&state, "nobody@example.com", "replacement123").await;
+    assert!(matches!(result, Err(AppError::NotFound(_))));
+}
+
+#[sqlx::test]
+async fn test_disable_and_enable_user(pool: PgPool) {
+    let state = test_state(pool);
+    let app = create_test_app_with_state(state.clone());
+    let token = create_test_user_with_token(&app, "suspended@example.com").await;
+
+    let (_, created) = send_json(
+        &app,
+        "POST",
+        "/me/api-keys",
+        Some(&token),
+        Some(json!({ "name": "Script", "scopes": ["tasks:read"] })),
+    )
+    .await;
+    let key = created["token"].as_str().unwrap();
+
+    admin::set_disabled(&state, "suspended@example.com", true)
+        .await
+        .unwrap();
+
+    let (status, _) = send_json(&app, "GET", "/tasks", Some(&token), None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = send_json(&app, "GET", "/tasks", Some(key), None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        login(&app, "suspended@example.com", "testpassword123").await,
+        StatusCode::FORBIDDEN
+    );
+
+    admin::set_disabled(&state, "suspended@example.com", false)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        login(&app, "suspended@example.com", "testpassword123").await,
+        StatusCode::OK
+    );
+    let (status, _) = send_json(&app, "GET", "/tasks", Some(key), None).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[sqlx::test]
+async fn test_purge_archived_tasks(pool: PgPool) {
+    let app = create_test_app(pool.clone()).await;
+    let token = create_test_user_with_token(&app, "tidy@example.com").await;
+
+    let old = create_test_task(&app, &token, "Archived long ago").await;
+    let recent = create_test_task(&app, &token, "Archived yesterday").await;
+    create_test_task(&app, &token, "Still open").await;
+
+    for (task, days) in [(&old, 45), (&recent, 1)] {
+        sqlx::query(
+            "UPDATE tasks SET archived_at = NOW() - make_interval(days => $2) WHERE id = $1::uuid",
+        )
+        .bind(task["id"].as_str().unwrap())
+        .bind(days)
+        .execute(&pool)
+        .await
+        .unwrap();
+    }
+
+    assert_eq!(admin::count_archived_tasks(&pool, 30).await.unwrap(), 1);
+    assert_eq!(list_test_tasks(&app, &token, true).await.len(), 2);
+
+    assert_eq!(admin::purge_archived_tasks(&pool, 30).await.unwrap(), 1);
+    assert_eq!(list_test_tasks(&app, &token, true).await.len(), 1);
+    assert_eq!(list_test_tasks(&app, &token, false).await.len(), 1);
+
+    assert!(admin::count_archived_tasks(&pool, -1).await.is_err());
+    assert!(admin::purge_archived_tasks(&pool, -1).await.is_err());
+}
+
+#[sqlx::test]
+async fn test_migration_status(pool: PgPool) {
+    let status = db::migration_status(&pool).await.unwrap();
+
+    let ups = db::MIGRATOR
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration())
+        .count();
+    assert_eq!(status.len(), ups);
+    assert!(status.iter().all(|m| m.applied && !m.modified));
+}
+
+#[sqlx::test]
+async fn test_revert_latest_migration(pool: PgPool) {
+    let applied = db::migration_status(&pool).await.unwrap();
+
+    // Every migration since disabling users has a down script
+    for latest in applied.iter().rev().take(3) {
+        assert_eq!(
+            db::revert_latest_migration(&pool).await.unwrap(),
+            Revert::Reverted(latest.version, latest.description.clone())
+        );
+    }
+    let status = db::migration_status(&pool).await.unwrap();
+    assert!(status.iter().rev().take(3).all(|m| !m.applied));
+
+    let before = &applied[applied.len() - 4];
+    assert_eq!(
+        db::revert_latest_migration(&pool).await.unwrap(),
+        Revert::Irreversible(before.version, before.description.clone())
+    );
+
+    db::MIGRATOR.run(&pool).await.unwrap();
+    let status = db::migration_status(&pool).await.unwrap();
+    assert!(status.iter().all(|m| m.applied && !m.modified));
+}
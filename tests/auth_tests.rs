@@ -1,13 +1,26 @@
 // Unit tests for JWT authentication
 use jsonwebtoken::{decode, DecodingKey, Validation};
+use task_manager::keys::JwtKeys;
 use task_manager::middleware::auth::{create_jwt, Claims};
+use task_manager::tokens;
+
+const SECRET: &str = "test_secret_key";
+
+fn ttl() -> chrono::Duration {
+    chrono::Duration::minutes(15)
+}
 
 #[test]
 fn test_create_jwt_success() {
-    std::env::set_var("JWT_SECRET", "test_secret_key");
     let user_id = "550e8400-e29b-41d4-a716-446655440000";
 
-    let result = create_jwt(user_id);
+    let result = create_jwt(
+        &JwtKeys::hmac(SECRET),
+        ttl(),
+        user_id,
+        uuid::Uuid::new_v4(),
+        0,
+    );
     assert!(result.is_ok());
 
     let token = result.unwrap();
@@ -16,16 +29,15 @@ fn test_create_jwt_success() {
 
 #[test]
 fn test_jwt_contains_valid_claims() {
-    std::env::set_var("JWT_SECRET", "test_secret_key");
     let user_id = "550e8400-e29b-41d4-a716-446655440000";
+    let session_id = uuid::Uuid::new_v4();
 
-    let token = create_jwt(user_id).unwrap();
+    let token = create_jwt(&JwtKeys::hmac(SECRET), ttl(), user_id, session_id, 0).unwrap();
 
     // Decode and verify the token
-    let secret = std::env::var("JWT_SECRET").unwrap();
     let token_data = decode::<Claims>(
         &token,
-        &DecodingKey::from_secret(secret.as_bytes()),
+        &DecodingKey::from_secret(SECRET.as_bytes()),
         &Validation::default(),
     );
 
@@ -33,19 +45,109 @@ fn test_jwt_contains_valid_claims() {
     let claims = token_data.unwrap().claims;
     assert_eq!(claims.sub, user_id);
     assert!(claims.exp > chrono::Utc::now().timestamp() as usize);
+    assert!(uuid::Uuid::parse_str(&claims.jti).is_ok());
+    assert_eq!(claims.sid, session_id.to_string());
+    assert_eq!(claims.ver, 0);
 }
 
 #[test]
 fn test_invalid_jwt_fails() {
-    std::env::set_var("JWT_SECRET", "test_secret_key");
     let invalid_token = "invalid.token.here";
 
-    let secret = std::env::var("JWT_SECRET").unwrap();
     let result = decode::<Claims>(
         invalid_token,
-        &DecodingKey::from_secret(secret.as_bytes()),
+        &DecodingKey::from_secret(SECRET.as_bytes()),
         &Validation::default(),
     );
 
     assert!(result.is_err());
 }
+
+#[test]
+fn test_signed_token_roundtrip() {
+    let token = tokens::sign(
+        SECRET,
+        "invitation",
+        "subject-123",
+        chrono::Duration::hours(1),
+    )
+    .unwrap();
+    assert_eq!(
+        tokens::verify(SECRET, &token, "invitation").unwrap(),
+        "subject-123"
+    );
+
+    // Another deployment's secret does not verify it
+    assert!(tokens::verify("other_secret", &token, "invitation").is_err());
+}
+
+#[test]
+fn test_signed_token_rejects_other_purpose() {
+    let token = tokens::sign(
+        SECRET,
+        "invitation",
+        "subject-123",
+        chrono::Duration::hours(1),
+    )
+    .unwrap();
+    assert!(tokens::verify(SECRET, &token, "password-reset").is_err());
+
+    // Purpose tokens are not valid access tokens either
+    let result = decode::<Claims>(
+        &token,
+        &DecodingKey::from_secret(SECRET.as_bytes()),
+        &Validation::default(),
+    );
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_signed_token_rejects_expired() {
+    let token = tokens::sign(
+        SECRET,
+        "invitation",
+        "subject-123",
+        chrono::Duration::hours(-1),
+    )
+    .unwrap();
+    assert!(tokens::verify(SECRET, &token, "invitation").is_err());
+}
+
+#[test]
+fn test_opaque_tokens_are_unique_and_hashed() {
+    let first = tokens::generate_opaque();
+    let second = tokens::generate_opaque();
+
+    assert_ne!(first, second);
+    assert_eq!(first.len(), 43);
+    assert_eq!(tokens::hash_opaque(&first), tokens::hash_opaque(&first));
+    assert_ne!(tokens::hash_opaque(&first), first);
+}
+
+#[test]
+fn test_jwt_ids_are_unique() {
+    let user_id = "550e8400-e29b-41d4-a716-446655440000";
+
+    let jtis: Vec<String> = (0..2)
+        .map(|_| {
+            let token = create_jwt(
+                &JwtKeys::hmac(SECRET),
+                ttl(),
+                user_id,
+                uuid::Uuid::new_v4(),
+                3,
+            )
+            .unwrap();
+            decode::<Claims>(
+                &token,
+                &DecodingKey::from_secret(SECRET.as_bytes()),
+                &Validation::default(),
+            )
+            .unwrap()
+            .claims
+            .jti
+        })
+        .collect();
+
+    assert_ne!(jtis[0], jtis[1]);
+}
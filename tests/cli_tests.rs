@@ -0,0 +1,78 @@
+// Unit tests for parsing the command line
+use clap::{CommandFactory, Parser};
+use task_manager::cli::{Cli, Command, MigrateCommand, TasksCommand, UserCommand};
+
+fn parse(args: &[&str]) -> Option<Command> {
+    Cli::try_parse_from(std::iter::once("task-manager").chain(args.iter().copied()))
+        .unwrap()
+        .command
+}
+
+#[test]
+fn test_command_definitions() {
+    Cli::command().debug_assert();
+}
+
+#[test]
+fn test_serves_by_default() {
+    assert!(parse(&[]).is_none());
+    assert!(matches!(
+        parse(&["serve", "--no-migrate"]),
+        Some(Command::Serve { no_migrate: true })
+    ));
+}
+
+#[test]
+fn test_parses_subcommands() {
+    assert!(matches!(
+        parse(&["migrate", "status"]),
+        Some(Command::Migrate(MigrateCommand::Status))
+    ));
+    assert!(matches!(
+        parse(&["user", "create", "ops@example.com", "--verified"]),
+        Some(Command::User(UserCommand::Create { email, password_stdin: false, verified: true }))
+            if email == "ops@example.com"
+    ));
+    assert!(matches!(
+        parse(&[
+            "user",
+            "reset-password",
+            "ops@example.com",
+            "--password-stdin"
+        ]),
+        Some(Command::User(UserCommand::ResetPassword {
+            password_stdin: true,
+            ..
+        }))
+    ));
+    assert!(matches!(
+        parse(&["tasks", "purge-archived", "--older-than-days", "7"]),
+        Some(Command::Tasks(TasksCommand::PurgeArchived {
+            older_than_days: 7,
+            yes: false
+        }))
+    ));
+    // The name the command was first asked for still works
+    assert!(matches!(
+        parse(&["tasks", "purge-trash", "--older-than-days", "7"]),
+        Some(Command::Tasks(TasksCommand::PurgeArchived {
+            older_than_days: 7,
+            yes: false
+        }))
+    ));
+    assert!(matches!(
+        parse(&["tasks", "purge-archived", "--older-than-days", "7", "--yes"]),
+        Some(Command::Tasks(TasksCommand::PurgeArchived {
+            older_than_days: 7,
+            yes: true
+        }))
+    ));
+}
+
+#[test]
+fn test_rejects_unknown_commands() {
+    assert!(Cli::try_parse_from(["task-manager", "user", "delete", "a@example.com"]).is_err());
+    assert!(Cli::try_parse_from(["task-manager", "user", "disable"]).is_err());
+    // Deleting archived tasks needs an explicit age
+    assert!(Cli::try_parse_from(["task-manager", "tasks", "purge-archived", "--yes"]).is_err());
+}
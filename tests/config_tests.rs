@@ -0,0 +1,272 @@
+// Unit tests for loading and validating configuration
+use std::{net::SocketAddr, path::PathBuf};
+
+use task_manager::{
+    authz::VerifiedAction,
+    config::{Config, ConfigError},
+    lockout::AttemptStoreKind,
+    mailer::{MailerKind, SmtpTls},
+};
+
+const REQUIRED: [(&str, &str); 2] = [
+    ("DATABASE_URL", "postgres://localhost/task_manager"),
+    ("JWT_SECRET", "secret"),
+];
+
+fn load(file: &str, vars: &[(&str, &str)]) -> Result<Config, ConfigError> {
+    Config::from_sources(file, REQUIRED.iter().chain(vars).copied())
+}
+
+fn invalid_key(result: Result<Config, ConfigError>) -> String {
+    match result {
+        Err(ConfigError::Invalid { key, .. }) => key,
+        Err(e) => panic!("expected an invalid setting, got {}", e),
+        Ok(_) => panic!("expected an invalid setting"),
+    }
+}
+
+#[test]
+fn test_defaults() {
+    let config = load("", &[]).unwrap();
+
+    assert_eq!(
+        config.bind_address,
+        "0.0.0.0:3000".parse::<SocketAddr>().unwrap()
+    );
+    assert_eq!(config.database_max_connections, 10);
+    assert!(config.migrate_on_startup);
+    assert_eq!(config.app_base_url, "http://localhost:3000");
+    assert_eq!(config.access_token_ttl(), chrono::Duration::minutes(15));
+    assert_eq!(config.refresh_token_ttl_days, 30);
+    assert_eq!(config.mailer, MailerKind::Log);
+    assert_eq!(config.login_attempt_store, AttemptStoreKind::Postgres);
+    assert_eq!(config.lockout.account_threshold, 5);
+    assert_eq!(config.rate_limit_auth_per_minute, 20);
+    assert_eq!(config.background_job_interval().as_secs(), 3600);
+    assert!(config.jwt_keys.is_empty());
+    assert!(config.oidc_providers.is_empty());
+    assert!(!config
+        .trusted_proxies
+        .contains(&"127.0.0.1".parse().unwrap()));
+    assert!(!config.requires_verified_email(VerifiedAction::CreateWorkspace));
+}
+
+#[test]
+fn test_required_settings() {
+    let result = Config::from_sources("", [("JWT_SECRET", "secret")]);
+    assert!(matches!(result, Err(ConfigError::Missing(key)) if key == "DATABASE_URL"));
+
+    // Empty values count as unset
+    let result = Config::from_sources(
+        "",
+        [
+            ("DATABASE_URL", "postgres://localhost/db"),
+            ("JWT_SECRET", ""),
+        ],
+    );
+    assert!(matches!(result, Err(ConfigError::Missing(key)) if key == "JWT_SECRET"));
+}
+
+#[test]
+fn test_reads_variables() {
+    let config = load(
+        "",
+        &[
+            ("BIND_ADDRESS", "127.0.0.1:8080"),
+            ("DATABASE_MAX_CONNECTIONS", "25"),
+            ("MIGRATE_ON_STARTUP", "false"),
+            ("APP_BASE_URL", "https://tasks.example.com/"),
+            ("JWT_KEYS", "keys/a.pem, keys/b.pem"),
+            ("REQUIRE_VERIFIED_EMAIL", "create_workspace"),
+            ("MAILER", "file"),
+            ("MAIL_DIR", "/var/mail"),
+            ("LOGIN_ATTEMPT_STORE", "memory"),
+            ("LOGIN_LOCKOUT_BASE_SECS", "10"),
+            ("TRUSTED_PROXIES", "10.0.0.0/8, ::1"),
+        ],
+    )
+    .unwrap();
+
+    assert_eq!(config.bind_address.port(), 8080);
+    assert_eq!(config.database_max_connections, 25);
+    assert!(!config.migrate_on_startup);
+    assert_eq!(config.app_base_url, "https://tasks.example.com");
+    assert_eq!(
+        config.jwt_keys,
+        vec![PathBuf::from("keys/a.pem"), PathBuf::from("keys/b.pem")]
+    );
+    assert!(config.requires_verified_email(VerifiedAction::CreateWorkspace));
+    assert!(!config.requires_verified_email(VerifiedAction::InviteMembers));
+    assert_eq!(config.mailer, MailerKind::File(PathBuf::from("/var/mail")));
+    assert_eq!(config.login_attempt_store, AttemptStoreKind::Memory);
+    assert_eq!(config.lockout.base_lockout.as_secs(), 10);
+    assert!(config
+        .trusted_proxies
+        .contains(&"10.1.2.3".parse().unwrap()));
+    assert!(config.trusted_proxies.contains(&"::1".parse().unwrap()));
+    assert!(!config
+        .trusted_proxies
+        .contains(&"192.0.2.1".parse().unwrap()));
+}
+
+#[test]
+fn test_background_job_interval_keeps_its_old_name() {
+    let config = load("", &[("AUTO_ARCHIVE_INTERVAL_SECS", "60")]).unwrap();
+    assert_eq!(config.background_job_interval().as_secs(), 60);
+
+    let config = load(
+        "",
+        &[
+            ("AUTO_ARCHIVE_INTERVAL_SECS", "60"),
+            ("BACKGROUND_JOB_INTERVAL_SECS", "300"),
+        ],
+    )
+    .unwrap();
+    assert_eq!(config.background_job_interval().as_secs(), 300);
+
+    let result = load("", &[("BACKGROUND_JOB_INTERVAL_SECS", "0")]);
+    assert_eq!(invalid_key(result), "BACKGROUND_JOB_INTERVAL_SECS");
+}
+
+#[test]
+fn test_totp_encryption_key_must_be_32_bytes() {
+    assert_eq!(load("", &[]).unwrap().totp_encryption_key, None);
+
+    let key = "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=";
+    let config = load("", &[("TOTP_ENCRYPTION_KEY", key)]).unwrap();
+    let expected: Vec<u8> = (0..32).collect();
+    assert_eq!(config.totp_encryption_key.unwrap().as_slice(), expected);
+
+    for key in ["AAECAwQFBgcICQoLDA0ODw==", "not base64"] {
+        let result = load("", &[("TOTP_ENCRYPTION_KEY", key)]);
+        assert_eq!(invalid_key(result), "TOTP_ENCRYPTION_KEY");
+    }
+}
+
+#[test]
+fn test_file_settings_yield_to_variables() {
+    let file = r#"
+        database_max_connections = 5
+        access_token_ttl_minutes = 30
+        jwt_keys = ["keys/a.pem", "keys/b.pem"]
+        oidc_providers = "acme"
+        oidc_acme_issuer = "https://id.acme.example"
+        oidc_acme_client_id = "tasks"
+    "#;
+    let config = load(file, &[("ACCESS_TOKEN_TTL_MINUTES", "5")]).unwrap();
+
+    assert_eq!(config.database_max_connections, 5);
+    assert_eq!(config.access_token_ttl(), chrono::Duration::minutes(5));
+    assert_eq!(config.jwt_keys.len(), 2);
+    assert_eq!(config.oidc_providers.len(), 1);
+    assert_eq!(config.oidc_providers[0].name, "acme");
+    assert_eq!(config.oidc_providers[0].issuer, "https://id.acme.example");
+    assert_eq!(config.oidc_providers[0].scopes, "openid email profile");
+}
+
+#[test]
+fn test_rejects_invalid_values() {
+    assert_eq!(
+        invalid_key(load("", &[("DATABASE_MAX_CONNECTIONS", "lots")])),
+        "DATABASE_MAX_CONNECTIONS"
+    );
+    assert_eq!(
+        invalid_key(load("", &[("DATABASE_MAX_CONNECTIONS", "0")])),
+        "DATABASE_MAX_CONNECTIONS"
+    );
+    assert_eq!(
+        invalid_key(load("", &[("BIND_ADDRESS", "localhost")])),
+        "BIND_ADDRESS"
+    );
+    assert_eq!(
+        invalid_key(load("", &[("ACCESS_TOKEN_TTL_MINUTES", "-1")])),
+        "ACCESS_TOKEN_TTL_MINUTES"
+    );
+    assert_eq!(
+        invalid_key(load("", &[("APP_BASE_URL", "tasks.example.com")])),
+        "APP_BASE_URL"
+    );
+    assert_eq!(invalid_key(load("", &[("MAILER", "pigeon")])), "MAILER");
+    assert_eq!(
+        invalid_key(load("", &[("TRUSTED_PROXIES", "proxy.internal")])),
+        "TRUSTED_PROXIES"
+    );
+    assert_eq!(
+        invalid_key(load("", &[("MIGRATE_ON_STARTUP", "no")])),
+        "MIGRATE_ON_STARTUP"
+    );
+    assert_eq!(
+        invalid_key(load("", &[("REQUIRE_VERIFIED_EMAIL", "delete_everything")])),
+        "REQUIRE_VERIFIED_EMAIL"
+    );
+    assert_eq!(
+        invalid_key(load(
+            "",
+            &[
+                ("LOGIN_LOCKOUT_BASE_SECS", "600"),
+                ("LOGIN_LOCKOUT_MAX_SECS", "60")
+            ]
+        )),
+        "LOGIN_LOCKOUT_MAX_SECS"
+    );
+}
+
+#[test]
+fn test_smtp_mailer_settings() {
+    let config = load(
+        "",
+        &[
+            ("MAILER", "smtp"),
+            ("SMTP_HOST", "smtp.example.com"),
+            ("SMTP_TLS", "tls"),
+            ("SMTP_USERNAME", "tasks"),
+            ("SMTP_PASSWORD", "secret"),
+            ("MAIL_FROM", "Tasks <noreply@example.com>"),
+        ],
+    )
+    .unwrap();
+
+    let MailerKind::Smtp(smtp) = config.mailer else {
+        panic!("expected the SMTP mailer");
+    };
+    assert_eq!(smtp.host, "smtp.example.com");
+    assert_eq!(smtp.port, 465);
+    assert_eq!(smtp.tls, SmtpTls::Tls);
+    assert_eq!(smtp.username.as_deref(), Some("tasks"));
+    assert_eq!(smtp.from.email.to_string(), "noreply@example.com");
+
+    let smtp = |vars: &[(&str, &str)]| {
+        let base = [("MAILER", "smtp"), ("SMTP_HOST", "localhost")];
+        load("", &[&base[..], vars].concat())
+    };
+    assert!(matches!(smtp(&[]), Err(ConfigError::Missing(key)) if key == "MAIL_FROM"));
+    assert_eq!(invalid_key(smtp(&[("MAIL_FROM", "nobody")])), "MAIL_FROM");
+    assert_eq!(
+        invalid_key(smtp(&[("MAIL_FROM", "a@example.com"), ("SMTP_TLS", "ssl")])),
+        "SMTP_TLS"
+    );
+    let config = smtp(&[("MAIL_FROM", "a@example.com"), ("SMTP_TLS", "none")]).unwrap();
+    assert!(matches!(config.mailer, MailerKind::Smtp(s) if s.port == 25));
+}
+
+#[test]
+fn test_oidc_providers_need_issuer_and_client_id() {
+    let result = load("", &[("OIDC_PROVIDERS", "acme")]);
+    assert!(matches!(result, Err(ConfigError::Missing(key)) if key == "OIDC_ACME_ISSUER"));
+}
+
+#[test]
+fn test_rejects_malformed_file() {
+    let result = load("database_max_connections = ", &[]);
+    assert!(matches!(result, Err(ConfigError::File { .. })));
+}
+
+#[test]
+fn test_errors_name_the_setting() {
+    let error = load("", &[("REFRESH_TOKEN_TTL_DAYS", "soon")])
+        .err()
+        .unwrap();
+    assert!(error
+        .to_string()
+        .starts_with("Invalid REFRESH_TOKEN_TTL_DAYS:"));
+}
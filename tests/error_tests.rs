@@ -1,7 +1,7 @@
 // Unit tests for error handling
 use axum::http::StatusCode;
 use axum::response::IntoResponse;
-use task_manager::errors::AppError;
+use task_manager::errors::{AppError, FieldError};
 
 #[test]
 fn test_app_error_display() {
@@ -28,4 +28,103 @@ fn test_error_status_codes() {
     let error = AppError::BadRequest("test".to_string());
     let response = error.into_response();
     assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let error = AppError::TooManyRequests("test".to_string());
+    let response = error.into_response();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    let error = AppError::Conflict("test".to_string());
+    let response = error.into_response();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    let error = AppError::Validation("test".to_string());
+    let response = error.into_response();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let response = AppError::MethodNotAllowed.into_response();
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+    let response = AppError::UnsupportedMediaType("test".to_string()).into_response();
+    assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+    let response = AppError::PayloadTooLarge.into_response();
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+async fn body_json(error: AppError) -> serde_json::Value {
+    let body = axum::body::to_bytes(error.into_response().into_body(), usize::MAX)
+        .await
+        .unwrap();
+    serde_json::from_slice(&body).unwrap()
+}
+
+#[tokio::test]
+async fn test_error_body_has_code() {
+    let body = body_json(AppError::Conflict("Email is already in use".to_string())).await;
+    assert_eq!(body["detail"], "Email is already in use");
+    assert_eq!(body["code"], "conflict");
+
+    let body = body_json(AppError::NotFound("Task not found".to_string())).await;
+    assert_eq!(body["code"], "not_found");
+
+    let body = body_json(AppError::RateLimited(3)).await;
+    assert_eq!(body["code"], "rate_limited");
+}
+
+#[tokio::test]
+async fn test_internal_errors_are_not_leaked() {
+    let body = body_json(AppError::Database(sqlx::Error::RowNotFound)).await;
+    assert_eq!(body["detail"], "Internal server error");
+    assert_eq!(body["code"], "internal_error");
+
+    let body = body_json(AppError::Internal("signing key missing".to_string())).await;
+    assert_eq!(body["detail"], "Internal server error");
+}
+
+#[test]
+fn test_non_constraint_database_errors_stay_internal() {
+    let error = AppError::from(sqlx::Error::RowNotFound);
+    assert!(matches!(error, AppError::Database(_)));
+    assert_eq!(error.status(), StatusCode::INTERNAL_SERVER_ERROR);
+}
+
+#[test]
+fn test_locked_out_sets_retry_after() {
+    let response = AppError::LockedOut(42).into_response();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.headers()["retry-after"], "42");
+}
+
+#[tokio::test]
+async fn test_errors_are_problem_json() {
+    let response = AppError::NotFound("Task not found".to_string()).into_response();
+    assert_eq!(
+        response.headers()["content-type"],
+        "application/problem+json"
+    );
+
+    let body = body_json(AppError::NotFound("Task not found".to_string())).await;
+    assert_eq!(body["type"], "urn:task-manager:problem:not_found");
+    assert_eq!(body["title"], "Not Found");
+    assert_eq!(body["status"], 404);
+    assert_eq!(body["detail"], "Task not found");
+    // Outside a request there is nothing to point at
+    assert!(body.get("instance").is_none());
+    assert!(body.get("request_id").is_none());
+}
+
+#[tokio::test]
+async fn test_invalid_fields_are_listed() {
+    let body = body_json(AppError::InvalidFields(vec![FieldError::new(
+        "title",
+        "blank",
+        "Must not be blank",
+    )]))
+    .await;
+    assert_eq!(body["status"], 422);
+    assert_eq!(body["code"], "validation_failed");
+    assert_eq!(
+        body["errors"],
+        serde_json::json!([{ "field": "title", "code": "blank", "message": "Must not be blank" }])
+    );
 }
@@ -0,0 +1,116 @@
+// Unit tests for access token signing keys and key rotation
+use task_manager::keys::{JwtKeys, KeyError};
+use task_manager::middleware::auth::{create_jwt, Claims};
+use uuid::Uuid;
+
+const RSA_KEY: &str = include_str!("fixtures/jwt_rs256.pem");
+const OLD_ED_KEY: &str = include_str!("fixtures/jwt_ed25519_2026_05.pem");
+const NEW_ED_KEY: &str = include_str!("fixtures/jwt_ed25519_2026_06.pem");
+
+const USER_ID: &str = "550e8400-e29b-41d4-a716-446655440000";
+const TTL: chrono::TimeDelta = chrono::TimeDelta::minutes(15);
+
+fn header(token: &str) -> jsonwebtoken::Header {
+    jsonwebtoken::decode_header(token).unwrap()
+}
+
+#[test]
+fn test_rs256_tokens_carry_kid() {
+    let keys = JwtKeys::from_pems(&[("rsa-1", RSA_KEY)]).unwrap();
+    let token = create_jwt(&keys, TTL, USER_ID, Uuid::new_v4(), 0).unwrap();
+
+    let header = header(&token);
+    assert_eq!(header.alg, jsonwebtoken::Algorithm::RS256);
+    assert_eq!(header.kid.as_deref(), Some("rsa-1"));
+
+    let claims: Claims = keys.verify(&token).unwrap();
+    assert_eq!(claims.sub, USER_ID);
+}
+
+#[test]
+fn test_eddsa_tokens_roundtrip() {
+    let keys = JwtKeys::from_pems(&[("2026-06", NEW_ED_KEY)]).unwrap();
+    let token = create_jwt(&keys, TTL, USER_ID, Uuid::new_v4(), 0).unwrap();
+
+    assert_eq!(header(&token).alg, jsonwebtoken::Algorithm::EdDSA);
+    assert!(keys.verify::<Claims>(&token).is_ok());
+}
+
+#[test]
+fn test_rotation_keeps_old_tokens_valid() {
+    let before = JwtKeys::from_pems(&[("2026-05", OLD_ED_KEY)]).unwrap();
+    let during = JwtKeys::from_pems(&[("2026-06", NEW_ED_KEY), ("2026-05", OLD_ED_KEY)]).unwrap();
+    let after = JwtKeys::from_pems(&[("2026-06", NEW_ED_KEY)]).unwrap();
+
+    let old_token = create_jwt(&before, TTL, USER_ID, Uuid::new_v4(), 0).unwrap();
+    let new_token = create_jwt(&during, TTL, USER_ID, Uuid::new_v4(), 0).unwrap();
+
+    // New tokens are signed with the first key
+    assert_eq!(header(&new_token).kid.as_deref(), Some("2026-06"));
+    assert!(during.verify::<Claims>(&old_token).is_ok());
+    assert!(during.verify::<Claims>(&new_token).is_ok());
+
+    // Once the old key is retired its tokens stop working
+    assert!(after.verify::<Claims>(&old_token).is_err());
+    assert!(after.verify::<Claims>(&new_token).is_ok());
+}
+
+#[test]
+fn test_verify_rejects_other_keys_and_algorithms() {
+    let keys = JwtKeys::from_pems(&[("2026-06", NEW_ED_KEY)]).unwrap();
+
+    // Same kid, different key
+    let impostor = JwtKeys::from_pems(&[("2026-06", OLD_ED_KEY)]).unwrap();
+    let token = create_jwt(&impostor, TTL, USER_ID, Uuid::new_v4(), 0).unwrap();
+    assert!(keys.verify::<Claims>(&token).is_err());
+
+    // HMAC tokens have no kid and are not accepted once keys are configured
+    let hmac = JwtKeys::hmac("test_secret_key");
+    let token = create_jwt(&hmac, TTL, USER_ID, Uuid::new_v4(), 0).unwrap();
+    assert!(keys.verify::<Claims>(&token).is_err());
+    assert!(hmac.verify::<Claims>(&token).is_ok());
+}
+
+#[test]
+fn test_jwks_lists_public_keys() {
+    let keys = JwtKeys::from_pems(&[("rsa-1", RSA_KEY), ("2026-05", OLD_ED_KEY)]).unwrap();
+    let jwks = serde_json::to_value(keys.jwks()).unwrap();
+
+    let listed = jwks["keys"].as_array().unwrap();
+    assert_eq!(listed.len(), 2);
+    assert_eq!(listed[0]["kid"], "rsa-1");
+    assert_eq!(listed[0]["kty"], "RSA");
+    assert_eq!(listed[0]["alg"], "RS256");
+    assert_eq!(listed[0]["e"], "AQAB");
+    assert!(listed[0].get("d").is_none());
+    assert_eq!(listed[1]["kid"], "2026-05");
+    assert_eq!(listed[1]["kty"], "OKP");
+    assert_eq!(listed[1]["crv"], "Ed25519");
+
+    // Other services can verify our tokens with nothing but the JWKS
+    let token = create_jwt(&keys, TTL, USER_ID, Uuid::new_v4(), 0).unwrap();
+    let jwk: jsonwebtoken::jwk::Jwk = serde_json::from_value(listed[0].clone()).unwrap();
+    assert!(jsonwebtoken::decode::<Claims>(
+        &token,
+        &jsonwebtoken::DecodingKey::from_jwk(&jwk).unwrap(),
+        &jsonwebtoken::Validation::new(jsonwebtoken::Algorithm::RS256),
+    )
+    .is_ok());
+
+    assert!(JwtKeys::hmac("test_secret_key").jwks().keys.is_empty());
+}
+
+#[test]
+fn test_key_files_use_file_name_as_kid() {
+    let keys = JwtKeys::from_files(&["tests/fixtures/jwt_ed25519_2026_06.pem"]).unwrap();
+    let token = create_jwt(&keys, TTL, USER_ID, Uuid::new_v4(), 0).unwrap();
+    assert_eq!(header(&token).kid.as_deref(), Some("jwt_ed25519_2026_06"));
+
+    assert!(JwtKeys::from_files(&["tests/fixtures/missing.pem"]).is_err());
+    assert!(JwtKeys::from_pems(&[("bad", "not a key")]).is_err());
+    assert!(JwtKeys::from_pems(&[]).is_err());
+
+    // The same file name in two directories would give two keys one kid
+    let result = JwtKeys::from_pems(&[("2026-06", NEW_ED_KEY), ("2026-06", OLD_ED_KEY)]);
+    assert!(matches!(result, Err(KeyError::Invalid(kid, _)) if kid == "2026-06"));
+}
@@ -0,0 +1,220 @@
+// Unit tests for failed login counting and lockout, with the in-memory store
+use std::{sync::Arc, time::Duration};
+
+use task_manager::{
+    errors::AppError,
+    lockout::{
+        AttemptStore, Attempts, LockoutPolicy, LoginAttempt, LoginThrottle, MemoryAttemptStore,
+        Recorded,
+    },
+};
+
+fn policy() -> LockoutPolicy {
+    LockoutPolicy {
+        account_threshold: 3,
+        ip_threshold: 5,
+        base_lockout: Duration::from_secs(30),
+        max_lockout: Duration::from_secs(600),
+        window: Duration::from_secs(3600),
+    }
+}
+
+fn throttle() -> LoginThrottle {
+    LoginThrottle::new(Arc::new(MemoryAttemptStore::default()), policy())
+}
+
+/// Makes a login attempt that fails, and returns the lockout it started.
+async fn fail(throttle: &LoginThrottle, email: &str, ip_address: Option<&str>) -> Option<Duration> {
+    let attempt = throttle.begin(email, ip_address).await.unwrap();
+    throttle.failed(attempt)
+}
+
+/// Seconds until the lockout ends, if the attempt is refused. An attempt
+/// that is let in is taken back.
+async fn retry_after(
+    throttle: &LoginThrottle,
+    email: &str,
+    ip_address: Option<&str>,
+) -> Option<u64> {
+    match throttle.begin(email, ip_address).await {
+        Ok(attempt) => {
+            throttle.forgive(attempt).await.unwrap();
+            None
+        }
+        Err(AppError::LockedOut(secs)) => Some(secs),
+        Err(e) => panic!("unexpected error: {}", e),
+    }
+}
+
+#[test]
+fn test_lockout_doubles_up_to_max() {
+    let policy = policy();
+    assert_eq!(policy.lockout(2, 3), None);
+    assert_eq!(policy.lockout(3, 3), Some(Duration::from_secs(30)));
+    assert_eq!(policy.lockout(4, 3), Some(Duration::from_secs(60)));
+    assert_eq!(policy.lockout(6, 3), Some(Duration::from_secs(240)));
+    assert_eq!(policy.lockout(8, 3), Some(Duration::from_secs(600)));
+    assert_eq!(policy.lockout(1000, 3), Some(Duration::from_secs(600)));
+}
+
+#[tokio::test]
+async fn test_account_locks_after_threshold() {
+    let throttle = throttle();
+
+    assert_eq!(fail(&throttle, "a@example.com", None).await, None);
+    assert_eq!(fail(&throttle, "a@example.com", None).await, None);
+    assert_eq!(retry_after(&throttle, "a@example.com", None).await, None);
+
+    let lockout = fail(&throttle, "a@example.com", None).await;
+    assert_eq!(lockout, Some(Duration::from_secs(30)));
+
+    let secs = retry_after(&throttle, "a@example.com", None).await.unwrap();
+    assert!((29..=30).contains(&secs));
+    assert_eq!(retry_after(&throttle, "b@example.com", None).await, None);
+
+    throttle.unlock("a@example.com").await.unwrap();
+    assert_eq!(retry_after(&throttle, "a@example.com", None).await, None);
+}
+
+#[tokio::test]
+async fn test_ip_locks_across_accounts() {
+    let throttle = throttle();
+
+    for i in 0..5 {
+        let email = format!("user{}@example.com", i);
+        fail(&throttle, &email, Some("203.0.113.5")).await;
+    }
+
+    // Every account is refused from that address, but not from others
+    assert!(
+        retry_after(&throttle, "new@example.com", Some("203.0.113.5"))
+            .await
+            .is_some()
+    );
+    assert_eq!(
+        retry_after(&throttle, "new@example.com", Some("198.51.100.7")).await,
+        None
+    );
+
+    // A successful login elsewhere does not reset the address
+    throttle.unlock("user0@example.com").await.unwrap();
+    assert!(
+        retry_after(&throttle, "user0@example.com", Some("203.0.113.5"))
+            .await
+            .is_some()
+    );
+}
+
+#[tokio::test]
+async fn test_parallel_attempts_stop_at_threshold() {
+    let throttle = Arc::new(throttle());
+
+    // Attempts in flight count, so the fourth of these is refused even
+    // though none has failed yet
+    let attempts = parallel_attempts(&throttle, 10).await;
+    let let_in = attempts.iter().filter(|attempt| attempt.is_ok()).count();
+    assert_eq!(let_in, 3);
+    assert!(attempts
+        .iter()
+        .filter_map(|attempt| attempt.as_ref().err())
+        .all(|e| matches!(e, AppError::LockedOut(_))));
+}
+
+async fn parallel_attempts(
+    throttle: &Arc<LoginThrottle>,
+    count: usize,
+) -> Vec<Result<LoginAttempt, AppError>> {
+    let tasks: Vec<_> = (0..count)
+        .map(|_| {
+            let throttle = throttle.clone();
+            tokio::spawn(async move { throttle.begin("a@example.com", None).await })
+        })
+        .collect();
+
+    let mut attempts = Vec::new();
+    for task in tasks {
+        attempts.push(task.await.unwrap());
+    }
+    attempts
+}
+
+#[tokio::test]
+async fn test_valid_logins_are_taken_back() {
+    let throttle = throttle();
+
+    for i in 0..4 {
+        let email = format!("user{}@example.com", i);
+        fail(&throttle, &email, Some("203.0.113.5")).await;
+    }
+    // Logging in many times from the address does not lock it out
+    for _ in 0..10 {
+        let attempt = throttle
+            .begin("a@example.com", Some("203.0.113.5"))
+            .await
+            .unwrap();
+        throttle.succeeded(attempt).await.unwrap();
+    }
+    assert_eq!(
+        retry_after(&throttle, "new@example.com", Some("203.0.113.5")).await,
+        None
+    );
+
+    // Neither does a right password that still needs a second factor
+    for _ in 0..10 {
+        let attempt = throttle.begin("2fa@example.com", None).await.unwrap();
+        throttle.forgive(attempt).await.unwrap();
+    }
+    assert_eq!(retry_after(&throttle, "2fa@example.com", None).await, None);
+}
+
+#[tokio::test]
+async fn test_memory_store_forgets_old_failures() {
+    let store = MemoryAttemptStore::default();
+    let never = |_: &Attempts| None;
+    let failures = |recorded: Recorded| match recorded {
+        Recorded::Counted(attempts) => attempts.failures,
+        Recorded::LockedOut(_) => panic!("unexpected lockout"),
+    };
+
+    store
+        .record_failure("k", Duration::from_secs(3600), &never)
+        .await
+        .unwrap();
+    let recorded = store
+        .record_failure("k", Duration::from_secs(3600), &never)
+        .await
+        .unwrap();
+    assert_eq!(failures(recorded), 2);
+
+    // With a zero window the previous failure is already stale
+    tokio::time::sleep(Duration::from_millis(5)).await;
+    let recorded = store
+        .record_failure("k", Duration::ZERO, &never)
+        .await
+        .unwrap();
+    assert_eq!(failures(recorded), 1);
+
+    tokio::time::sleep(Duration::from_millis(5)).await;
+    assert_eq!(store.purge(Duration::ZERO).await.unwrap(), 1);
+    assert!(store.get("k").await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_memory_store_refuses_locked_out_keys() {
+    let store = MemoryAttemptStore::default();
+    let window = Duration::from_secs(3600);
+    let after_two =
+        |attempts: &Attempts| (attempts.failures >= 2).then_some(Duration::from_secs(9));
+
+    for _ in 0..2 {
+        store.record_failure("k", window, &after_two).await.unwrap();
+    }
+    assert_eq!(
+        store.record_failure("k", window, &after_two).await.unwrap(),
+        Recorded::LockedOut(Duration::from_secs(9))
+    );
+    assert_eq!(store.get("k").await.unwrap().unwrap().failures, 2);
+
+    store.forgive("k").await.unwrap();
+    assert_eq!(store.get("k").await.unwrap().unwrap().failures, 1);
+}
@@ -0,0 +1,175 @@
+// Unit tests for the mailer implementations
+use task_manager::mailer::{
+    Email, FileMailer, MailError, Mailer, MemoryMailer, SmtpMailer, SmtpSettings, SmtpTls,
+};
+
+fn email(to: &str) -> Email {
+    Email {
+        to: to.to_string(),
+        subject: "Hello".to_string(),
+        body: "Body text".to_string(),
+    }
+}
+
+#[tokio::test]
+async fn test_memory_mailer_records_messages() {
+    let mailer = MemoryMailer::default();
+
+    mailer.send(email("a@example.com")).await.unwrap();
+    mailer.send(email("b@example.com")).await.unwrap();
+
+    assert_eq!(mailer.sent().len(), 2);
+    assert_eq!(mailer.last_to("b@example.com").unwrap().to, "b@example.com");
+    assert!(mailer.last_to("c@example.com").is_none());
+}
+
+#[tokio::test]
+async fn test_file_mailer_writes_eml_file() {
+    let dir = std::env::temp_dir().join(format!("mailer-test-{}", uuid::Uuid::new_v4()));
+    let mailer = FileMailer::new(&dir);
+
+    mailer.send(email("file@example.com")).await.unwrap();
+
+    let entries: Vec<_> = std::fs::read_dir(&dir).unwrap().collect();
+    assert_eq!(entries.len(), 1);
+
+    let path = entries[0].as_ref().unwrap().path();
+    assert_eq!(path.extension().unwrap(), "eml");
+    let contents = std::fs::read_to_string(&path).unwrap();
+    assert!(contents.contains("To: file@example.com"));
+    assert!(contents.contains("Subject: Hello"));
+    assert!(contents.contains("Body text"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_line_breaks_cannot_add_headers() {
+    let dir = std::env::temp_dir().join(format!("mailer-test-{}", uuid::Uuid::new_v4()));
+    let mailer = FileMailer::new(&dir);
+    let injected = Email {
+        to: "file@example.com\r\nBcc: spy@example.com".to_string(),
+        subject: "You're invited to Team\r\nBcc: spy@example.com".to_string(),
+        body: "Body text".to_string(),
+    };
+
+    mailer.send(injected.clone()).await.unwrap();
+
+    let path = std::fs::read_dir(&dir)
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap()
+        .path();
+    let contents = std::fs::read_to_string(&path).unwrap();
+    assert!(!contents.contains("\nBcc:"));
+    assert!(contents.contains("Subject: You're invited to Team  Bcc: spy@example.com\r\n"));
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let mailer = MemoryMailer::default();
+    mailer.send(injected).await.unwrap();
+    let sent = &mailer.sent()[0];
+    assert_eq!(sent.to, "file@example.comBcc: spy@example.com");
+    assert!(!sent.subject.contains('\n'));
+}
+
+// Accepts one SMTP session on a local port and yields the message data
+async fn fake_smtp_server() -> (u16, tokio::sync::oneshot::Receiver<String>) {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let (sender, receiver) = tokio::sync::oneshot::channel();
+
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (read, mut write) = stream.into_split();
+        let mut lines = BufReader::new(read).lines();
+        write.write_all(b"220 localhost ESMTP\r\n").await.unwrap();
+
+        while let Some(line) = lines.next_line().await.unwrap() {
+            let reply: &[u8] = match line.split(' ').next().unwrap().to_uppercase().as_str() {
+                "EHLO" | "HELO" => b"250 localhost\r\n",
+                "DATA" => {
+                    write.write_all(b"354 Go ahead\r\n").await.unwrap();
+                    let mut data = String::new();
+                    while let Some(line) = lines.next_line().await.unwrap() {
+                        if line == "." {
+                            break;
+                        }
+                        data.push_str(&line);
+                        data.push('\n');
+                    }
+                    write.write_all(b"250 Queued\r\n").await.unwrap();
+                    sender.send(data).unwrap();
+                    return;
+                }
+                _ => b"250 OK\r\n",
+            };
+            write.write_all(reply).await.unwrap();
+        }
+    });
+
+    (port, receiver)
+}
+
+fn smtp_settings(port: u16) -> SmtpSettings {
+    SmtpSettings {
+        host: "127.0.0.1".to_string(),
+        port,
+        tls: SmtpTls::None,
+        username: None,
+        password: None,
+        from: "Tasks <noreply@example.com>".parse().unwrap(),
+    }
+}
+
+#[tokio::test]
+async fn test_smtp_mailer_delivers_message() {
+    let (port, received) = fake_smtp_server().await;
+    let mailer = SmtpMailer::new(&smtp_settings(port)).unwrap();
+
+    mailer.send(email("smtp@example.com")).await.unwrap();
+
+    let data = received.await.unwrap();
+    assert!(data.contains("From: Tasks <noreply@example.com>"));
+    assert!(data.contains("To: smtp@example.com"));
+    assert!(data.contains("Subject: Hello"));
+    assert!(data.contains("Body text"));
+}
+
+#[tokio::test]
+async fn test_smtp_mailer_reports_failures() {
+    let (port, _) = fake_smtp_server().await;
+    let mailer = SmtpMailer::new(&smtp_settings(port)).unwrap();
+    assert!(matches!(
+        mailer.send(email("not an address")).await,
+        Err(MailError::Address(_))
+    ));
+
+    // Nothing listens on the port any more
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let closed = listener.local_addr().unwrap().port();
+    drop(listener);
+    let mailer = SmtpMailer::new(&smtp_settings(closed)).unwrap();
+    assert!(matches!(
+        mailer.send(email("smtp@example.com")).await,
+        Err(MailError::Smtp(_))
+    ));
+}
+
+#[test]
+fn test_smtp_mailer_builds_tls_transports() {
+    for tls in [SmtpTls::StartTls, SmtpTls::Tls] {
+        let settings = SmtpSettings {
+            host: "smtp.example.com".to_string(),
+            port: tls.default_port(),
+            tls,
+            username: Some("tasks".to_string()),
+            password: Some("secret".to_string()),
+            ..smtp_settings(0)
+        };
+        assert!(SmtpMailer::new(&settings).is_ok());
+        assert!(!format!("{:?}", settings).contains("secret"));
+    }
+}
@@ -0,0 +1,447 @@
+// OpenID Connect login tests, against a mock identity provider served
+// in-process on a random local port.
+//
+// Needs the database, like integration_tests:
+//   cargo test --test oidc_tests
+//
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, Request, StatusCode},
+    routing::{get, post},
+    Form, Json, Router,
+};
+use jsonwebtoken::{encode, EncodingKey, Header};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use task_manager::oidc::{OidcClient, ProviderConfig};
+use tower::ServiceExt;
+
+mod common;
+use common::*;
+
+const SIGNING_KEY: &str = include_str!("fixtures/oidc_signing_key.pem");
+// Base64url modulus of the fixture key; its exponent is 65537 ("AQAB")
+const SIGNING_KEY_MODULUS: &str = "xtgBVLM2jOGe2umRnCMmsNjUxt9C6OlzyXaCzbv1xFY23lQwh6pkDcdPh4KTXpGWy1IKTjSToINWoPUz6qTzDlpU-jCTUIfGZIufeSHV1MzINDWpZlwEWramgh8oOiTgSB8_iDdc4r564ie9Gh8RmNQtJDjRzeHcg04gk8VmcWHgP-kK6p3WVnTvPHLaiczU4CHvfB4wdRWMlhmOmY_sSxRQyDhZfuzkGyLZ4288pRBwUYUJsMEGsJyDZ-mDZNil8Vm6Rtpj9xzneN9wqGx_QmLJj-6UnQwG6osRMuLqPt9fO71PxP3E__1iimeEJaL5IWNVcKOBQ9fBjpefj9Wsew";
+const CLIENT_ID: &str = "task-manager";
+const CLIENT_SECRET: &str = "mock-secret";
+
+/// A code the mock provider has issued, with what it needs to check the
+/// token request and the claims of the ID token it will return.
+struct IssuedCode {
+    redirect_uri: String,
+    code_challenge: String,
+    claims: Value,
+}
+
+#[derive(Default)]
+struct MockState {
+    issuer: String,
+    codes: Mutex<HashMap<String, IssuedCode>>,
+}
+
+struct MockProvider {
+    issuer: String,
+    state: Arc<MockState>,
+}
+
+#[derive(Deserialize)]
+struct TokenRequest {
+    grant_type: String,
+    code: String,
+    redirect_uri: String,
+    client_id: String,
+    client_secret: Option<String>,
+    code_verifier: String,
+}
+
+async fn discovery(State(state): State<Arc<MockState>>) -> Json<Value> {
+    Json(json!({
+        "issuer": state.issuer,
+        "authorization_endpoint": format!("{}/authorize", state.issuer),
+        "token_endpoint": format!("{}/token", state.issuer),
+        "jwks_uri": format!("{}/jwks", state.issuer),
+        "response_types_supported": ["code"],
+        "subject_types_supported": ["public"],
+        "id_token_signing_alg_values_supported": ["RS256"]
+    }))
+}
+
+async fn jwks() -> Json<Value> {
+    Json(json!({
+        "keys": [{
+            "kty": "RSA",
+            "use": "sig",
+            "alg": "RS256",
+            "kid": "mock-key",
+            "n": SIGNING_KEY_MODULUS,
+            "e": "AQAB"
+        }]
+    }))
+}
+
+async fn token(
+    State(state): State<Arc<MockState>>,
+    Form(request): Form<TokenRequest>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+    use sha2::{Digest, Sha256};
+
+    let invalid_grant = || {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "invalid_grant" })),
+        )
+    };
+
+    let issued = state
+        .codes
+        .lock()
+        .unwrap()
+        .remove(&request.code)
+        .ok_or_else(invalid_grant)?;
+
+    let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(request.code_verifier.as_bytes()));
+    if request.grant_type != "authorization_code"
+        || request.client_id != CLIENT_ID
+        || request.client_secret.as_deref() != Some(CLIENT_SECRET)
+        || request.redirect_uri != issued.redirect_uri
+        || challenge != issued.code_challenge
+    {
+        return Err(invalid_grant());
+    }
+
+    let mut header = Header::new(jsonwebtoken::Algorithm::RS256);
+    header.kid = Some("mock-key".to_string());
+    let id_token = encode(
+        &header,
+        &issued.claims,
+        &EncodingKey::from_rsa_pem(SIGNING_KEY.as_bytes()).unwrap(),
+    )
+    .unwrap();
+
+    Ok(Json(json!({
+        "access_token": "mock-access-token",
+        "token_type": "Bearer",
+        "id_token": id_token
+    })))
+}
+
+async fn start_mock_provider() -> MockProvider {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let issuer = format!("http://{}", listener.local_addr().unwrap());
+    let state = Arc::new(MockState {
+        issuer: issuer.clone(),
+        ..Default::default()
+    });
+
+    let router = Router::new()
+        .route("/.well-known/openid-configuration", get(discovery))
+        .route("/jwks", get(jwks))
+        .route("/token", post(token))
+        .with_state(state.clone());
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+    MockProvider { issuer, state }
+}
+
+impl MockProvider {
+    /// Plays the user signing in at the provider: reads the authorization
+    /// URL our API built and returns the `code` and `state` the provider
+    /// would redirect back with. `overrides` replace ID token claims.
+    fn sign_in(&self, authorization_url: &str, sub: &str, email: &str, overrides: Value) -> Value {
+        let url = reqwest::Url::parse(authorization_url).unwrap();
+        let params: HashMap<String, String> = url.query_pairs().into_owned().collect();
+        assert_eq!(params["response_type"], "code");
+        assert_eq!(params["client_id"], CLIENT_ID);
+        assert_eq!(params["code_challenge_method"], "S256");
+
+        let mut claims = json!({
+            "iss": self.issuer,
+            "sub": sub,
+            "aud": CLIENT_ID,
+            "exp": chrono::Utc::now().timestamp() + 300,
+            "iat": chrono::Utc::now().timestamp(),
+            "nonce": params["nonce"],
+            "email": email,
+            "email_verified": true
+        });
+        for (key, value) in overrides.as_object().unwrap() {
+            claims[key] = value.clone();
+        }
+
+        let code = uuid::Uuid::new_v4().to_string();
+        self.state.codes.lock().unwrap().insert(
+            code.clone(),
+            IssuedCode {
+                redirect_uri: params["redirect_uri"].clone(),
+                code_challenge: params["code_challenge"].clone(),
+                claims,
+            },
+        );
+
+        json!({ "code": code, "state": params["state"] })
+    }
+}
+
+async fn create_oidc_test_app(pool: sqlx::PgPool, provider: &MockProvider) -> axum::Router {
+    let oidc = OidcClient::new(vec![ProviderConfig::new(
+        "mock",
+        &provider.issuer,
+        CLIENT_ID,
+    )
+    .with_client_secret(CLIENT_SECRET)]);
+    let state = test_state(pool).with_oidc(oidc);
+
+    create_test_app_with_state(state)
+}
+
+/// What the provider's redirect carries back to the browser that started
+/// the login, along with the state cookie that browser holds.
+struct Callback {
+    body: Value,
+    cookie: String,
+}
+
+/// Starts a login and returns the callback the provider's redirect would
+/// lead to.
+async fn sign_in(
+    app: &axum::Router,
+    provider: &MockProvider,
+    sub: &str,
+    email: &str,
+    overrides: Value,
+) -> Callback {
+    let request = Request::get("/auth/oidc/mock/authorize")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let set_cookie = response.headers()[header::SET_COOKIE].to_str().unwrap();
+    assert!(set_cookie.contains("HttpOnly"));
+    let cookie = set_cookie.split(';').next().unwrap().to_string();
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let json: Value = serde_json::from_slice(&body).unwrap();
+    let authorization_url = json["authorization_url"].as_str().unwrap();
+    assert!(authorization_url.starts_with(&format!("{}/authorize?", provider.issuer)));
+
+    Callback {
+        body: provider.sign_in(authorization_url, sub, email, overrides),
+        cookie,
+    }
+}
+
+/// Posts the callback to the API the way a frontend would, sending `cookie`
+/// if there is one.
+async fn finish_sign_in(
+    app: &axum::Router,
+    body: &Value,
+    cookie: Option<&str>,
+) -> (StatusCode, Value) {
+    let mut request =
+        Request::post("/auth/oidc/mock/callback").header(header::CONTENT_TYPE, "application/json");
+    if let Some(cookie) = cookie {
+        request = request.header(header::COOKIE, cookie);
+    }
+    let request = request.body(Body::from(body.to_string())).unwrap();
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+impl Callback {
+    async fn finish(&self, app: &axum::Router) -> (StatusCode, Value) {
+        finish_sign_in(app, &self.body, Some(&self.cookie)).await
+    }
+}
+
+#[sqlx::test]
+async fn test_oidc_login_creates_user_and_reuses_identity(pool: PgPool) {
+    let provider = start_mock_provider().await;
+    let app = create_oidc_test_app(pool.clone(), &provider).await;
+
+    let callback = sign_in(&app, &provider, "user-1", "sso@example.com", json!({})).await;
+    let (status, json) = callback.finish(&app).await;
+    assert_eq!(status, StatusCode::OK);
+    let token = json["token"].as_str().unwrap();
+
+    let (status, _) = send_json(&app, "GET", "/tasks", Some(token), None).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // The state is single-use
+    let (status, _) = callback.finish(&app).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // The identity is matched on its subject, even if the provider's email changed
+    let callback = sign_in(&app, &provider, "user-1", "renamed@example.com", json!({})).await;
+    let (status, _) = callback.finish(&app).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let emails = sqlx::query_scalar::<_, String>("SELECT email FROM users")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    assert_eq!(emails, vec!["sso@example.com"]);
+
+    let verified = sqlx::query_scalar::<_, bool>(
+        "SELECT email_verified_at IS NOT NULL FROM users WHERE email = 'sso@example.com'",
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert!(verified);
+}
+
+#[sqlx::test]
+async fn test_oidc_login_links_existing_account(pool: PgPool) {
+    let provider = start_mock_provider().await;
+    let app = create_oidc_test_app(pool.clone(), &provider).await;
+
+    let token = create_test_user_with_token(&app, "linked@example.com").await;
+    create_test_task(&app, &token, "Made before SSO").await;
+    sqlx::query("UPDATE users SET email_verified_at = NOW()")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    // Only an address the provider verified is trusted to match the account
+    let callback = sign_in(
+        &app,
+        &provider,
+        "user-2",
+        "linked@example.com",
+        json!({ "email_verified": false }),
+    )
+    .await;
+    let (status, _) = callback.finish(&app).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let callback = sign_in(&app, &provider, "user-2", "linked@EXAMPLE.com", json!({})).await;
+    let (status, json) = callback.finish(&app).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let tasks = list_test_tasks(&app, json["token"].as_str().unwrap(), false).await;
+    assert_eq!(tasks.len(), 1);
+
+    let actions = sqlx::query_scalar::<_, String>("SELECT action FROM audit_events")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    assert_eq!(actions, vec!["identity_linked"]);
+}
+
+#[sqlx::test]
+async fn test_oidc_login_does_not_inherit_unverified_accounts(pool: PgPool) {
+    let provider = start_mock_provider().await;
+    let app = create_oidc_test_app(pool.clone(), &provider).await;
+
+    // Someone registers the address before its owner ever signs in
+    let token = create_test_user_with_token(&app, "victim@example.com").await;
+    create_test_task(&app, &token, "Planted").await;
+
+    let callback = sign_in(&app, &provider, "victim", "victim@example.com", json!({})).await;
+    let (status, json) = callback.finish(&app).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert!(json.get("token").is_none());
+
+    let (identities, verified) = sqlx::query_as::<_, (i64, bool)>(
+        "SELECT (SELECT COUNT(*) FROM user_identities),
+                (SELECT email_verified_at IS NOT NULL FROM users)",
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!(identities, 0);
+    assert!(!verified);
+}
+
+#[sqlx::test]
+async fn test_oidc_rejects_invalid_id_tokens(pool: PgPool) {
+    let provider = start_mock_provider().await;
+    let app = create_oidc_test_app(pool, &provider).await;
+
+    for overrides in [
+        json!({ "nonce": "replayed" }),
+        json!({ "aud": "another-client" }),
+        json!({ "iss": "http://evil.example.com" }),
+        json!({ "exp": chrono::Utc::now().timestamp() - 3600 }),
+    ] {
+        let callback = sign_in(
+            &app,
+            &provider,
+            "user-3",
+            "bad@example.com",
+            overrides.clone(),
+        )
+        .await;
+        let (status, _) = callback.finish(&app).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED, "{}", overrides);
+    }
+
+    // A code the provider does not recognise
+    let mut callback = sign_in(&app, &provider, "user-3", "bad@example.com", json!({})).await;
+    callback.body["code"] = json!("forged");
+    let (status, _) = callback.finish(&app).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, _) = send_json(&app, "GET", "/auth/oidc/unknown/authorize", None, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[sqlx::test]
+async fn test_oidc_callback_from_provider_redirect(pool: PgPool) {
+    let provider = start_mock_provider().await;
+    let app = create_oidc_test_app(pool, &provider).await;
+
+    let callback = sign_in(&app, &provider, "user-4", "redirect@example.com", json!({})).await;
+    let uri = format!(
+        "/auth/oidc/mock/callback?code={}&state={}",
+        callback.body["code"].as_str().unwrap(),
+        callback.body["state"].as_str().unwrap()
+    );
+    let request = Request::get(&uri)
+        .header(header::COOKIE, &callback.cookie)
+        .body(Body::empty())
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let set_cookie = response.headers()[header::SET_COOKIE].to_str().unwrap();
+    assert!(set_cookie.contains("Max-Age=0"));
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let json: Value = serde_json::from_slice(&body).unwrap();
+    assert!(json["token"].is_string());
+}
+
+#[sqlx::test]
+async fn test_oidc_callback_requires_the_browser_that_started_it(pool: PgPool) {
+    let provider = start_mock_provider().await;
+    let app = create_oidc_test_app(pool, &provider).await;
+
+    let callback = sign_in(&app, &provider, "user-5", "victim@example.com", json!({})).await;
+    let other = sign_in(&app, &provider, "user-6", "other@example.com", json!({})).await;
+
+    let (status, _) = finish_sign_in(&app, &callback.body, None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = finish_sign_in(&app, &callback.body, Some(&other.cookie)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // Refused callbacks leave the login to the browser that started it
+    let (status, _) = callback.finish(&app).await;
+    assert_eq!(status, StatusCode::OK);
+}
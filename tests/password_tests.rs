@@ -0,0 +1,114 @@
+// Unit tests for password hashing and the password policy
+use task_manager::errors::AppError;
+use task_manager::password::{PasswordHasher, PasswordPolicy};
+
+// Small parameters keep the tests fast
+fn hasher() -> PasswordHasher {
+    PasswordHasher::new(1024, 1, 1).unwrap()
+}
+
+#[tokio::test]
+async fn test_hashes_with_argon2id() {
+    let hasher = hasher();
+    let hash = hasher.hash("correct horse").await.unwrap();
+
+    assert!(hash.starts_with("$argon2id$v=19$m=1024,t=1,p=1$"));
+    assert!(hasher.verify("correct horse", &hash).await.unwrap());
+    assert!(!hasher.verify("wrong horse", &hash).await.unwrap());
+    assert!(!hasher.needs_rehash(&hash));
+
+    // Salted, so the same password hashes differently
+    assert_ne!(hash, hasher.hash("correct horse").await.unwrap());
+}
+
+#[tokio::test]
+async fn test_unknown_accounts_take_a_verification() {
+    let hasher = hasher();
+
+    // A real check against a hash made with the same parameters, every time
+    for _ in 0..2 {
+        hasher.verify_unknown("correct horse").await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_verifies_legacy_bcrypt_hashes() {
+    let hasher = hasher();
+    let legacy = bcrypt::hash("correct horse", 4).unwrap();
+
+    assert!(hasher.verify("correct horse", &legacy).await.unwrap());
+    assert!(!hasher.verify("wrong horse", &legacy).await.unwrap());
+    assert!(hasher.needs_rehash(&legacy));
+}
+
+#[tokio::test]
+async fn test_outdated_parameters_need_rehash() {
+    let old = hasher().hash("correct horse").await.unwrap();
+    let stronger = PasswordHasher::new(2048, 2, 1).unwrap();
+
+    assert!(stronger.verify("correct horse", &old).await.unwrap());
+    assert!(stronger.needs_rehash(&old));
+    assert!(!stronger
+        .verify("correct horse", "not a hash")
+        .await
+        .unwrap());
+    assert!(stronger.needs_rehash("not a hash"));
+}
+
+#[test]
+fn test_rejects_invalid_parameters() {
+    assert!(PasswordHasher::new(1, 1, 1).is_err());
+    assert!(PasswordHasher::new(1024, 0, 1).is_err());
+}
+
+#[test]
+fn test_policy_requires_min_length() {
+    let policy = PasswordPolicy::default();
+
+    assert!(policy.check("password", "short1").is_err());
+    assert!(policy.check("password", "exactly8").is_ok());
+    // Length counts characters, not bytes
+    assert!(PasswordPolicy::new(4, "").check("password", "ñññ").is_err());
+}
+
+#[test]
+fn test_policy_caps_the_length() {
+    let policy = PasswordPolicy::default();
+
+    assert!(policy.check("password", &"ñ".repeat(128)).is_ok());
+    let Err(AppError::InvalidFields(errors)) = policy.check("password", &"x".repeat(129)) else {
+        panic!("expected invalid fields");
+    };
+    assert_eq!(errors[0].code, "too_long");
+}
+
+#[test]
+fn test_policy_rejects_breached_passwords() {
+    let policy = PasswordPolicy::default();
+
+    assert!(policy.check("password", "password123").is_err());
+    assert!(policy.check("password", "PassWord123").is_err());
+    assert!(policy.check("password", "qwertyuiop").is_err());
+    assert!(policy.check("password", "testpassword123").is_ok());
+
+    let custom = PasswordPolicy::new(8, "# comment\n\nhunter2hunter2\n");
+    assert!(custom.check("password", "Hunter2Hunter2").is_err());
+    assert!(custom.check("password", "password123").is_ok());
+}
+
+#[test]
+fn test_policy_reports_the_field() {
+    let policy = PasswordPolicy::default();
+
+    let Err(AppError::InvalidFields(errors)) = policy.check("new_password", "short1") else {
+        panic!("expected invalid fields");
+    };
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].field, "new_password");
+    assert_eq!(errors[0].code, "too_short");
+
+    let Err(AppError::InvalidFields(errors)) = policy.check("password", "password123") else {
+        panic!("expected invalid fields");
+    };
+    assert_eq!(errors[0].code, "too_common");
+}
@@ -0,0 +1,109 @@
+// Unit tests for the token bucket rate limiter
+use std::time::Duration;
+
+use task_manager::middleware::rate_limit::{RateLimit, RateLimiter, RouteGroup};
+
+#[test]
+fn test_bucket_allows_burst_then_refuses() {
+    let limiter = RateLimiter::default().with_limit(RouteGroup::Auth, RateLimit::per_minute(3));
+
+    for remaining in [2, 1, 0] {
+        let decision = limiter.acquire(RouteGroup::Auth, "ip:203.0.113.5").unwrap();
+        assert_eq!(decision.retry_after, None);
+        assert_eq!(decision.remaining, remaining);
+        assert_eq!(decision.limit.requests, 3);
+    }
+
+    let decision = limiter.acquire(RouteGroup::Auth, "ip:203.0.113.5").unwrap();
+    assert_eq!(decision.remaining, 0);
+    // One request refills every 20 seconds, the whole bucket in a minute
+    assert_eq!(decision.retry_after, Some(20));
+    assert_eq!(decision.reset, 60);
+}
+
+#[test]
+fn test_buckets_are_per_caller_and_group() {
+    let limiter = RateLimiter::default()
+        .with_limit(RouteGroup::Auth, RateLimit::per_minute(1))
+        .with_limit(RouteGroup::Api, RateLimit::per_minute(1));
+
+    assert!(limiter
+        .acquire(RouteGroup::Auth, "user:a")
+        .unwrap()
+        .retry_after
+        .is_none());
+    assert!(limiter
+        .acquire(RouteGroup::Auth, "user:a")
+        .unwrap()
+        .retry_after
+        .is_some());
+    assert!(limiter
+        .acquire(RouteGroup::Auth, "user:b")
+        .unwrap()
+        .retry_after
+        .is_none());
+    assert!(limiter
+        .acquire(RouteGroup::Api, "user:a")
+        .unwrap()
+        .retry_after
+        .is_none());
+}
+
+#[test]
+fn test_groups_without_limit_are_not_limited() {
+    let limiter = RateLimiter::default().with_limit(RouteGroup::Auth, RateLimit::per_minute(1));
+    assert!(limiter.acquire(RouteGroup::Api, "user:a").is_none());
+}
+
+#[test]
+fn test_bucket_refills_over_period() {
+    let limit = RateLimit {
+        requests: 2,
+        period: Duration::from_millis(100),
+    };
+    let limiter = RateLimiter::default().with_limit(RouteGroup::Api, limit);
+
+    limiter.acquire(RouteGroup::Api, "user:a");
+    limiter.acquire(RouteGroup::Api, "user:a");
+    assert!(limiter
+        .acquire(RouteGroup::Api, "user:a")
+        .unwrap()
+        .retry_after
+        .is_some());
+
+    std::thread::sleep(Duration::from_millis(60));
+    assert!(limiter
+        .acquire(RouteGroup::Api, "user:a")
+        .unwrap()
+        .retry_after
+        .is_none());
+}
+
+#[test]
+fn test_least_recently_used_buckets_are_dropped_past_the_cap() {
+    let limiter = RateLimiter::default()
+        .with_limit(RouteGroup::Auth, RateLimit::per_minute(1))
+        .with_max_buckets(2);
+
+    limiter.acquire(RouteGroup::Auth, "ip:a");
+    limiter.acquire(RouteGroup::Auth, "ip:b");
+    std::thread::sleep(Duration::from_millis(2));
+    assert!(limiter
+        .acquire(RouteGroup::Auth, "ip:a")
+        .unwrap()
+        .retry_after
+        .is_some());
+
+    // A third caller pushes out b, which was used longest ago, but not a
+    limiter.acquire(RouteGroup::Auth, "ip:c");
+    assert!(limiter
+        .acquire(RouteGroup::Auth, "ip:a")
+        .unwrap()
+        .retry_after
+        .is_some());
+    assert!(limiter
+        .acquire(RouteGroup::Auth, "ip:b")
+        .unwrap()
+        .retry_after
+        .is_none());
+}
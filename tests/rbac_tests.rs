@@ -0,0 +1,355 @@
+// Role-based access control tests: the permission table itself, and every
+// workspace-scoped endpoint exercised as each role.
+//
+// The endpoint matrix needs the database, like integration_tests:
+//   cargo test --test rbac_tests
+//
+use axum::http::StatusCode;
+use serde_json::json;
+use sqlx::PgPool;
+use task_manager::{authz::Permission, models::workspace::Role};
+
+mod common;
+use common::*;
+
+const ROLES: [Role; 5] = [
+    Role::Owner,
+    Role::Admin,
+    Role::Editor,
+    Role::Commenter,
+    Role::Viewer,
+];
+
+fn role_name(role: Role) -> String {
+    serde_json::to_value(role)
+        .unwrap()
+        .as_str()
+        .unwrap()
+        .to_string()
+}
+
+#[test]
+fn test_permission_table() {
+    use Permission::*;
+
+    let expected: [(Permission, &[Role]); 5] = [
+        (View, &ROLES),
+        (EditTasks, &[Role::Owner, Role::Admin, Role::Editor]),
+        (ManageMembers, &[Role::Owner, Role::Admin]),
+        (ManageWorkspace, &[Role::Owner, Role::Admin]),
+        (DeleteWorkspace, &[Role::Owner]),
+    ];
+
+    for (permission, allowed) in expected {
+        for role in ROLES {
+            assert_eq!(
+                role.can(permission),
+                allowed.contains(&role),
+                "{:?} / {:?}",
+                role,
+                permission
+            );
+        }
+    }
+}
+
+#[test]
+fn test_role_serialization() {
+    assert_eq!(role_name(Role::Commenter), "commenter");
+    let role: Role = serde_json::from_str(r#""admin""#).unwrap();
+    assert_eq!(role, Role::Admin);
+}
+
+#[sqlx::test]
+async fn test_endpoint_role_matrix(pool: PgPool) {
+    let app = create_test_app(pool).await;
+
+    let owner = create_test_user_with_token(&app, "rbac_creator@example.com").await;
+    let outsider = create_test_user_with_token(&app, "rbac_outsider@example.com").await;
+    for role in ROLES {
+        create_test_user_with_token(&app, &format!("rbac_{}@example.com", role_name(role))).await;
+        create_test_user_with_token(
+            &app,
+            &format!("rbac_target_{}@example.com", role_name(role)),
+        )
+        .await;
+    }
+
+    for role in ROLES {
+        let name = role_name(role);
+        let workspace_id = create_test_workspace(&app, &owner, &format!("RBAC {}", name)).await;
+        let members = format!("/workspaces/{}/members", workspace_id);
+
+        // The owner already holds the owner role; everyone else is added with theirs
+        let caller = if role == Role::Owner {
+            owner.clone()
+        } else {
+            let (status, _) = send_json(
+                &app,
+                "POST",
+                &members,
+                Some(&owner),
+                Some(json!({ "email": format!("rbac_{}@example.com", name), "role": name })),
+            )
+            .await;
+            assert_eq!(status, StatusCode::CREATED);
+
+            let (status, json) = send_json(
+                &app,
+                "POST",
+                "/auth/login",
+                None,
+                Some(json!({
+                    "email": format!("rbac_{}@example.com", name),
+                    "password": "testpassword123"
+                })),
+            )
+            .await;
+            assert_eq!(status, StatusCode::OK);
+            json["token"].as_str().unwrap().to_string()
+        };
+
+        let (_, target) = send_json(
+            &app,
+            "POST",
+            &members,
+            Some(&owner),
+            Some(json!({ "email": format!("rbac_target_{}@example.com", name), "role": "viewer" })),
+        )
+        .await;
+        let target_id = target["user_id"].as_str().unwrap().to_string();
+        let target_member = format!("{}/{}", members, target_id);
+
+        let task = create_test_task_in(&app, &owner, &workspace_id, "Matrix task").await;
+        let task_uri = format!("/tasks/{}", task["id"].as_str().unwrap());
+
+        // The workspace deletion comes last so earlier cases still have a workspace
+        let cases = [
+            ("GET", members.clone(), None, Permission::View),
+            (
+                "GET",
+                format!("/tasks?workspace_id={}", workspace_id),
+                None,
+                Permission::View,
+            ),
+            (
+                "POST",
+                "/tasks".to_string(),
+                Some(json!({ "title": "New", "workspace_id": workspace_id })),
+                Permission::EditTasks,
+            ),
+            (
+                "PUT",
+                task_uri.clone(),
+                Some(json!({ "done": true })),
+                Permission::EditTasks,
+            ),
+            (
+                "POST",
+                format!("{}/archive", task_uri),
+                None,
+                Permission::EditTasks,
+            ),
+            (
+                "POST",
+                format!("{}/unarchive", task_uri),
+                None,
+                Permission::EditTasks,
+            ),
+            (
+                "PUT",
+                format!("{}/assignee", task_uri),
+                Some(json!({ "user_id": target_id })),
+                Permission::EditTasks,
+            ),
+            (
+                "GET",
+                format!("{}/history", task_uri),
+                None,
+                Permission::View,
+            ),
+            (
+                "DELETE",
+                format!("{}/assignee", task_uri),
+                None,
+                Permission::EditTasks,
+            ),
+            (
+                "PUT",
+                format!("/workspaces/{}", workspace_id),
+                Some(json!({ "name": "Renamed" })),
+                Permission::ManageWorkspace,
+            ),
+            (
+                "PUT",
+                target_member.clone(),
+                Some(json!({ "role": "commenter" })),
+                Permission::ManageMembers,
+            ),
+            (
+                "POST",
+                format!("/workspaces/{}/invitations", workspace_id),
+                Some(json!({ "email": format!("rbac_invitee_{}@example.com", name) })),
+                Permission::ManageMembers,
+            ),
+            (
+                "DELETE",
+                target_member.clone(),
+                None,
+                Permission::ManageMembers,
+            ),
+            (
+                "POST",
+                members.clone(),
+                Some(json!({ "email": format!("rbac_target_{}@example.com", name) })),
+                Permission::ManageMembers,
+            ),
+            ("DELETE", task_uri.clone(), None, Permission::EditTasks),
+            (
+                "DELETE",
+                format!("/workspaces/{}", workspace_id),
+                None,
+                Permission::DeleteWorkspace,
+            ),
+        ];
+
+        for (method, uri, body, permission) in cases {
+            let (status, _) = send_json(&app, method, &uri, Some(&outsider), body.clone()).await;
+            assert_eq!(status, StatusCode::NOT_FOUND, "outsider {} {}", method, uri);
+
+            let (status, _) = send_json(&app, method, &uri, Some(&caller), body).await;
+            if role.can(permission) {
+                assert!(
+                    status.is_success(),
+                    "{} {} {} -> {}",
+                    name,
+                    method,
+                    uri,
+                    status
+                );
+            } else {
+                assert_eq!(status, StatusCode::FORBIDDEN, "{} {} {}", name, method, uri);
+            }
+        }
+    }
+}
+
+#[sqlx::test]
+async fn test_admin_cannot_manage_owners(pool: PgPool) {
+    let app = create_test_app(pool).await;
+
+    let owner = create_test_user_with_token(&app, "owner_guard@example.com").await;
+    let admin = create_test_user_with_token(&app, "admin_guard@example.com").await;
+    let workspace_id = create_test_workspace(&app, &owner, "Guarded").await;
+    let members = format!("/workspaces/{}/members", workspace_id);
+
+    let (status, _) = send_json(
+        &app,
+        "POST",
+        &members,
+        Some(&owner),
+        Some(json!({ "email": "admin_guard@example.com", "role": "admin" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let (_, listed) = send_json(&app, "GET", &members, Some(&admin), None).await;
+    let owner_id = listed
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|m| m["role"] == "owner")
+        .unwrap()["user_id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let (status, _) = send_json(
+        &app,
+        "DELETE",
+        &format!("{}/{}", members, owner_id),
+        Some(&admin),
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // The sole owner cannot step down either
+    let (status, _) = send_json(
+        &app,
+        "PUT",
+        &format!("{}/{}", members, owner_id),
+        Some(&owner),
+        Some(json!({ "role": "editor" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[sqlx::test]
+async fn test_owners_stepping_down_together_keep_one(pool: PgPool) {
+    let app = create_test_app(pool.clone()).await;
+
+    let first = create_test_user_with_token(&app, "first_owner@example.com").await;
+    let second = create_test_user_with_token(&app, "second_owner@example.com").await;
+    let workspace_id = create_test_workspace(&app, &first, "Co-owned").await;
+    let members = format!("/workspaces/{}/members", workspace_id);
+    let (status, added) = send_json(
+        &app,
+        "POST",
+        &members,
+        Some(&first),
+        Some(json!({ "email": "second_owner@example.com", "role": "owner" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let (_, listed) = send_json(&app, "GET", &members, Some(&first), None).await;
+    let first_id = listed
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|m| m["email"] == "first_owner@example.com")
+        .unwrap()["user_id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let second_id = added["user_id"].as_str().unwrap().to_string();
+
+    // Each owner demotes the other, and removes them, at the same time
+    for method in ["PUT", "DELETE"] {
+        let requests: Vec<_> = [(&first, &second_id), (&second, &first_id)]
+            .into_iter()
+            .map(|(token, other)| {
+                let app = app.clone();
+                let token = token.clone();
+                let uri = format!("{}/{}", members, other);
+                let body = (method == "PUT").then(|| json!({ "role": "editor" }));
+                tokio::spawn(async move { send_json(&app, method, &uri, Some(&token), body).await })
+            })
+            .collect();
+        for request in requests {
+            request.await.unwrap();
+        }
+
+        let owners = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM workspace_members WHERE workspace_id = $1::uuid AND role = 'owner'",
+        )
+        .bind(&workspace_id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(owners, 1);
+
+        // Make both owners again for the next round
+        sqlx::query(
+            "INSERT INTO workspace_members (workspace_id, user_id, role)
+             SELECT $1::uuid, id, 'owner' FROM users
+             ON CONFLICT (workspace_id, user_id) DO UPDATE SET role = 'owner'",
+        )
+        .bind(&workspace_id)
+        .execute(&pool)
+        .await
+        .unwrap();
+    }
+}
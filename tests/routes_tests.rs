@@ -0,0 +1,155 @@
+// Route table tests: every listed route is served, and every route needs
+// credentials unless the table marks it public.
+//
+// The requests go through the full app, so these need the database:
+//   cargo test --test routes_tests
+//
+use std::collections::HashSet;
+
+use axum::{
+    body::Body,
+    http::{header, Method, Request, StatusCode},
+    Router,
+};
+use sqlx::PgPool;
+use task_manager::routes::{routes, Access, Route};
+use tower::ServiceExt;
+
+mod common;
+use common::*;
+
+// A concrete path for the route, with a random id for each parameter
+fn example_path(route: &Route) -> String {
+    route
+        .path
+        .split('/')
+        .map(|segment| match segment.strip_prefix(':') {
+            Some(_) => uuid::Uuid::new_v4().to_string(),
+            None => segment.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+async fn call(app: &Router, route: &Route, token: Option<&str>) -> (StatusCode, bool) {
+    let mut request = Request::builder()
+        .method(route.method.clone())
+        .uri(example_path(route));
+    if let Some(token) = token {
+        request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+    }
+
+    let response = app
+        .clone()
+        .oneshot(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+
+    // Only the router's own fallback answers without a body
+    let handled =
+        response.headers().contains_key(header::CONTENT_TYPE) || response.status().is_success();
+    (response.status(), handled)
+}
+
+#[test]
+fn test_routes_are_unique() {
+    let mut seen = HashSet::new();
+    for route in routes() {
+        assert!(
+            seen.insert((route.method.clone(), route.path)),
+            "{} {} is listed twice",
+            route.method,
+            route.path
+        );
+    }
+}
+
+#[test]
+fn test_public_routes() {
+    let public: Vec<(Method, &str)> = routes()
+        .into_iter()
+        .filter(|route| route.access == Access::Public)
+        .map(|route| (route.method, route.path))
+        .collect();
+
+    // Adding a route here is a security decision: review it as one
+    assert_eq!(
+        public,
+        vec![
+            (Method::POST, "/auth/register"),
+            (Method::POST, "/auth/login"),
+            (Method::POST, "/auth/login/2fa"),
+            (Method::GET, "/auth/oidc/:provider/authorize"),
+            (Method::GET, "/auth/oidc/:provider/callback"),
+            (Method::POST, "/auth/oidc/:provider/callback"),
+            (Method::POST, "/auth/refresh"),
+            (Method::POST, "/auth/verify-email"),
+            (Method::POST, "/auth/password-reset/request"),
+            (Method::POST, "/auth/password-reset/confirm"),
+            (Method::GET, "/.well-known/jwks.json"),
+            (Method::POST, "/invitations/:token/accept"),
+        ]
+    );
+}
+
+#[test]
+fn test_only_scoped_routes_accept_api_keys() {
+    for route in routes() {
+        if route.scope.is_some() {
+            assert_eq!(
+                route.access,
+                Access::Authenticated,
+                "{} {} has a scope but is public",
+                route.method,
+                route.path
+            );
+        }
+    }
+}
+
+#[sqlx::test]
+async fn test_every_route_is_served(pool: PgPool) {
+    let app = create_test_app(pool).await;
+
+    for route in routes() {
+        let (status, handled) = call(&app, &route, None).await;
+        assert!(
+            handled && status != StatusCode::METHOD_NOT_ALLOWED,
+            "{} {} is not routed ({})",
+            route.method,
+            route.path,
+            status
+        );
+    }
+
+    let (status, _) = send_json(&app, "GET", "/no-such-route", None, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[sqlx::test]
+async fn test_authenticated_routes_need_credentials(pool: PgPool) {
+    let app = create_test_app(pool).await;
+
+    for route in routes() {
+        for token in [None, Some("not-a-token"), Some("pat_not_a_key")] {
+            let (status, _) = call(&app, &route, token).await;
+            match route.access {
+                Access::Authenticated => assert_eq!(
+                    status,
+                    StatusCode::UNAUTHORIZED,
+                    "{} {} answered without valid credentials",
+                    route.method,
+                    route.path
+                ),
+                Access::Public if token.is_none() => assert_ne!(
+                    status,
+                    StatusCode::UNAUTHORIZED,
+                    "{} {} is public but asked for credentials",
+                    route.method,
+                    route.path
+                ),
+                Access::Public => {}
+            }
+        }
+    }
+}
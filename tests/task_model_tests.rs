@@ -1,6 +1,8 @@
 // Unit tests for Task model
 use chrono::Utc;
-use task_manager::models::task::{CreateTaskRequest, Task, UpdateTaskRequest};
+use task_manager::models::task::{
+    AssignTaskRequest, CreateTaskRequest, Task, TaskListQuery, UpdateTaskRequest,
+};
 use uuid::Uuid;
 
 #[test]
@@ -10,6 +12,7 @@ fn test_create_task_request_deserialization() {
 
     assert_eq!(request.title, "Test Task");
     assert_eq!(request.description, Some("Test Description".to_string()));
+    assert_eq!(request.workspace_id, None);
 }
 
 #[test]
@@ -45,11 +48,15 @@ fn test_update_task_request_all_fields() {
 fn test_task_serialization() {
     let task = Task {
         id: Uuid::nil(),
-        user_id: Uuid::nil(),
+        workspace_id: Uuid::nil(),
+        user_id: Some(Uuid::nil()),
+        assignee_id: None,
         title: "Test Task".to_string(),
         description: Some("Description".to_string()),
         done: false,
         created_at: Utc::now(),
+        completed_at: None,
+        archived_at: None,
     };
 
     let json = serde_json::to_value(&task).unwrap();
@@ -57,6 +64,7 @@ fn test_task_serialization() {
     assert_eq!(json["description"], "Description");
     assert_eq!(json["done"], false);
     assert!(json["id"].is_string());
+    assert!(json["workspace_id"].is_string());
     assert!(json["user_id"].is_string());
 }
 
@@ -64,14 +72,38 @@ fn test_task_serialization() {
 fn test_task_with_null_description() {
     let task = Task {
         id: Uuid::nil(),
-        user_id: Uuid::nil(),
+        workspace_id: Uuid::nil(),
+        user_id: Some(Uuid::nil()),
+        assignee_id: None,
         title: "Test Task".to_string(),
         description: None,
         done: false,
         created_at: Utc::now(),
+        completed_at: None,
+        archived_at: None,
     };
 
     let json = serde_json::to_value(&task).unwrap();
     assert_eq!(json["title"], "Test Task");
     assert!(json["description"].is_null());
 }
+
+#[test]
+fn test_task_list_query_defaults_to_active_tasks() {
+    let query: TaskListQuery = serde_json::from_str("{}").unwrap();
+    assert!(!query.archived);
+
+    let query: TaskListQuery = serde_json::from_str(r#"{"archived": true}"#).unwrap();
+    assert!(query.archived);
+}
+
+#[test]
+fn test_assign_task_request_deserialization() {
+    let json = r#"{"user_id": "550e8400-e29b-41d4-a716-446655440000"}"#;
+    let request: AssignTaskRequest = serde_json::from_str(json).unwrap();
+
+    assert_eq!(
+        request.user_id,
+        Uuid::parse_str("550e8400-e29b-41d4-a716-446655440000").unwrap()
+    );
+}
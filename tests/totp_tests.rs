@@ -0,0 +1,98 @@
+// Unit tests for TOTP codes, against the RFC 6238 SHA-1 test vectors
+use task_manager::totp::{self, SecretCipher, ALLOWED_DRIFT};
+use uuid::Uuid;
+
+// Base32 of the RFC 6238 test key "12345678901234567890"
+const SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+fn code_at(unix_time: u64) -> String {
+    totp::code_for_step(SECRET, totp::step_at(unix_time)).unwrap()
+}
+
+#[test]
+fn test_codes_match_rfc_vectors() {
+    // The RFC lists 8-digit codes; 6-digit codes are their last six digits
+    for (time, expected) in [
+        (59, "287082"),
+        (1111111109, "081804"),
+        (1111111111, "050471"),
+        (1234567890, "005924"),
+        (2000000000, "279037"),
+        (20000000000, "353130"),
+    ] {
+        assert_eq!(code_at(time), expected, "t = {}", time);
+    }
+}
+
+#[test]
+fn test_verify_accepts_clock_drift_within_window() {
+    // 1111111109 is in step 37037036; its code stays valid one step either side
+    let code = code_at(1111111109);
+    assert_eq!(
+        totp::verify(SECRET, &code, 1111111109, ALLOWED_DRIFT),
+        Some(37037036)
+    );
+    assert_eq!(
+        totp::verify(SECRET, &code, 1111111109 - 30, ALLOWED_DRIFT),
+        Some(37037036)
+    );
+    assert_eq!(
+        totp::verify(SECRET, &code, 1111111109 + 30, ALLOWED_DRIFT),
+        Some(37037036)
+    );
+    assert_eq!(totp::verify(SECRET, &code, 1111111109 + 30, 0), None);
+}
+
+#[test]
+fn test_verify_rejects_codes_outside_window() {
+    let code = code_at(1111111109);
+    assert_eq!(
+        totp::verify(SECRET, &code, 1111111109 + 90, ALLOWED_DRIFT),
+        None
+    );
+    assert_eq!(
+        totp::verify(SECRET, &code, 1111111109 - 90, ALLOWED_DRIFT),
+        None
+    );
+}
+
+#[test]
+fn test_verify_rejects_malformed_codes() {
+    for code in ["", "08180", "0818044", "08l804", "abcdef"] {
+        assert_eq!(totp::verify(SECRET, code, 1111111109, ALLOWED_DRIFT), None);
+    }
+    // Surrounding whitespace from copy and paste is fine
+    assert!(totp::verify(SECRET, " 081804 ", 1111111109, ALLOWED_DRIFT).is_some());
+}
+
+#[test]
+fn test_generated_secret_and_uri() {
+    let secret = totp::generate_secret();
+    assert_eq!(secret.len(), 32);
+    assert!(totp::code_for_step(&secret, 1).is_some());
+
+    let uri = totp::otpauth_uri(&secret, "jane@example.com");
+    assert!(uri.starts_with("otpauth://totp/Task%20Manager:jane%40example.com?"));
+    assert!(uri.contains(&format!("secret={}", secret)));
+    assert!(uri.contains("issuer=Task%20Manager"));
+}
+
+#[test]
+fn test_secrets_are_sealed_per_user() {
+    let cipher = SecretCipher::new(&[7; 32]);
+    let user_id = Uuid::new_v4();
+
+    let sealed = cipher.seal(user_id, SECRET).unwrap();
+    assert!(totp::is_sealed(&sealed));
+    assert!(!sealed.contains(SECRET));
+    // A fresh nonce every time
+    assert_ne!(cipher.seal(user_id, SECRET).unwrap(), sealed);
+    assert_eq!(cipher.open(user_id, &sealed).unwrap(), SECRET);
+
+    assert!(cipher.open(Uuid::new_v4(), &sealed).is_err());
+    assert!(SecretCipher::new(&[8; 32]).open(user_id, &sealed).is_err());
+
+    // Secrets stored before encryption are read as they are
+    assert!(!totp::is_sealed(SECRET));
+    assert_eq!(cipher.open(user_id, SECRET).unwrap(), SECRET);
+}
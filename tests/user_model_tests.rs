@@ -1,6 +1,8 @@
 // Unit tests for User model
 use chrono::Utc;
-use task_manager::models::user::{AuthResponse, LoginRequest, RegisterRequest, User};
+use task_manager::models::user::{
+    AuthResponse, LoginRequest, RefreshRequest, RegisterRequest, User,
+};
 use uuid::Uuid;
 
 #[test]
@@ -25,11 +27,19 @@ fn test_login_request_deserialization() {
 fn test_auth_response_serialization() {
     let response = AuthResponse {
         token: "test_token_123".to_string(),
+        refresh_token: "refresh_456".to_string(),
+        token_type: "Bearer".to_string(),
+        expires_in: 900,
     };
 
     let json = serde_json::to_string(&response).unwrap();
     assert!(json.contains("test_token_123"));
     assert!(json.contains("token"));
+
+    let json = serde_json::to_value(&response).unwrap();
+    assert_eq!(json["refresh_token"], "refresh_456");
+    assert_eq!(json["token_type"], "Bearer");
+    assert_eq!(json["expires_in"], 900);
 }
 
 #[test]
@@ -39,6 +49,9 @@ fn test_user_serialization() {
         email: "test@example.com".to_string(),
         password_hash: "hashed_password".to_string(),
         created_at: Utc::now(),
+        auto_archive_after_days: None,
+        email_verified_at: None,
+        totp_enabled_at: None,
     };
 
     let json = serde_json::to_value(&user).unwrap();
@@ -46,3 +59,11 @@ fn test_user_serialization() {
     assert!(json["id"].is_string());
     assert!(json["created_at"].is_string());
 }
+
+#[test]
+fn test_refresh_request_deserialization() {
+    let json = r#"{"refresh_token": "opaque"}"#;
+    let request: RefreshRequest = serde_json::from_str(json).unwrap();
+
+    assert_eq!(request.refresh_token, "opaque");
+}
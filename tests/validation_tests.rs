@@ -0,0 +1,160 @@
+// Unit tests for input validation
+use serde_json::json;
+use task_manager::models::{
+    api_key::CreateApiKeyRequest,
+    task::{CreateTaskRequest, UpdateTaskRequest},
+    two_factor::DisableTwoFactorRequest,
+    user::{
+        ChangeEmailRequest, ChangePasswordRequest, DeleteAccountRequest, LoginRequest,
+        PasswordResetConfirm, RegisterRequest,
+    },
+    workspace::AcceptInvitationRequest,
+};
+use task_manager::validation::normalize_email;
+use validator::Validate;
+
+#[test]
+fn test_normalize_email_folds_domain_case() {
+    assert_eq!(
+        normalize_email("Jane.Doe@Example.COM").unwrap(),
+        "Jane.Doe@example.com"
+    );
+    assert_eq!(
+        normalize_email("  user@example.com\n").unwrap(),
+        "user@example.com"
+    );
+}
+
+#[test]
+fn test_normalize_email_rejects_invalid_addresses() {
+    for email in [
+        "",
+        "plainaddress",
+        "@example.com",
+        "user@",
+        "user@localhost",
+        "user@@example.com",
+        "user@[127.0.0.1]",
+        "Jane <jane@example.com>",
+    ] {
+        assert!(normalize_email(email).is_err(), "{:?}", email);
+    }
+}
+
+#[test]
+fn test_task_request_rules() {
+    let valid: CreateTaskRequest =
+        serde_json::from_value(json!({ "title": "Write report", "description": "Q3" })).unwrap();
+    assert!(valid.validate().is_ok());
+
+    let blank: CreateTaskRequest = serde_json::from_value(json!({ "title": " \t" })).unwrap();
+    let errors = blank.validate().unwrap_err();
+    assert_eq!(errors.field_errors()["title"][0].code, "blank");
+
+    let long: UpdateTaskRequest =
+        serde_json::from_value(json!({ "title": "é".repeat(201) })).unwrap();
+    let errors = long.validate().unwrap_err();
+    assert_eq!(errors.field_errors()["title"][0].code, "too_long");
+
+    // Length counts characters, not bytes
+    let at_limit: UpdateTaskRequest =
+        serde_json::from_value(json!({ "title": "é".repeat(200) })).unwrap();
+    assert!(at_limit.validate().is_ok());
+
+    let empty_update: UpdateTaskRequest = serde_json::from_value(json!({})).unwrap();
+    assert!(empty_update.validate().is_ok());
+}
+
+#[test]
+fn test_register_request_rules() {
+    let invalid: RegisterRequest = serde_json::from_value(json!({
+        "email": "user@localhost",
+        "password": "x".repeat(129),
+    }))
+    .unwrap();
+    let errors = invalid.validate().unwrap_err();
+    let fields = errors.field_errors();
+    assert_eq!(fields["email"][0].code, "invalid_email");
+    assert_eq!(fields["password"][0].code, "too_long");
+
+    let valid: RegisterRequest = serde_json::from_value(json!({
+        "email": " User@Example.com ",
+        "password": "correct horse battery",
+    }))
+    .unwrap();
+    assert!(valid.validate().is_ok());
+}
+
+/// The fields of a request that fail validation.
+fn invalid_fields<T: Validate + serde::de::DeserializeOwned>(
+    body: serde_json::Value,
+) -> Vec<String> {
+    let request: T = serde_json::from_value(body).unwrap();
+    match request.validate() {
+        Ok(()) => Vec::new(),
+        Err(errors) => {
+            let mut fields: Vec<_> = errors
+                .field_errors()
+                .keys()
+                .map(|f| f.to_string())
+                .collect();
+            fields.sort();
+            fields
+        }
+    }
+}
+
+#[test]
+fn test_every_password_is_capped() {
+    let long = "x".repeat(129);
+
+    assert_eq!(
+        invalid_fields::<LoginRequest>(json!({ "email": "a@example.com", "password": long })),
+        ["password"]
+    );
+    assert_eq!(
+        invalid_fields::<PasswordResetConfirm>(json!({ "token": "t", "new_password": long })),
+        ["new_password"]
+    );
+    assert_eq!(
+        invalid_fields::<ChangePasswordRequest>(
+            json!({ "current_password": long, "new_password": long })
+        ),
+        ["current_password", "new_password"]
+    );
+    assert_eq!(
+        invalid_fields::<ChangeEmailRequest>(json!({ "email": "a@example.com", "password": long })),
+        ["password"]
+    );
+    assert_eq!(
+        invalid_fields::<DeleteAccountRequest>(json!({ "password": long })),
+        ["password"]
+    );
+    assert_eq!(
+        invalid_fields::<DisableTwoFactorRequest>(json!({ "password": long, "code": "123456" })),
+        ["password"]
+    );
+    assert_eq!(
+        invalid_fields::<AcceptInvitationRequest>(json!({ "password": long })),
+        ["password"]
+    );
+    assert!(invalid_fields::<AcceptInvitationRequest>(json!({})).is_empty());
+    assert!(invalid_fields::<LoginRequest>(
+        json!({ "email": "a@example.com", "password": "x".repeat(128) })
+    )
+    .is_empty());
+}
+
+#[test]
+fn test_api_key_names_are_capped() {
+    assert_eq!(
+        invalid_fields::<CreateApiKeyRequest>(
+            json!({ "name": "k".repeat(101), "scopes": ["tasks:read"] })
+        ),
+        ["name"]
+    );
+    assert!(invalid_fields::<CreateApiKeyRequest>(
+        json!({ "name": "k".repeat(100), "scopes": ["tasks:read"] })
+    )
+    .is_empty());
+}